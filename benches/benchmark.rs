@@ -37,5 +37,18 @@ fn benchmark_large_decoding_custom(bench: &mut Bencher) {
     })
 }
 
-benchmark_group!(benches, benchmark_encoding_custom, benchmark_decoding_custom, benchmark_large_encoding_custom, benchmark_large_decoding_custom);
+fn benchmark_verify_es256(bench: &mut Bencher) {
+    let cwt = "d28444a1013824a1044843415554494f4e21590117a401624348041b00701cd2fa9578ff061a6094d9a9390103a101a4617681aa626369782630313a43483a324137413633363544444644343433454138303430413236413245303442413462636f62434862646e016264746a323032312d30342d3237626973781942756e646573616d742066c3bc7220476573756e6468656974626d61781f4d6f6465726e6120537769747a65726c616e6420476d62482c20426173656c626d7065363832363762736402627467693834303533393030366276706a3131313933343930303763646f626a313934332d30322d3031636e616da462666e674dc3bc6c6c657262676e6743c3a96c696e6563666e74674d75656c6c657263676e746643656c696e656376657265312e302e30581d43415554494f4e212054686973206973206a75737420612066616b6521";
+    let key = rust_dgc::SigningKey::es256(&[3u8; 32]).unwrap();
+    let mut cwt = rust_dgc::get_payload(&rust_dgc::decode_hex(cwt).unwrap()).unwrap();
+    cwt.protected_headers.insert(serde_cbor::Value::Integer(1), serde_cbor::Value::Integer(-7));
+    cwt.sign(&key).unwrap();
+    let cwt = rust_dgc::get_payload(&cwt.to_cbor().unwrap()).unwrap();
+    let verification_key = key.verification_key().unwrap();
+    bench.iter(|| {
+        cwt.verify(&verification_key).is_ok()
+    })
+}
+
+benchmark_group!(benches, benchmark_encoding_custom, benchmark_decoding_custom, benchmark_large_encoding_custom, benchmark_large_decoding_custom, benchmark_verify_es256);
 benchmark_main!(benches);
\ No newline at end of file
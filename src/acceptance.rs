@@ -0,0 +1,366 @@
+//! Acceptance of a certificate for access control, independent of the CertLogic business rules.
+//!
+//! An [AcceptancePolicy] defines for how long vaccinations, tests and recoveries are accepted. Evaluating it
+//! gives the period in which the certificate is acceptable, so a caller can tell whether it is acceptable now
+//! and until when.
+use crate::certlogic::format_date;
+use crate::hcert::{CertificateType, DatePrecision, Hcert, PartialDate};
+
+/// `tg` of COVID-19
+pub const COVID_19: &str = "840539006";
+/// `tt` of a NAAT (PCR) test
+pub const NAAT_TEST: &str = "LP6464-4";
+/// `tt` of a rapid antigen test
+pub const RAPID_ANTIGEN_TEST: &str = "LP217198-3";
+/// `tr` of a negative test
+pub const NOT_DETECTED: &str = "260415000";
+
+const DAY: i64 = 86_400;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// How long each kind of certificate is accepted. A maximum of `None` means no limit, except for tests, where
+/// it means the test type is not accepted.
+pub struct AcceptancePolicy {
+    /// Days after the last dose of a complete series (`dn` = `sd`) before it is accepted
+    pub vaccination_min_days: u32,
+    pub vaccination_max_days: Option<u32>,
+    /// Days after a booster dose (`dn` > `sd`) before it is accepted
+    pub booster_min_days: u32,
+    pub booster_max_days: Option<u32>,
+    /// Hours after the sample collection a NAAT test is accepted
+    pub naat_test_max_hours: Option<u32>,
+    /// Hours after the sample collection a rapid antigen test is accepted
+    pub rapid_antigen_test_max_hours: Option<u32>,
+    /// Days after the first positive test before a recovery is accepted
+    pub recovery_min_days: u32,
+    pub recovery_max_days: Option<u32>,
+}
+
+impl Default for AcceptancePolicy {
+    fn default() -> Self {
+        AcceptancePolicy {
+            vaccination_min_days: 14,
+            vaccination_max_days: Some(270),
+            booster_min_days: 0,
+            booster_max_days: None,
+            naat_test_max_hours: Some(72),
+            rapid_antigen_test_max_hours: Some(48),
+            recovery_min_days: 11,
+            recovery_max_days: Some(180),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The outcome of [AcceptancePolicy::evaluate]. The period is the one containing the validation time, or the
+/// next one if the certificate is not yet acceptable.
+pub struct Acceptance {
+    pub acceptable: bool,
+    /// Seconds since the UNIX epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<i64>,
+    /// Seconds since the UNIX epoch, `None` if acceptable without limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<i64>,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Period {
+    from: i64,
+    until: Option<i64>,
+}
+
+impl Period {
+    fn after(start: i64, min_days: u32, max_days: Option<u32>) -> Period {
+        Period {
+            from: start + min_days as i64 * DAY,
+            until: max_days.map(|days| start + days as i64 * DAY),
+        }
+    }
+
+    fn contains(&self, time: i64) -> bool {
+        self.from <= time && self.until.is_none_or(|until| time <= until)
+    }
+
+    fn describe(&self) -> String {
+        match self.until {
+            Some(until) => format!(
+                "from {} until {}",
+                format_date(self.from),
+                format_date(until)
+            ),
+            None => format!("from {}", format_date(self.from)),
+        }
+    }
+}
+
+/// Start of the day of an event, partial dates would extend the acceptance to their earliest day
+fn date(field: &str, date: &PartialDate) -> Result<i64, String> {
+    match (date.precision(), date.earliest()) {
+        (DatePrecision::Day, Some(start)) => Ok(start),
+        _ => Err(format!("{}: {} is not a complete date", field, date)),
+    }
+}
+
+fn targets_covid_19(field: &str, tg: &str) -> Result<(), String> {
+    if tg == COVID_19 {
+        Ok(())
+    } else {
+        Err(format!("{}: disease {} is not accepted", field, tg))
+    }
+}
+
+impl AcceptancePolicy {
+    /// Evaluate `hcert` at `time` (seconds since the UNIX epoch). Entries of the wrong disease or with
+    /// invalid dates are never acceptable.
+    pub fn evaluate(&self, hcert: &Hcert, time: i64) -> Acceptance {
+        let periods: Vec<Result<(String, Period), String>> = match hcert.certificate_type() {
+            Some(CertificateType::Vaccination) => self.vaccination_periods(hcert),
+            Some(CertificateType::Test) => self.test_periods(hcert),
+            Some(CertificateType::Recovery) => self.recovery_periods(hcert),
+            None => vec![Err("Exactly one of v, t or r is required".to_string())],
+        };
+        let mut reasons = vec![];
+        let mut current = None;
+        let mut next: Option<Period> = None;
+        for result in periods {
+            match result {
+                Ok((field, period)) => {
+                    if period.contains(time) {
+                        reasons.push(format!("{}: acceptable {}", field, period.describe()));
+                        current = current.or(Some(period));
+                    } else if period.from > time {
+                        reasons.push(format!(
+                            "{}: not yet acceptable, {}",
+                            field,
+                            period.describe()
+                        ));
+                        next = Some(next.map_or(period, |next| {
+                            if period.from < next.from {
+                                period
+                            } else {
+                                next
+                            }
+                        }));
+                    } else {
+                        reasons.push(format!(
+                            "{}: expired, was acceptable {}",
+                            field,
+                            period.describe()
+                        ));
+                    }
+                }
+                Err(reason) => reasons.push(reason),
+            }
+        }
+        let period = current.or(next);
+        Acceptance {
+            acceptable: current.is_some(),
+            valid_from: period.map(|period| period.from),
+            valid_until: period.and_then(|period| period.until),
+            reasons,
+        }
+    }
+
+    fn vaccination_periods(&self, hcert: &Hcert) -> Vec<Result<(String, Period), String>> {
+        let mut periods = vec![];
+        for (i, v) in hcert.v.iter().flatten().enumerate() {
+            let field = format!("v.{}", i);
+            periods.push(targets_covid_19(&field, &v.tg).and_then(|_| {
+                let start = date(&field, &v.dt)?;
+                let period = if v.dn > v.sd {
+                    Period::after(start, self.booster_min_days, self.booster_max_days)
+                } else if v.dn == v.sd && v.sd > 0 {
+                    Period::after(start, self.vaccination_min_days, self.vaccination_max_days)
+                } else {
+                    return Err(format!("{}: incomplete, dose {} of {}", field, v.dn, v.sd));
+                };
+                Ok((field, period))
+            }));
+        }
+        periods
+    }
+
+    fn test_periods(&self, hcert: &Hcert) -> Vec<Result<(String, Period), String>> {
+        let mut periods = vec![];
+        for (i, t) in hcert.t.iter().flatten().enumerate() {
+            let field = format!("t.{}", i);
+            periods.push(targets_covid_19(&field, &t.tg).and_then(|_| {
+                if t.tr != NOT_DETECTED {
+                    return Err(format!("{}: result {} is not negative", field, t.tr));
+                }
+                let max_hours = match t.tt.as_str() {
+                    NAAT_TEST => self.naat_test_max_hours,
+                    RAPID_ANTIGEN_TEST => self.rapid_antigen_test_max_hours,
+                    _ => None,
+                }
+                .ok_or_else(|| format!("{}: test type {} is not accepted", field, t.tt))?;
+                let sample =
+                    t.sc.earliest()
+                        .ok_or_else(|| format!("{}: no sample time", field))?;
+                let period = Period {
+                    from: sample,
+                    until: Some(sample + max_hours as i64 * 3600),
+                };
+                Ok((field, period))
+            }));
+        }
+        periods
+    }
+
+    fn recovery_periods(&self, hcert: &Hcert) -> Vec<Result<(String, Period), String>> {
+        let mut periods = vec![];
+        for (i, r) in hcert.r.iter().flatten().enumerate() {
+            let field = format!("r.{}", i);
+            periods.push(targets_covid_19(&field, &r.tg).and_then(|_| {
+                let period = Period::after(
+                    date(&field, &r.fr)?,
+                    self.recovery_min_days,
+                    self.recovery_max_days,
+                );
+                // the certificate itself is only valid from df until the end of du
+                let valid_from = date(&field, &r.df)?;
+                let valid_until = date(&field, &r.du)? + DAY - 1;
+                let until = period
+                    .until
+                    .map_or(valid_until, |until| until.min(valid_until));
+                let period = Period {
+                    from: period.from.max(valid_from),
+                    until: Some(until),
+                };
+                if period.from > until {
+                    return Err(format!("{}: no acceptable period", field));
+                }
+                Ok((field, period))
+            }));
+        }
+        periods
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::get_payload;
+    use crate::hcert::{Recovery, Test};
+
+    // 2021-04-21T00:00:00Z, the vaccination date of the SE certificate
+    const VACCINATED: i64 = 1_618_963_200;
+
+    fn hcert() -> Hcert {
+        get_payload(&crate::decode_hex(SE_HCERT).unwrap())
+            .unwrap()
+            .hcert()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_vaccination() {
+        let policy = AcceptancePolicy::default();
+        let hcert = hcert();
+        let acceptance = policy.evaluate(&hcert, VACCINATED + 10 * DAY);
+        assert!(!acceptance.acceptable);
+        assert_eq!(acceptance.valid_from, Some(VACCINATED + 14 * DAY));
+
+        let acceptance = policy.evaluate(&hcert, VACCINATED + 14 * DAY);
+        assert!(acceptance.acceptable, "{:?}", acceptance.reasons);
+        assert_eq!(acceptance.valid_until, Some(VACCINATED + 270 * DAY));
+        assert_eq!(
+            acceptance.reasons,
+            vec!["v.0: acceptable from 2021-05-05T00:00:00Z until 2022-01-16T00:00:00Z"]
+        );
+        assert!(!policy.evaluate(&hcert, VACCINATED + 271 * DAY).acceptable);
+
+        let mut incomplete = hcert.clone();
+        incomplete.v.as_mut().unwrap()[0].dn = 1;
+        let acceptance = policy.evaluate(&incomplete, VACCINATED + 20 * DAY);
+        assert!(!acceptance.acceptable);
+        assert_eq!(acceptance.valid_from, None);
+
+        let mut partial = hcert.clone();
+        partial.v.as_mut().unwrap()[0].dt = PartialDate::parse("2021-04").unwrap();
+        let acceptance = policy.evaluate(&partial, VACCINATED + 20 * DAY);
+        assert!(!acceptance.acceptable);
+        assert_eq!(
+            acceptance.reasons,
+            vec!["v.0: 2021-04 is not a complete date"]
+        );
+
+        let mut booster = hcert;
+        booster.v.as_mut().unwrap()[0].dn = 3;
+        let acceptance = policy.evaluate(&booster, VACCINATED);
+        assert!(acceptance.acceptable);
+        assert_eq!(acceptance.valid_until, None);
+    }
+
+    #[test]
+    fn test_test_and_recovery() {
+        let policy = AcceptancePolicy {
+            rapid_antigen_test_max_hours: Some(24),
+            ..AcceptancePolicy::default()
+        };
+        let mut hcert = hcert();
+        let v = hcert.v.take().unwrap().remove(0);
+        let test = Test {
+            tg: COVID_19.to_string(),
+            tt: NAAT_TEST.to_string(),
+            nm: None,
+            ma: None,
+            sc: "2021-04-21T10:00:00+02:00".parse().unwrap(),
+            tr: NOT_DETECTED.to_string(),
+            tc: None,
+            co: v.co.clone(),
+            is: v.is.clone(),
+            ci: v.ci.clone(),
+        };
+        hcert.t = Some(vec![test.clone()]);
+        let acceptance = policy.evaluate(&hcert, VACCINATED + DAY);
+        assert!(acceptance.acceptable);
+        assert_eq!(
+            acceptance.valid_until,
+            Some(VACCINATED + 8 * 3600 + 72 * 3600)
+        );
+
+        hcert.t = Some(vec![Test {
+            tt: RAPID_ANTIGEN_TEST.to_string(),
+            ..test.clone()
+        }]);
+        assert!(!policy.evaluate(&hcert, VACCINATED + 2 * DAY).acceptable);
+        hcert.t = Some(vec![Test {
+            tr: "260373001".to_string(),
+            ..test
+        }]);
+        assert!(!policy.evaluate(&hcert, VACCINATED + DAY).acceptable);
+
+        hcert.t = None;
+        hcert.r = Some(vec![Recovery {
+            tg: COVID_19.to_string(),
+            fr: PartialDate::parse("2021-04-21").unwrap(),
+            co: v.co,
+            is: v.is,
+            df: PartialDate::parse("2021-05-01").unwrap(),
+            du: PartialDate::parse("2021-06-30").unwrap(),
+            ci: v.ci,
+        }]);
+        let acceptance = policy.evaluate(&hcert, VACCINATED + 11 * DAY);
+        assert!(acceptance.acceptable);
+        assert_eq!(acceptance.valid_until, Some(VACCINATED + 71 * DAY - 1));
+        assert!(!policy.evaluate(&hcert, VACCINATED + 10 * DAY).acceptable);
+
+        for field in &["fr", "df", "du"] {
+            let mut partial = hcert.clone();
+            let r = &mut partial.r.as_mut().unwrap()[0];
+            let date = match *field {
+                "fr" => &mut r.fr,
+                "df" => &mut r.df,
+                _ => &mut r.du,
+            };
+            *date = PartialDate::parse("2021").unwrap();
+            let acceptance = policy.evaluate(&partial, VACCINATED + 11 * DAY);
+            assert!(!acceptance.acceptable, "{}", field);
+            assert_eq!(acceptance.reasons, vec!["r.0: 2021 is not a complete date"]);
+        }
+    }
+}
@@ -0,0 +1,624 @@
+//! Evaluation of business rules written in [CertLogic](https://github.com/ehn-dcc-development/dgc-business-rules/tree/main/certlogic),
+//! the JsonLogic dialect used by the member states for their validation rules.
+//!
+//! A rule is evaluated on the data `{ "payload": <hcert>, "external": <ExternalParameters> }`. A rule evaluating to
+//! `true` passed, `false` failed. Rules which cannot be evaluated, e.g. because of a missing field or a malformed
+//! expression, are reported as [Outcome::Open] instead of failing the whole validation.
+//!
+//! Supported operations are `var`, `if`, `===`, `and`, `<`, `>`, `<=`, `>=`, `in`, `+`, `!`, `plusTime`,
+//! `before`, `not-before`, `after`, `not-after`, `reduce`, `extractFromUVCI` and `dccDateOfBirth`.
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::claims::{civil_from_days, days_from_civil, parse_rfc3339};
+use crate::hcert::{DatePrecision, Hcert, PartialDate};
+use crate::report::{Check, Verdict};
+use crate::CwtParsed;
+
+/// CertLogic truthiness: `false`, `null`, `0`, `""`, `[]` and `{}` are falsy, everything else is truthy
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// Parse a date or date/time, as used in the hcert and the external parameters, into seconds since the UNIX
+/// epoch. Dates without time denote midnight UTC, date/times without time zone are taken as UTC.
+pub fn parse_date(text: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    if text.len() == 10 {
+        return parse_rfc3339(&format!("{}T00:00:00Z", text));
+    }
+    let has_zone = text
+        .get(19..)
+        .is_some_and(|rest| rest.contains(['Z', 'z', '+', '-']));
+    if has_zone {
+        parse_rfc3339(text)
+    } else {
+        parse_rfc3339(&format!("{}Z", text))
+    }
+}
+
+/// Format seconds since the UNIX epoch as RFC 3339 date/time in UTC
+pub fn format_date(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let seconds = seconds.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn integer(value: &Value, operation: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    value
+        .as_i64()
+        .ok_or_else(|| format!("{} needs integer operands, got {}", operation, value).into())
+}
+
+fn date(value: &Value, operation: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    match value {
+        Value::String(text) => parse_date(text)
+            .map_err(|e| format!("{} needs date operands, {}: {}", operation, text, e).into()),
+        _ => Err(format!("{} needs date operands, got {}", operation, value).into()),
+    }
+}
+
+fn arity(
+    operation: &str,
+    operands: &[Value],
+    allowed: std::ops::RangeInclusive<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if allowed.contains(&operands.len()) {
+        Ok(())
+    } else {
+        Err(format!("{} with {} operands", operation, operands.len()).into())
+    }
+}
+
+/// Add `amount` `unit`s to `date`. Months and years are added to the calendar date, an overflowing day moves
+/// into the next month (`2021-01-31` plus one month is `2021-03-03`).
+fn plus_time(date: i64, amount: i64, unit: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let overflow = || format!("plusTime overflows for {} {}", amount, unit);
+    let in_range = |year: i64| (0..=9999).contains(&year);
+    let calendar = |months: Option<i64>| {
+        let (year, month, day) = civil_from_days(date.div_euclid(86_400));
+        let month = months
+            .and_then(|months| (month as i64 - 1).checked_add(months))
+            .ok_or_else(overflow)?;
+        let year = year + month.div_euclid(12);
+        if !in_range(year) {
+            return Err(overflow());
+        }
+        let first = days_from_civil(year, month.rem_euclid(12) as u32 + 1, 1);
+        Ok((first + day as i64 - 1) * 86_400 + date.rem_euclid(86_400))
+    };
+    let seconds = |factor: i64| {
+        amount
+            .checked_mul(factor)
+            .and_then(|seconds| date.checked_add(seconds))
+            .ok_or_else(overflow)
+    };
+    let result = match unit {
+        "year" => calendar(amount.checked_mul(12))?,
+        "month" => calendar(Some(amount))?,
+        "day" => seconds(86_400)?,
+        "hour" => seconds(3600)?,
+        _ => return Err(format!("plusTime with unknown unit {}", unit).into()),
+    };
+    if !in_range(civil_from_days(result.div_euclid(86_400)).0) {
+        return Err(overflow().into());
+    }
+    Ok(result)
+}
+
+/// The fragments of a UVCI, split at `/`, `#` and `:` after removing the optional `URN:UVCI:` prefix
+fn uvci_fragment(uvci: &str, index: usize) -> Option<&str> {
+    uvci.strip_prefix("URN:UVCI:")
+        .unwrap_or(uvci)
+        .split(['/', '#', ':'])
+        .nth(index)
+}
+
+/// The last day of a possibly partial date of birth (`1998`, `1998-02` or `1998-02-26`)
+fn date_of_birth(dob: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let date = PartialDate::parse(dob)?;
+    match (date.precision(), date.latest()) {
+        (DatePrecision::Year | DatePrecision::Month | DatePrecision::Day, Some(latest)) => {
+            Ok(latest - 86_399)
+        }
+        _ => Err(format!("Invalid date of birth {}", dob).into()),
+    }
+}
+
+/// Evaluate the CertLogic expression `logic` on `data`
+pub fn evaluate(logic: &Value, data: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let (operation, operands) = match logic {
+        Value::Array(items) => {
+            return Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| evaluate(item, data))
+                    .collect::<Result<_, _>>()?,
+            ))
+        }
+        Value::Object(object) if object.len() == 1 => object.iter().next().unwrap(),
+        Value::Object(_) => return Err("Expressions must have exactly one operation".into()),
+        literal => return Ok(literal.clone()),
+    };
+    if operation == "var" {
+        return var(operands, data);
+    }
+    let operands = match operands {
+        Value::Array(operands) => operands,
+        _ => return Err(format!("Operands of {} must be an array", operation).into()),
+    };
+    let operation = operation.as_str();
+    match operation {
+        "if" => {
+            arity(operation, operands, 3..=3)?;
+            if is_truthy(&evaluate(&operands[0], data)?) {
+                evaluate(&operands[1], data)
+            } else {
+                evaluate(&operands[2], data)
+            }
+        }
+        "and" => {
+            arity(operation, operands, 2..=usize::MAX)?;
+            let mut result = Value::Null;
+            for operand in operands {
+                result = evaluate(operand, data)?;
+                if !is_truthy(&result) {
+                    break;
+                }
+            }
+            Ok(result)
+        }
+        "reduce" => {
+            arity(operation, operands, 3..=3)?;
+            let mut accumulator = evaluate(&operands[2], data)?;
+            match evaluate(&operands[0], data)? {
+                Value::Null => {}
+                Value::Array(items) => {
+                    for current in items {
+                        accumulator = evaluate(
+                            &operands[1],
+                            &json!({ "accumulator": accumulator, "current": current }),
+                        )?;
+                    }
+                }
+                other => return Err(format!("reduce needs an array, got {}", other).into()),
+            }
+            Ok(accumulator)
+        }
+        _ => {
+            let values = operands
+                .iter()
+                .map(|operand| evaluate(operand, data))
+                .collect::<Result<Vec<_>, _>>()?;
+            apply(operation, &values)
+        }
+    }
+}
+
+fn var(path: &Value, data: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let path = match path {
+        Value::String(path) => path,
+        _ => return Err("var needs a string path".into()),
+    };
+    if path.is_empty() {
+        return Ok(data.clone());
+    }
+    let mut current = data;
+    for segment in path.split('.') {
+        let next = match current {
+            Value::Object(object) => object.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => return Ok(Value::Null),
+        }
+    }
+    Ok(current.clone())
+}
+
+/// Conversion of an operand for comparisons
+type Conversion = fn(&Value, &str) -> Result<i64, Box<dyn std::error::Error>>;
+
+/// Apply the operations with eagerly evaluated operands
+fn apply(operation: &str, values: &[Value]) -> Result<Value, Box<dyn std::error::Error>> {
+    let chain = |ordered: fn(i64, i64) -> bool,
+                 convert: Conversion|
+     -> Result<Value, Box<dyn std::error::Error>> {
+        let values = values
+            .iter()
+            .map(|v| convert(v, operation))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Bool(values.windows(2).all(|w| ordered(w[0], w[1]))))
+    };
+    match operation {
+        "===" => {
+            arity(operation, values, 2..=2)?;
+            Ok(Value::Bool(values[0] == values[1]))
+        }
+        "!" => {
+            arity(operation, values, 1..=1)?;
+            Ok(Value::Bool(!is_truthy(&values[0])))
+        }
+        "<" | "<=" => {
+            arity(operation, values, 2..=3)?;
+            if operation == "<" {
+                chain(|a, b| a < b, integer)
+            } else {
+                chain(|a, b| a <= b, integer)
+            }
+        }
+        ">" | ">=" => {
+            arity(operation, values, 2..=2)?;
+            if operation == ">" {
+                chain(|a, b| a > b, integer)
+            } else {
+                chain(|a, b| a >= b, integer)
+            }
+        }
+        "before" | "not-after" => {
+            arity(operation, values, 2..=3)?;
+            if operation == "before" {
+                chain(|a, b| a < b, date)
+            } else {
+                chain(|a, b| a <= b, date)
+            }
+        }
+        "after" | "not-before" => {
+            arity(operation, values, 2..=2)?;
+            if operation == "after" {
+                chain(|a, b| a > b, date)
+            } else {
+                chain(|a, b| a >= b, date)
+            }
+        }
+        "+" => {
+            arity(operation, values, 2..=usize::MAX)?;
+            let mut sum = 0i64;
+            for value in values {
+                sum = sum
+                    .checked_add(integer(value, operation)?)
+                    .ok_or("+ overflows")?;
+            }
+            Ok(json!(sum))
+        }
+        "in" => {
+            arity(operation, values, 2..=2)?;
+            match &values[1] {
+                Value::Array(items) => Ok(Value::Bool(items.contains(&values[0]))),
+                other => Err(format!("in needs an array, got {}", other).into()),
+            }
+        }
+        "plusTime" => {
+            arity(operation, values, 3..=3)?;
+            let unit = values[2].as_str().ok_or("plusTime needs a string unit")?;
+            Ok(Value::String(format_date(plus_time(
+                date(&values[0], operation)?,
+                integer(&values[1], operation)?,
+                unit,
+            )?)))
+        }
+        "extractFromUVCI" => {
+            arity(operation, values, 2..=2)?;
+            let index = integer(&values[1], operation)?;
+            match &values[0] {
+                Value::Null => Ok(Value::Null),
+                Value::String(uvci) if index >= 0 => Ok(uvci_fragment(uvci, index as usize)
+                    .map_or(Value::Null, |f| Value::String(f.to_string()))),
+                Value::String(_) => Ok(Value::Null),
+                other => Err(format!("extractFromUVCI needs a string, got {}", other).into()),
+            }
+        }
+        "dccDateOfBirth" => {
+            arity(operation, values, 1..=1)?;
+            match &values[0] {
+                Value::String(dob) => Ok(Value::String(format_date(date_of_birth(dob)?))),
+                other => Err(format!("dccDateOfBirth needs a string, got {}", other).into()),
+            }
+        }
+        _ => Err(format!("Unknown operation {}", operation).into()),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The `external` part of the data the rules are evaluated on
+pub struct ExternalParameters {
+    /// The validation time as RFC 3339 date/time
+    pub validation_clock: String,
+    /// Permitted codes by value set id, e.g. `covid-19-lab-test-type`
+    pub value_sets: BTreeMap<String, Vec<String>>,
+    /// The country the rules are evaluated for
+    pub country_code: String,
+    /// Expiry of the certificate as RFC 3339 date/time
+    pub exp: String,
+    /// Issuance of the certificate as RFC 3339 date/time
+    pub iat: String,
+    pub issuer_country_code: String,
+    /// The base64 encoded `kid`
+    pub kid: String,
+}
+
+impl ExternalParameters {
+    /// Take `exp`, `iat`, `iss` and `kid` from the `cwt`
+    pub fn from_cwt(
+        cwt: &CwtParsed,
+        validation_time: i64,
+        country_code: &str,
+    ) -> Result<ExternalParameters, Box<dyn std::error::Error>> {
+        let claims = cwt.claims()?;
+        Ok(ExternalParameters {
+            validation_clock: format_date(validation_time),
+            value_sets: BTreeMap::new(),
+            country_code: country_code.to_string(),
+            exp: claims
+                .exp
+                .map(|exp| format_date(exp.timestamp()))
+                .unwrap_or_default(),
+            iat: claims
+                .iat
+                .map(|iat| format_date(iat.timestamp()))
+                .unwrap_or_default(),
+            issuer_country_code: claims.iss.unwrap_or_default(),
+            kid: cwt.key_id().map(base64::encode).unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A localized rule description
+pub struct Description {
+    pub lang: String,
+    pub desc: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+/// A business rule as published on the EU gateway
+pub struct Rule {
+    /// E.g. `VR-DE-0001`
+    pub identifier: String,
+    /// `Acceptance` or `Invalidation`
+    #[serde(rename = "Type")]
+    pub rule_type: String,
+    pub country: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub version: String,
+    pub schema_version: String,
+    pub engine: String,
+    pub engine_version: String,
+    /// `General`, `Vaccination`, `Test` or `Recovery`
+    pub certificate_type: String,
+    pub description: Vec<Description>,
+    pub valid_from: String,
+    pub valid_to: String,
+    pub affected_fields: Vec<String>,
+    pub logic: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// The rule could not be evaluated
+    Open,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The outcome of one rule
+pub struct RuleResult {
+    pub identifier: String,
+    pub version: String,
+    pub description: String,
+    pub outcome: Outcome,
+    /// Why the rule is open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Rule {
+    pub fn from_json(json: &str) -> Result<Rule, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The description in `lang`, falling back to English and then to the first description
+    pub fn description(&self, lang: &str) -> String {
+        self.description
+            .iter()
+            .find(|d| d.lang == lang)
+            .or_else(|| self.description.iter().find(|d| d.lang == "en"))
+            .or_else(|| self.description.first())
+            .map(|d| d.desc.clone())
+            .unwrap_or_default()
+    }
+
+    /// Evaluate the rule on `hcert`. The description is taken in English.
+    pub fn evaluate(&self, hcert: &Hcert, external: &ExternalParameters) -> RuleResult {
+        let result = hcert.to_json().and_then(|payload| {
+            let data = json!({ "payload": payload, "external": external });
+            evaluate(&self.logic, &data)
+        });
+        let (outcome, error) = match result {
+            Ok(Value::Bool(true)) => (Outcome::Passed, None),
+            Ok(Value::Bool(false)) => (Outcome::Failed, None),
+            Ok(other) => (
+                Outcome::Open,
+                Some(format!("Rule evaluated to {} instead of a boolean", other)),
+            ),
+            Err(e) => (Outcome::Open, Some(e.to_string())),
+        };
+        RuleResult {
+            identifier: self.identifier.clone(),
+            version: self.version.clone(),
+            description: self.description("en"),
+            outcome,
+            error,
+        }
+    }
+}
+
+/// Evaluate all `rules` on `hcert`
+pub fn evaluate_rules(
+    rules: &[Rule],
+    hcert: &Hcert,
+    external: &ExternalParameters,
+) -> Vec<RuleResult> {
+    rules
+        .iter()
+        .map(|rule| rule.evaluate(hcert, external))
+        .collect()
+}
+
+/// Summarize rule results for the [crate::report::Step::BusinessRules] step, listing the rule versions used.
+/// Open rules count as failures.
+pub fn report_check(results: &[RuleResult]) -> Check {
+    let details = results
+        .iter()
+        .map(|result| {
+            let outcome = match result.outcome {
+                Outcome::Passed => "passed",
+                Outcome::Failed => "failed",
+                Outcome::Open => "open",
+            };
+            format!(
+                "{} {} {}: {}",
+                result.identifier, result.version, outcome, result.description
+            )
+        })
+        .collect();
+    let verdict = if results.iter().all(|r| r.outcome == Outcome::Passed) {
+        Verdict::Passed
+    } else {
+        Verdict::Failed
+    };
+    Check { verdict, details }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::get_payload;
+
+    /// The EU template rule for the vaccination date, in a reduced form
+    const RULE: &str = r#"{
+        "Identifier": "VR-EU-0004",
+        "Type": "Acceptance",
+        "Country": "EU",
+        "Version": "1.0.0",
+        "SchemaVersion": "1.0.0",
+        "Engine": "CERTLOGIC",
+        "EngineVersion": "0.7.5",
+        "CertificateType": "Vaccination",
+        "Description": [{"lang": "en", "desc": "At least 14 days must have passed since the last dose."}],
+        "ValidFrom": "2021-07-01T00:00:00Z",
+        "ValidTo": "2030-06-01T00:00:00Z",
+        "AffectedFields": ["v.0", "v.0.dn", "v.0.sd", "v.0.dt"],
+        "Logic": {
+            "if": [
+                {"var": "payload.v.0"},
+                {"if": [
+                    {">=": [{"var": "payload.v.0.dn"}, {"var": "payload.v.0.sd"}]},
+                    {"not-before": [
+                        {"plusTime": [{"var": "external.validationClock"}, 0, "day"]},
+                        {"plusTime": [{"var": "payload.v.0.dt"}, 14, "day"]}
+                    ]},
+                    false
+                ]},
+                true
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn test_operations() {
+        let data = json!({ "a": [1, 2, 3], "s": "URN:UVCI:01:NL:PlA8UWS60Z4RZXVALl6GAZ" });
+        let eval = |logic: Value| evaluate(&logic, &data).unwrap();
+        assert_eq!(eval(json!({"var": "a.1"})), json!(2));
+        assert_eq!(eval(json!({"var": "a.7"})), Value::Null);
+        assert_eq!(eval(json!({"<": [1, {"var": "a.1"}, 3]})), json!(true));
+        assert_eq!(eval(json!({"in": [3, {"var": "a"}]})), json!(true));
+        assert_eq!(eval(json!({"and": [1, "", true]})), json!(""));
+        assert_eq!(eval(json!({"!": [[]]})), json!(true));
+        assert_eq!(
+            eval(
+                json!({"reduce": [{"var": "a"}, {"+": [{"var": "accumulator"}, {"var": "current"}]}, 0]})
+            ),
+            json!(6)
+        );
+        assert_eq!(
+            eval(json!({"extractFromUVCI": [{"var": "s"}, 1]})),
+            json!("NL")
+        );
+        assert_eq!(
+            eval(json!({"plusTime": ["2021-01-31", 1, "month"]})),
+            json!("2021-03-03T00:00:00Z")
+        );
+        assert_eq!(
+            eval(json!({"plusTime": ["2021-05-01T12:00:00+02:00", -1, "hour"]})),
+            json!("2021-05-01T09:00:00Z")
+        );
+        assert_eq!(
+            eval(json!({"dccDateOfBirth": ["2000-02"]})),
+            json!("2000-02-29T00:00:00Z")
+        );
+        assert_eq!(
+            eval(json!({"before": ["2021-01-01", "2021-01-01T00:00:01Z", "2021-01-02"]})),
+            json!(true)
+        );
+
+        assert!(evaluate(&json!({"<": [1, "2"]}), &data).is_err());
+        assert!(evaluate(&json!({"if": [true, 1]}), &data).is_err());
+        assert!(evaluate(&json!({"unknown": [1]}), &data).is_err());
+        for unit in ["year", "month", "day", "hour"] {
+            for amount in [i64::MAX, i64::MIN, 1_000_000_000] {
+                assert!(
+                    evaluate(&json!({"plusTime": ["2021-01-31", amount, unit]}), &data).is_err()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rule() {
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let hcert = cwt.hcert().unwrap();
+        let rule = Rule::from_json(RULE).unwrap();
+
+        // vaccinated on 2021-04-21
+        let external = ExternalParameters::from_cwt(&cwt, 1_621_000_000, "AT").unwrap();
+        assert_eq!(external.exp, "2022-04-23T09:25:16Z");
+        let result = rule.evaluate(&hcert, &external);
+        assert_eq!(result.outcome, Outcome::Passed);
+        assert_eq!(result.description, rule.description("de"));
+
+        let early = ExternalParameters::from_cwt(&cwt, 1_619_100_000, "AT").unwrap();
+        assert_eq!(rule.evaluate(&hcert, &early).outcome, Outcome::Failed);
+
+        let mut broken = hcert.clone();
+        // a partial date, which the decoder rejects, but the rules cannot handle either
+        broken.v.as_mut().unwrap()[0].dt = PartialDate::parse("2021-04").unwrap();
+        let results = evaluate_rules(&[rule], &broken, &external);
+        assert_eq!(results[0].outcome, Outcome::Open);
+        assert!(results[0].error.is_some());
+        assert_eq!(report_check(&results).verdict, Verdict::Failed);
+    }
+}
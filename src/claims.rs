@@ -0,0 +1,364 @@
+//! Typed CWT claims set ([RFC-8392 Section-3](https://tools.ietf.org/html/rfc8392#section-3)).
+//!
+//! [CwtParsed::message] keeps the raw claims, [CwtClaims] decodes the registered claims together with the `hcert`
+//! and light certificate containers and reports claims of the wrong type instead of silently ignoring them.
+//! serde_cbor drops CBOR tags when decoding, so a date tagged with `1` (epoch) reads like an untagged number and
+//! a date tagged with `0` (date/time string) reads like a text claim, which is accepted for the date claims.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use serde_cbor::Value;
+
+use crate::CwtParsed;
+
+/// Keys of the claims handled by [CwtClaims]
+pub mod claim {
+    pub const ISS: i128 = 1;
+    pub const SUB: i128 = 2;
+    pub const AUD: i128 = 3;
+    pub const EXP: i128 = 4;
+    pub const NBF: i128 = 5;
+    pub const IAT: i128 = 6;
+    pub const CTI: i128 = 7;
+    /// The light certificate container
+    pub const LIGHT_CERT: i128 = -250;
+    pub const HCERT: i128 = crate::HCERT_KEY;
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+/// A `NumericDate` as found in the claims. The representation is kept, so claims can be re-encoded unchanged.
+pub enum NumericDate {
+    /// Seconds since the UNIX epoch, as required by [RFC-8392 Section-2](https://tools.ietf.org/html/rfc8392#section-2)
+    Integer(i64),
+    /// Seconds since the UNIX epoch with fraction
+    Float(f64),
+    /// An RFC 3339 date/time string together with the seconds since the UNIX epoch it denotes
+    Text { text: String, seconds: i64 },
+}
+
+impl NumericDate {
+    /// The (whole) seconds since the UNIX epoch, fractions are truncated towards the past
+    pub fn timestamp(&self) -> i64 {
+        match self {
+            NumericDate::Integer(seconds) => *seconds,
+            NumericDate::Float(seconds) => seconds.floor() as i64,
+            NumericDate::Text { seconds, .. } => *seconds,
+        }
+    }
+
+    fn from_value(value: &Value, name: &str) -> Result<NumericDate, Box<dyn std::error::Error>> {
+        match value {
+            Value::Integer(i) => Ok(NumericDate::Integer(
+                i64::try_from(*i).map_err(|_| format!("{} is out of range", name))?,
+            )),
+            Value::Float(f) if f.is_finite() => Ok(NumericDate::Float(*f)),
+            Value::Text(text) => Ok(NumericDate::Text {
+                seconds: parse_rfc3339(text)
+                    .map_err(|e| format!("{} is no valid date: {}", name, e))?,
+                text: text.to_owned(),
+            }),
+            _ => Err(format!("{} must be a NumericDate", name).into()),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            NumericDate::Integer(seconds) => Value::Integer(*seconds as i128),
+            NumericDate::Float(seconds) => Value::Float(*seconds),
+            NumericDate::Text { text, .. } => Value::Text(text.to_owned()),
+        }
+    }
+}
+
+/// Days since 1970-01-01 of the proleptic Gregorian date
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date `(year, month, day)` of the days since 1970-01-01, inverse of [days_from_civil]
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Number of days of `month` in `year`
+pub(crate) fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+fn number(text: &str, range: std::ops::Range<usize>) -> Result<u32, Box<dyn std::error::Error>> {
+    let digits = text.get(range).ok_or("too short")?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("{} is not a number", digits).into());
+    }
+    Ok(digits.parse()?)
+}
+
+/// Parse an RFC 3339 date/time like `2021-05-01T12:00:00.5+02:00` into seconds since the UNIX epoch
+pub(crate) fn parse_rfc3339(text: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let bytes = text.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return Err("expected YYYY-MM-DDTHH:MM:SS".into());
+    }
+    let (year, month, day) = (
+        number(text, 0..4)? as i64,
+        number(text, 5..7)?,
+        number(text, 8..10)?,
+    );
+    let (hour, minute, second) = (
+        number(text, 11..13)?,
+        number(text, 14..16)?,
+        number(text, 17..19)?,
+    );
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err("invalid date".into());
+    }
+    // allow a leap second
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err("invalid time".into());
+    }
+    let mut rest = &text[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return Err("empty fraction".into());
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6
+            && matches!(rest.as_bytes()[0], b'+' | b'-')
+            && rest.as_bytes()[3] == b':' =>
+        {
+            let offset = (number(rest, 1..3)? * 3600 + number(rest, 4..6)? * 60) as i64;
+            if rest.starts_with('-') {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return Err("invalid time zone".into()),
+    };
+    Ok(
+        days_from_civil(year, month, day) * 86_400 + (hour * 3600 + minute * 60 + second) as i64
+            - offset,
+    )
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The decoded CWT claims
+pub struct CwtClaims {
+    pub iss: Option<String>,
+    pub sub: Option<String>,
+    pub aud: Option<String>,
+    pub exp: Option<NumericDate>,
+    pub nbf: Option<NumericDate>,
+    pub iat: Option<NumericDate>,
+    pub cti: Option<Vec<u8>>,
+    /// The `hcert` container (claim `-260`), c.f. [CwtParsed::get_hcert] for the certificate itself
+    pub hcert: Option<BTreeMap<Value, Value>>,
+    /// The light certificate container (claim `-250`)
+    pub light_cert: Option<BTreeMap<Value, Value>>,
+    /// All other claims
+    pub other: BTreeMap<Value, Value>,
+}
+
+fn text(value: &Value, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    match value {
+        Value::Text(t) => Ok(t.to_owned()),
+        _ => Err(format!("{} must be a text string", name).into()),
+    }
+}
+
+fn map(value: &Value, name: &str) -> Result<BTreeMap<Value, Value>, Box<dyn std::error::Error>> {
+    match value {
+        Value::Map(m) => Ok(m.to_owned()),
+        _ => Err(format!("{} must be a map", name).into()),
+    }
+}
+
+impl CwtClaims {
+    /// Decode the raw claims, failing on wrongly typed claims
+    pub fn from_map(
+        claims: &BTreeMap<Value, Value>,
+    ) -> Result<CwtClaims, Box<dyn std::error::Error>> {
+        let mut result = CwtClaims::default();
+        for (key, value) in claims {
+            result.insert(key, value)?;
+        }
+        Ok(result)
+    }
+
+    /// Decode the raw claims like [CwtClaims::from_map], but keep wrongly typed claims in [CwtClaims::other] and
+    /// return their errors instead of failing
+    pub fn from_map_lenient(claims: &BTreeMap<Value, Value>) -> (CwtClaims, Vec<String>) {
+        let mut result = CwtClaims::default();
+        let mut errors = vec![];
+        for (key, value) in claims {
+            if let Err(e) = result.insert(key, value) {
+                errors.push(e.to_string());
+                result.other.insert(key.clone(), value.clone());
+            }
+        }
+        (result, errors)
+    }
+
+    fn insert(&mut self, key: &Value, value: &Value) -> Result<(), Box<dyn std::error::Error>> {
+        match key {
+            Value::Integer(claim::ISS) => self.iss = Some(text(value, "iss")?),
+            Value::Integer(claim::SUB) => self.sub = Some(text(value, "sub")?),
+            Value::Integer(claim::AUD) => self.aud = Some(text(value, "aud")?),
+            Value::Integer(claim::EXP) => self.exp = Some(NumericDate::from_value(value, "exp")?),
+            Value::Integer(claim::NBF) => self.nbf = Some(NumericDate::from_value(value, "nbf")?),
+            Value::Integer(claim::IAT) => self.iat = Some(NumericDate::from_value(value, "iat")?),
+            Value::Integer(claim::CTI) => match value {
+                Value::Bytes(b) => self.cti = Some(b.to_owned()),
+                _ => return Err("cti must be a byte string".into()),
+            },
+            Value::Integer(claim::HCERT) => self.hcert = Some(map(value, "hcert")?),
+            Value::Integer(claim::LIGHT_CERT) => {
+                self.light_cert = Some(map(value, "light certificate")?)
+            }
+            _ => {
+                self.other.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode into a raw claims map
+    pub fn to_map(&self) -> BTreeMap<Value, Value> {
+        let mut claims = self.other.clone();
+        let mut insert = |key: i128, value: Option<Value>| {
+            if let Some(value) = value {
+                claims.insert(Value::Integer(key), value);
+            }
+        };
+        insert(claim::ISS, self.iss.clone().map(Value::Text));
+        insert(claim::SUB, self.sub.clone().map(Value::Text));
+        insert(claim::AUD, self.aud.clone().map(Value::Text));
+        insert(claim::EXP, self.exp.as_ref().map(NumericDate::to_value));
+        insert(claim::NBF, self.nbf.as_ref().map(NumericDate::to_value));
+        insert(claim::IAT, self.iat.as_ref().map(NumericDate::to_value));
+        insert(claim::CTI, self.cti.clone().map(Value::Bytes));
+        insert(claim::HCERT, self.hcert.clone().map(Value::Map));
+        insert(claim::LIGHT_CERT, self.light_cert.clone().map(Value::Map));
+        claims
+    }
+}
+
+impl CwtParsed {
+    /// Decode the claims, c.f. [CwtClaims::from_map]
+    pub fn claims(&self) -> Result<CwtClaims, Box<dyn std::error::Error>> {
+        CwtClaims::from_map(&self.message)
+    }
+
+    /// Replace the raw claims. They are serialized when signing.
+    pub fn set_claims(&mut self, claims: &CwtClaims) {
+        self.message = claims.to_map();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::get_payload;
+
+    #[test]
+    fn test_rfc_claims() {
+        let cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        let claims = cwt.claims().unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("coap://as.example.com"));
+        assert_eq!(claims.sub.as_deref(), Some("erikw"));
+        assert_eq!(claims.aud.as_deref(), Some("coap://light.example.com"));
+        assert_eq!(claims.exp, Some(NumericDate::Integer(1_444_064_944)));
+        assert_eq!(
+            claims.nbf.as_ref().map(NumericDate::timestamp),
+            Some(1_443_944_944)
+        );
+        assert_eq!(claims.cti, Some(vec![0x0b, 0x71]));
+        assert!(claims.hcert.is_none());
+        assert!(claims.other.is_empty());
+        assert_eq!(claims.to_map(), cwt.message);
+    }
+
+    #[test]
+    fn test_dates() {
+        let mut raw = BTreeMap::new();
+        raw.insert(Value::Integer(claim::EXP), Value::Float(1_620_000_000.75));
+        raw.insert(
+            Value::Integer(claim::IAT),
+            Value::Text("2021-05-03T00:00:00+02:00".to_string()),
+        );
+        let claims = CwtClaims::from_map(&raw).unwrap();
+        assert_eq!(claims.exp.as_ref().unwrap().timestamp(), 1_620_000_000);
+        assert_eq!(claims.iat.as_ref().unwrap().timestamp(), 1_619_992_800);
+        assert_eq!(claims.to_map(), raw);
+
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z").unwrap(), 0);
+        assert_eq!(
+            parse_rfc3339("2000-02-29T12:30:15.123Z").unwrap(),
+            951_827_415
+        );
+        assert!(parse_rfc3339("2021-02-29T00:00:00Z").is_err());
+        assert!(parse_rfc3339("2021-01-01").is_err());
+        // multibyte characters in the time zone
+        assert!(parse_rfc3339("2021-05-01T12:00:00é1:23").is_err());
+        assert!(parse_rfc3339("2021-05-01T12:00:00+0é00").is_err());
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 29)), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let mut raw = BTreeMap::new();
+        raw.insert(
+            Value::Integer(claim::EXP),
+            Value::Text("tomorrow".to_string()),
+        );
+        assert!(CwtClaims::from_map(&raw).is_err());
+        raw.insert(Value::Integer(claim::EXP), Value::Bool(true));
+        assert!(CwtClaims::from_map(&raw).is_err());
+        raw.insert(Value::Integer(claim::EXP), Value::Integer(1));
+        raw.insert(Value::Integer(claim::ISS), Value::Integer(1));
+        assert!(CwtClaims::from_map(&raw).is_err());
+        raw.insert(Value::Integer(claim::ISS), Value::Text("XX".to_string()));
+        raw.insert(Value::Integer(claim::HCERT), Value::Bytes(vec![]));
+        assert!(CwtClaims::from_map(&raw).is_err());
+
+        let (claims, errors) = CwtClaims::from_map_lenient(&raw);
+        assert_eq!(errors, vec!["hcert must be a map".to_string()]);
+        assert_eq!(claims.exp, Some(NumericDate::Integer(1)));
+        assert!(claims.hcert.is_none());
+        assert_eq!(claims.to_map(), raw);
+    }
+}
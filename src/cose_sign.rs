@@ -0,0 +1,381 @@
+//! The multi-signer `COSE_Sign` structure ([RFC-8152 Section-4.1](https://tools.ietf.org/html/rfc8152#section-4.1)).
+//!
+//! ```text
+//! COSE_Sign = [
+//!       Headers,
+//!       payload : bstr / nil,
+//!       signatures : [+ COSE_Signature]
+//!   ]
+//!
+//! COSE_Signature =  [
+//!       Headers,
+//!       signature : bstr
+//!   ]
+//! ```
+//!
+//! Each signer has its own headers (usually `alg` and `kid`), which are covered by its signature together with the
+//! protected headers of the body and the payload.
+use std::collections::BTreeMap;
+
+use serde_cbor::Value;
+
+use crate::countersign;
+use crate::headers::label;
+use crate::signer::Signer;
+use crate::{CwtParsed, VerificationKey};
+
+/// CBOR tag of `COSE_Sign` ([RFC-8152 Section-2](https://tools.ietf.org/html/rfc8152#section-2))
+pub const COSE_SIGN_TAG: u8 = 98;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// A single `COSE_Signature` of a [CoseSignParsed]. Countersignatures ([crate::countersign]) have the same layout.
+pub struct CoseSignature {
+    pub protected_headers: BTreeMap<Value, Value>,
+    pub protected_headers_original: Value,
+    pub unprotected_headers: BTreeMap<Value, Value>,
+    pub signature: Vec<u8>,
+}
+
+impl CoseSignature {
+    /// Prepare an (unsigned) signature with the `alg` and `kid` of `signer` in the protected headers
+    pub(crate) fn for_signer<S: Signer + ?Sized>(
+        signer: &S,
+    ) -> Result<CoseSignature, Box<dyn std::error::Error>> {
+        let mut protected_headers = BTreeMap::new();
+        protected_headers.insert(
+            Value::Integer(label::ALG),
+            Value::Integer(signer.algorithm()),
+        );
+        protected_headers.insert(Value::Integer(label::KID), Value::Bytes(signer.kid()?));
+        Ok(CoseSignature {
+            protected_headers_original: Value::Bytes(serde_cbor::to_vec(&protected_headers)?),
+            protected_headers,
+            unprotected_headers: BTreeMap::new(),
+            signature: vec![],
+        })
+    }
+
+    pub(crate) fn from_value(value: &Value) -> Result<CoseSignature, Box<dyn std::error::Error>> {
+        let value = match value {
+            Value::Array(inner) if inner.len() == 3 => inner,
+            _ => return Err("COSE_Signature is not an array of three".into()),
+        };
+        let (protected_headers_original, protected_headers) = protected_headers(&value[0])?;
+        let unprotected_headers = match &value[1] {
+            Value::Map(m) => m.clone(),
+            _ => return Err("No unprotected headers".into()),
+        };
+        let signature = match &value[2] {
+            Value::Bytes(b) => b.clone(),
+            _ => return Err("no signature".into()),
+        };
+        Ok(CoseSignature {
+            protected_headers,
+            protected_headers_original,
+            unprotected_headers,
+            signature,
+        })
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        Value::Array(vec![
+            self.protected_headers_original.clone(),
+            Value::Map(self.unprotected_headers.clone()),
+            Value::Bytes(self.signature.clone()),
+        ])
+    }
+
+    /// The `kid` of the signer, taken from the protected headers with fallback to the unprotected ones
+    pub fn key_id(&self) -> Option<Vec<u8>> {
+        crate::key_id(&self.protected_headers, &self.unprotected_headers)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// The parsed `COSE_Sign` struct, the multi-signer counterpart of [CwtParsed]
+pub struct CoseSignParsed {
+    pub protected_headers: BTreeMap<Value, Value>,
+    pub protected_headers_original: Value,
+    pub unprotected_headers: BTreeMap<Value, Value>,
+    /// The CWT claims
+    pub message: BTreeMap<Value, Value>,
+    original: Value,
+    pub signatures: Vec<CoseSignature>,
+}
+
+/// Parse a (tagged or untagged) `COSE_Sign` structure
+pub fn get_cose_sign(raw_payload: &[u8]) -> Result<CoseSignParsed, Box<dyn std::error::Error>> {
+    let value: serde_cbor::Value = serde_cbor::from_reader(raw_payload)?;
+    let value = match value {
+        Value::Array(inner) if inner.len() == 4 => inner,
+        _ => return Err("not an array of four".into()),
+    };
+    let (protected_headers_original, protected_headers) = protected_headers(&value[0])?;
+    let unprotected_headers = match &value[1] {
+        Value::Map(m) => m.clone(),
+        _ => return Err("No unprotected headers".into()),
+    };
+    let message = match &value[2] {
+        Value::Bytes(b) => serde_cbor::from_reader(&b[..])?,
+        _ => return Err("no message".into()),
+    };
+    let signatures = match &value[3] {
+        Value::Array(signatures) if !signatures.is_empty() => signatures
+            .iter()
+            .map(CoseSignature::from_value)
+            .collect::<Result<Vec<_>, _>>()?,
+        Value::Bytes(_) => return Err("COSE_Sign1 structure, use get_payload".into()),
+        _ => return Err("no signatures".into()),
+    };
+    Ok(CoseSignParsed {
+        protected_headers,
+        protected_headers_original,
+        unprotected_headers,
+        message,
+        original: value[2].clone(),
+        signatures,
+    })
+}
+
+impl CoseSignParsed {
+    /// Create an unsigned `COSE_Sign` with the given body headers and claims
+    pub fn new(
+        protected_headers: BTreeMap<Value, Value>,
+        message: BTreeMap<Value, Value>,
+    ) -> Result<CoseSignParsed, Box<dyn std::error::Error>> {
+        Ok(CoseSignParsed {
+            protected_headers_original: Value::Bytes(serde_cbor::to_vec(&protected_headers)?),
+            protected_headers,
+            unprotected_headers: BTreeMap::new(),
+            original: Value::Bytes(serde_cbor::to_vec(&message)?),
+            message,
+            signatures: vec![],
+        })
+    }
+
+    /// Create an unsigned `COSE_Sign` carrying the claims of `cwt`. The signer specific `alg` and `kid`
+    /// headers are dropped from the body, as every signature brings its own.
+    pub fn from_cwt(cwt: &CwtParsed) -> Result<CoseSignParsed, Box<dyn std::error::Error>> {
+        let mut protected_headers = cwt.protected_headers.clone();
+        protected_headers.remove(&Value::Integer(label::ALG));
+        protected_headers.remove(&Value::Integer(label::KID));
+        CoseSignParsed::new(protected_headers, cwt.message.clone())
+    }
+
+    /// Add a signature of `signer`. Its `alg` and `kid` are put into the protected headers of the signature.
+    /// The body is frozen with the first signature, later changes to `protected_headers` or `message` are ignored.
+    pub fn add_signature<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.signatures.is_empty() {
+            self.protected_headers_original =
+                Value::Bytes(serde_cbor::to_vec(&self.protected_headers)?);
+            self.original = Value::Bytes(serde_cbor::to_vec(&self.message)?);
+        }
+        let mut signature = CoseSignature::for_signer(signer)?;
+        signature.signature = signer.sign(&self.get_verification_bytes(&signature)?)?;
+        self.signatures.push(signature);
+        Ok(())
+    }
+
+    /// Verify the signature at `index` with `key`
+    pub fn verify_signature(
+        &self,
+        index: usize,
+        key: &VerificationKey,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let signature = self
+            .signatures
+            .get(index)
+            .ok_or("No signature at this index")?;
+        key.verify_signature_with_algorithm(
+            crate::algorithm(&signature.protected_headers),
+            &self.get_verification_bytes(signature)?,
+            &signature.signature,
+        )
+    }
+
+    /// Verify the signature announcing the `kid` `expected_kid` with `key`. Returns the index of the signature.
+    pub fn verify_with_kid(
+        &self,
+        key: &VerificationKey,
+        expected_kid: &[u8],
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let index = self
+            .signatures
+            .iter()
+            .position(|s| s.key_id().as_deref() == Some(expected_kid))
+            .ok_or("No signature with this kid")?;
+        self.verify_signature(index, key)?;
+        Ok(index)
+    }
+
+    /// Get the `Sig_structure` of `signature` according to [RFC-8152 Section-4.4](https://tools.ietf.org/html/rfc8152#section-4.4)
+    pub fn get_verification_bytes(
+        &self,
+        signature: &CoseSignature,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let verification = vec![
+            Value::Text("Signature".to_string()),
+            self.protected_headers_original.clone(),
+            signature.protected_headers_original.clone(),
+            Value::Bytes(vec![]),
+            self.original.clone(),
+        ];
+        Ok(serde_cbor::to_vec(&verification)?)
+    }
+
+    /// The countersignatures ([RFC-9338](https://www.rfc-editor.org/rfc/rfc9338)) of the signature at `index`
+    pub fn countersignatures(
+        &self,
+        index: usize,
+    ) -> Result<Vec<CoseSignature>, Box<dyn std::error::Error>> {
+        let signature = self
+            .signatures
+            .get(index)
+            .ok_or("No signature at this index")?;
+        countersign::read(&signature.unprotected_headers)
+    }
+
+    /// Countersign the signature at `index` with `signer`
+    pub fn add_countersignature<S: Signer + ?Sized>(
+        &mut self,
+        index: usize,
+        signer: &S,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = self
+            .signatures
+            .get(index)
+            .ok_or("No signature at this index")?;
+        let countersignature = countersign::create(
+            signer,
+            &target.protected_headers_original,
+            &self.original,
+            &target.signature,
+        )?;
+        countersign::push(
+            &mut self.signatures[index].unprotected_headers,
+            countersignature,
+        );
+        Ok(())
+    }
+
+    /// Verify a countersignature of the signature at `index`
+    pub fn verify_countersignature(
+        &self,
+        index: usize,
+        countersignature: &CoseSignature,
+        key: &VerificationKey,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = self
+            .signatures
+            .get(index)
+            .ok_or("No signature at this index")?;
+        countersign::verify(
+            countersignature,
+            key,
+            &target.protected_headers_original,
+            &self.original,
+            &target.signature,
+        )
+    }
+
+    /// c.f. [CwtParsed::get_hcert]
+    pub fn get_hcert(&self) -> Option<BTreeMap<Value, Value>> {
+        crate::hcert_from_claims(&self.message)
+    }
+
+    /// Encode as tagged `COSE_Sign`
+    pub fn to_cbor(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let structure = vec![
+            self.protected_headers_original.clone(),
+            Value::Map(self.unprotected_headers.clone()),
+            self.original.clone(),
+            Value::Array(
+                self.signatures
+                    .iter()
+                    .map(CoseSignature::to_value)
+                    .collect(),
+            ),
+        ];
+        // tag 98 needs the one byte length form of major type 6
+        let mut bytes = vec![0xd8, COSE_SIGN_TAG];
+        bytes.extend(serde_cbor::to_vec(&structure)?);
+        Ok(bytes)
+    }
+}
+
+/// Decode a serialized protected header bucket. An empty byte string stands for an empty map.
+pub(crate) fn protected_headers(
+    value: &Value,
+) -> Result<(Value, BTreeMap<Value, Value>), Box<dyn std::error::Error>> {
+    match value {
+        Value::Bytes(b) if b.is_empty() => Ok((value.clone(), BTreeMap::new())),
+        Value::Bytes(b) => Ok((value.clone(), serde_cbor::from_reader(&b[..])?)),
+        _ => Err("No protected headers".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::{decode_hex, get_payload, SigningKey};
+
+    fn signers() -> Vec<SigningKey> {
+        vec![
+            SigningKey::es256(&[3u8; 32]).unwrap(),
+            SigningKey::ed_dsa(&[4u8; 32]).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_multi_signer_round_trip() {
+        let cwt = get_payload(&decode_hex(RFC_TEST).unwrap()).unwrap();
+        let mut cose_sign = CoseSignParsed::from_cwt(&cwt).unwrap();
+        let signers = signers();
+        for signer in &signers {
+            cose_sign.add_signature(signer).unwrap();
+        }
+        let bytes = cose_sign.to_cbor().unwrap();
+        assert_eq!(&bytes[..2], &[0xd8, 0x62]);
+        assert!(get_payload(&bytes).is_err());
+
+        let parsed = get_cose_sign(&bytes).unwrap();
+        assert_eq!(parsed.signatures.len(), 2);
+        assert_eq!(parsed.message, cwt.message);
+        for (index, signer) in signers.iter().enumerate() {
+            let key = signer.verification_key().unwrap();
+            assert!(parsed.verify_signature(index, &key).is_ok());
+            assert_eq!(
+                parsed.verify_with_kid(&key, &key.kid().unwrap()).unwrap(),
+                index
+            );
+        }
+        // the signatures are not interchangeable
+        let key = signers[0].verification_key().unwrap();
+        assert!(parsed.verify_signature(1, &key).is_err());
+    }
+
+    #[test]
+    fn test_signature_countersignature() {
+        let cwt = get_payload(&decode_hex(RFC_TEST).unwrap()).unwrap();
+        let mut cose_sign = CoseSignParsed::from_cwt(&cwt).unwrap();
+        let signers = signers();
+        cose_sign.add_signature(&signers[0]).unwrap();
+        cose_sign.add_countersignature(0, &signers[1]).unwrap();
+
+        let parsed = get_cose_sign(&cose_sign.to_cbor().unwrap()).unwrap();
+        let countersignatures = parsed.countersignatures(0).unwrap();
+        assert_eq!(countersignatures.len(), 1);
+        let key = signers[1].verification_key().unwrap();
+        assert!(parsed
+            .verify_countersignature(0, &countersignatures[0], &key)
+            .is_ok());
+        // adding a countersignature does not break the signature itself
+        assert!(parsed
+            .verify_signature(0, &signers[0].verification_key().unwrap())
+            .is_ok());
+    }
+}
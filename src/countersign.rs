@@ -0,0 +1,237 @@
+//! Countersignatures according to [RFC-9338](https://www.rfc-editor.org/rfc/rfc9338).
+//!
+//! A countersignature signs an already existing signature, e.g. a national backend confirming a certificate
+//! issued by a regional issuer. It is carried in the unprotected `Countersignature version 2` header (label 11) of
+//! the countersigned structure, either as single `COSE_Countersignature` or as array of them. The signed
+//! `Countersign_structure` ([RFC-9338 Section-3.3](https://www.rfc-editor.org/rfc/rfc9338#section-3.3)) is
+//!
+//! ```text
+//! Countersign_structure = [
+//!       context : "CounterSignatureV2",
+//!       body_protected : empty_or_serialized_map,
+//!       sign_protected : empty_or_serialized_map,
+//!       external_aad : bstr,
+//!       payload : bstr,
+//!       other_fields : [+ bstr ]
+//!   ]
+//! ```
+//!
+//! where `other_fields` holds the countersigned signature. As `other_fields` is always present for version 2
+//! countersignatures, the context is always `"CounterSignatureV2"`. Countersignatures of a `COSE_Sign1` are handled
+//! by the methods on [CwtParsed], the ones of the signatures of a `COSE_Sign` by
+//! [CoseSignParsed](crate::cose_sign::CoseSignParsed).
+use std::collections::BTreeMap;
+
+use serde_cbor::Value;
+
+use crate::cose_sign::CoseSignature;
+use crate::headers::label;
+use crate::signer::Signer;
+use crate::{CwtParsed, VerificationKey};
+
+impl CwtParsed {
+    /// The countersignatures of the signature of this CWT
+    pub fn countersignatures(&self) -> Result<Vec<CoseSignature>, Box<dyn std::error::Error>> {
+        read(&self.unprotected_headers)
+    }
+
+    /// Countersign the signature of this CWT with `signer`. The CWT has to be signed already.
+    pub fn add_countersignature<S: Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.signature.is_empty() {
+            return Err("Cannot countersign an unsigned CWT".into());
+        }
+        let countersignature = create(
+            signer,
+            &self.protected_headers_original,
+            &self.original,
+            &self.signature,
+        )?;
+        push(&mut self.unprotected_headers, countersignature);
+        Ok(())
+    }
+
+    /// Verify a countersignature (c.f. [CwtParsed::countersignatures]) of the signature of this CWT
+    pub fn verify_countersignature(
+        &self,
+        countersignature: &CoseSignature,
+        key: &VerificationKey,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        verify(
+            countersignature,
+            key,
+            &self.protected_headers_original,
+            &self.original,
+            &self.signature,
+        )
+    }
+}
+
+/// Read the countersignatures from the unprotected headers of the countersigned structure
+pub(crate) fn read(
+    unprotected_headers: &BTreeMap<Value, Value>,
+) -> Result<Vec<CoseSignature>, Box<dyn std::error::Error>> {
+    match unprotected_headers.get(&Value::Integer(label::COUNTER_SIGNATURE)) {
+        None => Ok(vec![]),
+        // a single COSE_Countersignature starts with its protected headers
+        Some(Value::Array(inner)) if matches!(inner.first(), Some(Value::Bytes(_))) => {
+            Ok(vec![CoseSignature::from_value(&Value::Array(
+                inner.clone(),
+            ))?])
+        }
+        Some(Value::Array(inner)) => inner.iter().map(CoseSignature::from_value).collect(),
+        Some(_) => Err("Invalid countersignature header".into()),
+    }
+}
+
+/// Add `countersignature` to the unprotected headers, switching to the array form if there is one already
+pub(crate) fn push(
+    unprotected_headers: &mut BTreeMap<Value, Value>,
+    countersignature: CoseSignature,
+) {
+    let label = Value::Integer(label::COUNTER_SIGNATURE);
+    let value = match unprotected_headers.remove(&label) {
+        None => countersignature.to_value(),
+        Some(Value::Array(inner)) if matches!(inner.first(), Some(Value::Bytes(_))) => {
+            Value::Array(vec![Value::Array(inner), countersignature.to_value()])
+        }
+        Some(Value::Array(mut inner)) => {
+            inner.push(countersignature.to_value());
+            Value::Array(inner)
+        }
+        Some(_) => countersignature.to_value(),
+    };
+    unprotected_headers.insert(label, value);
+}
+
+/// Create a countersignature over `signature` of the target with the given protected headers and payload
+pub(crate) fn create<S: Signer + ?Sized>(
+    signer: &S,
+    body_protected: &Value,
+    payload: &Value,
+    signature: &[u8],
+) -> Result<CoseSignature, Box<dyn std::error::Error>> {
+    let mut countersignature = CoseSignature::for_signer(signer)?;
+    countersignature.signature = signer.sign(&countersign_structure(
+        body_protected,
+        &countersignature.protected_headers_original,
+        payload,
+        signature,
+    )?)?;
+    Ok(countersignature)
+}
+
+pub(crate) fn verify(
+    countersignature: &CoseSignature,
+    key: &VerificationKey,
+    body_protected: &Value,
+    payload: &Value,
+    signature: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message = countersign_structure(
+        body_protected,
+        &countersignature.protected_headers_original,
+        payload,
+        signature,
+    )?;
+    key.verify_signature_with_algorithm(
+        crate::algorithm(&countersignature.protected_headers),
+        &message,
+        &countersignature.signature,
+    )
+}
+
+fn countersign_structure(
+    body_protected: &Value,
+    sign_protected: &Value,
+    payload: &Value,
+    signature: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let structure = vec![
+        Value::Text("CounterSignatureV2".to_string()),
+        body_protected.clone(),
+        sign_protected.clone(),
+        Value::Bytes(vec![]),
+        payload.clone(),
+        Value::Array(vec![Value::Bytes(signature.to_vec())]),
+    ];
+    Ok(serde_cbor::to_vec(&structure)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::{get_payload, SigningKey};
+
+    #[test]
+    fn test_countersign_cwt() {
+        let issuer = SigningKey::es256(&[3u8; 32]).unwrap();
+        let backend = SigningKey::es384(&[7u8; 48]).unwrap();
+        let notary = SigningKey::ed_dsa(&[42u8; 32]).unwrap();
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        cwt.sign_with_kid(&issuer).unwrap();
+        cwt.add_countersignature(&backend).unwrap();
+        assert_eq!(cwt.countersignatures().unwrap().len(), 1);
+        cwt.add_countersignature(&notary).unwrap();
+
+        let cwt = get_payload(&cwt.to_cbor().unwrap()).unwrap();
+        assert!(cwt.verify(&issuer.verification_key().unwrap()).is_ok());
+        let countersignatures = cwt.countersignatures().unwrap();
+        assert_eq!(countersignatures.len(), 2);
+        let backend_key = backend.verification_key().unwrap();
+        assert_eq!(
+            countersignatures[0].key_id(),
+            Some(backend_key.kid().unwrap())
+        );
+        assert!(cwt
+            .verify_countersignature(&countersignatures[0], &backend_key)
+            .is_ok());
+        assert!(cwt
+            .verify_countersignature(&countersignatures[1], &notary.verification_key().unwrap())
+            .is_ok());
+        assert!(cwt
+            .verify_countersignature(&countersignatures[1], &backend_key)
+            .is_err());
+    }
+
+    #[test]
+    fn test_countersign_structure() {
+        // RFC-9338 Section-3.3, encoded by hand
+        let expected = concat!(
+            "86",
+            "72436f756e7465725369676e61747572655632",
+            "43a10126",
+            "43a10127",
+            "40",
+            "54546869732069732074686520636f6e74656e742e",
+            "81420102"
+        );
+        let structure = countersign_structure(
+            &Value::Bytes(vec![0xa1, 0x01, 0x26]),
+            &Value::Bytes(vec![0xa1, 0x01, 0x27]),
+            &Value::Bytes(b"This is the content.".to_vec()),
+            &[1, 2],
+        )
+        .unwrap();
+        assert_eq!(crate::to_byte_string!(&structure), expected);
+    }
+
+    #[test]
+    fn test_countersignature_covers_signature() {
+        let issuer = SigningKey::es256(&[3u8; 32]).unwrap();
+        let backend = SigningKey::ed_dsa(&[42u8; 32]).unwrap();
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        assert!(cwt.countersignatures().unwrap().is_empty());
+        cwt.add_countersignature(&backend).unwrap();
+        let countersignature = cwt.countersignatures().unwrap().remove(0);
+
+        // re-signing the CWT invalidates the countersignature
+        cwt.sign(&issuer).unwrap();
+        assert!(cwt
+            .verify_countersignature(&countersignature, &backend.verification_key().unwrap())
+            .is_err());
+    }
+}
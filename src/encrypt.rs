@@ -0,0 +1,253 @@
+//! `COSE_Encrypt0` structures ([RFC-8152 Section-5.2](https://tools.ietf.org/html/rfc8152#section-5.2)), encrypted
+//! with a shared AES key in Galois/Counter Mode.
+//!
+//! ```text
+//! COSE_Encrypt0 = [
+//!    Headers,
+//!    ciphertext : bstr / nil,
+//! ]
+//! ```
+//!
+//! The plaintext usually is a complete (signed) `COSE_Sign1`, c.f. [CoseEncrypt0Parsed::decrypt_cwt]. AES-128 and
+//! AES-256 are supported, the key length selects the algorithm.
+use std::collections::BTreeMap;
+
+use ring::aead;
+use serde_cbor::Value;
+
+use crate::cose_sign::protected_headers;
+use crate::headers::label;
+use crate::{get_payload, CwtParsed};
+
+/// CBOR tag of `COSE_Encrypt0` ([RFC-8152 Section-2](https://tools.ietf.org/html/rfc8152#section-2))
+pub const COSE_ENCRYPT0_TAG: u8 = 16;
+
+/// The COSE identifiers of the supported content encryption algorithms
+pub mod algorithm {
+    pub const A128GCM: i128 = 1;
+    pub const A256GCM: i128 = 3;
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// The parsed `COSE_Encrypt0` struct
+pub struct CoseEncrypt0Parsed {
+    pub protected_headers: BTreeMap<Value, Value>,
+    pub protected_headers_original: Value,
+    /// Usually carries the `IV`
+    pub unprotected_headers: BTreeMap<Value, Value>,
+    /// The ciphertext including the 16 byte authentication tag
+    pub ciphertext: Vec<u8>,
+}
+
+/// Parse a (tagged or untagged) `COSE_Encrypt0` structure. Use [CoseEncrypt0Parsed::decrypt] to get the plaintext.
+pub fn get_encrypt0(raw_payload: &[u8]) -> Result<CoseEncrypt0Parsed, Box<dyn std::error::Error>> {
+    let value: serde_cbor::Value = serde_cbor::from_reader(raw_payload)?;
+    let value = match value {
+        Value::Array(inner) if inner.len() == 3 => inner,
+        _ => return Err("not an array of three".into()),
+    };
+    let (protected_headers_original, protected_headers) = protected_headers(&value[0])?;
+    let unprotected_headers = match &value[1] {
+        Value::Map(m) => m.clone(),
+        _ => return Err("No unprotected headers".into()),
+    };
+    let ciphertext = match &value[2] {
+        Value::Bytes(b) => b.clone(),
+        Value::Null => return Err("Detached ciphertexts are not supported".into()),
+        _ => return Err("no ciphertext".into()),
+    };
+    Ok(CoseEncrypt0Parsed {
+        protected_headers,
+        protected_headers_original,
+        unprotected_headers,
+        ciphertext,
+    })
+}
+
+/// The `ring` algorithm for the key length
+fn aead_algorithm(
+    key: &[u8],
+) -> Result<(i128, &'static aead::Algorithm), Box<dyn std::error::Error>> {
+    match key.len() {
+        16 => Ok((algorithm::A128GCM, &aead::AES_128_GCM)),
+        32 => Ok((algorithm::A256GCM, &aead::AES_256_GCM)),
+        len => Err(format!("Unsupported AES key length {}", len).into()),
+    }
+}
+
+impl CoseEncrypt0Parsed {
+    /// Encrypt `plaintext` with `key` and the 12 byte `iv`. The `alg` header is set according to the key length
+    /// and the `IV` is put into the unprotected headers. Never use the same `iv` twice with the same key, the
+    /// parameter only exists to produce reproducible test data.
+    pub fn encrypt(
+        protected_headers: BTreeMap<Value, Value>,
+        plaintext: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<CoseEncrypt0Parsed, Box<dyn std::error::Error>> {
+        let (alg, _) = aead_algorithm(key)?;
+        let mut protected_headers = protected_headers;
+        protected_headers.insert(Value::Integer(label::ALG), Value::Integer(alg));
+        let mut unprotected_headers = BTreeMap::new();
+        unprotected_headers.insert(Value::Integer(label::IV), Value::Bytes(iv.to_vec()));
+        let mut encrypt0 = CoseEncrypt0Parsed {
+            protected_headers,
+            protected_headers_original: Value::Bytes(vec![]),
+            unprotected_headers,
+            ciphertext: vec![],
+        };
+        encrypt0.seal(plaintext, key)?;
+        Ok(encrypt0)
+    }
+
+    /// Encrypt the signed `cwt`, c.f. [CoseEncrypt0Parsed::encrypt]. The `kid` of the CWT is not copied, as it
+    /// names the signing key and not the encryption key.
+    pub fn encrypt_cwt(
+        cwt: &mut CwtParsed,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<CoseEncrypt0Parsed, Box<dyn std::error::Error>> {
+        CoseEncrypt0Parsed::encrypt(BTreeMap::new(), &cwt.to_cbor()?, key, iv)
+    }
+
+    /// Encrypt `plaintext` again with the current headers. Like [CwtParsed::sign] this serializes the current
+    /// `protected_headers` first, but it takes the `IV` from the unprotected headers as is and does not check
+    /// the `alg` header, so both can be set to something wrong on purpose.
+    pub fn seal(&mut self, plaintext: &[u8], key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let (_, aead_algorithm) = aead_algorithm(key)?;
+        self.protected_headers_original =
+            Value::Bytes(serde_cbor::to_vec(&self.protected_headers)?);
+        let key = aead::LessSafeKey::new(
+            aead::UnboundKey::new(aead_algorithm, key).map_err(|_| "Invalid AES key")?,
+        );
+        let mut in_out = plaintext.to_vec();
+        key.seal_in_place_append_tag(
+            self.nonce()?,
+            aead::Aad::from(self.get_encryption_bytes()?),
+            &mut in_out,
+        )
+        .map_err(|_| "Could not encrypt")?;
+        self.ciphertext = in_out;
+        Ok(())
+    }
+
+    /// Decrypt with `key`. Fails if the `alg` header does not match the key length or the ciphertext,
+    /// the `IV` or the protected headers were modified.
+    pub fn decrypt(&self, key: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (alg, aead_algorithm) = aead_algorithm(key)?;
+        match self.protected_headers.get(&Value::Integer(label::ALG)) {
+            Some(Value::Integer(a)) if *a == alg => {}
+            Some(Value::Integer(a)) => {
+                return Err(format!("Algorithm {} does not match the key", a).into())
+            }
+            _ => return Err("No encryption algorithm".into()),
+        }
+        let key = aead::LessSafeKey::new(
+            aead::UnboundKey::new(aead_algorithm, key).map_err(|_| "Invalid AES key")?,
+        );
+        let mut in_out = self.ciphertext.clone();
+        let plaintext = key
+            .open_in_place(
+                self.nonce()?,
+                aead::Aad::from(self.get_encryption_bytes()?),
+                &mut in_out,
+            )
+            .map_err(|_| "Could not decrypt")?;
+        Ok(plaintext.to_vec())
+    }
+
+    /// Decrypt with `key` and parse the plaintext as `COSE_Sign1`
+    pub fn decrypt_cwt(&self, key: &[u8]) -> Result<CwtParsed, Box<dyn std::error::Error>> {
+        get_payload(&self.decrypt(key)?)
+    }
+
+    /// The `kid` of the shared key, c.f. [CwtParsed::key_id]
+    pub fn key_id(&self) -> Option<Vec<u8>> {
+        crate::key_id(&self.protected_headers, &self.unprotected_headers)
+    }
+
+    fn nonce(&self) -> Result<aead::Nonce, Box<dyn std::error::Error>> {
+        match self.unprotected_headers.get(&Value::Integer(label::IV)) {
+            Some(Value::Bytes(iv)) => Ok(aead::Nonce::try_assume_unique_for_key(iv)
+                .map_err(|_| "The IV must have 12 bytes")?),
+            _ => Err("No IV".into()),
+        }
+    }
+
+    /// Get the `Enc_structure` used as additional authenticated data according to [RFC-8152 Section-5.3](https://tools.ietf.org/html/rfc8152#section-5.3)
+    pub fn get_encryption_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let structure = vec![
+            Value::Text("Encrypt0".to_string()),
+            self.protected_headers_original.clone(),
+            Value::Bytes(vec![]),
+        ];
+        Ok(serde_cbor::to_vec(&structure)?)
+    }
+
+    /// Encode as tagged `COSE_Encrypt0`
+    pub fn to_cbor(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let structure = vec![
+            self.protected_headers_original.clone(),
+            Value::Map(self.unprotected_headers.clone()),
+            Value::Bytes(self.ciphertext.clone()),
+        ];
+        // tags below 24 are encoded in the initial byte
+        let mut bytes = vec![0xc0 | COSE_ENCRYPT0_TAG];
+        bytes.extend(serde_cbor::to_vec(&structure)?);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::SigningKey;
+
+    #[test]
+    fn test_encrypt0_round_trip() {
+        let signer = SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        cwt.sign_with_kid(&signer).unwrap();
+        let key = [9u8; 16];
+
+        let encrypt0 = CoseEncrypt0Parsed::encrypt_cwt(&mut cwt, &key, &[1u8; 12]).unwrap();
+        let bytes = encrypt0.to_cbor().unwrap();
+        assert_eq!(bytes[0], 0xd0);
+
+        let parsed = get_encrypt0(&bytes).unwrap();
+        let decrypted = parsed.decrypt_cwt(&key).unwrap();
+        assert_eq!(decrypted.message, cwt.message);
+        assert!(decrypted
+            .verify(&signer.verification_key().unwrap())
+            .is_ok());
+
+        assert!(parsed.decrypt(&[8u8; 16]).is_err());
+        // AES-256 key for an AES-128 ciphertext
+        assert!(parsed.decrypt(&[9u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_encrypt0_protects_headers() {
+        let key = [9u8; 32];
+        let mut encrypt0 =
+            CoseEncrypt0Parsed::encrypt(BTreeMap::new(), b"payload", &key, &[2u8; 12]).unwrap();
+        assert_eq!(encrypt0.decrypt(&key).unwrap(), b"payload".to_vec());
+
+        encrypt0
+            .protected_headers
+            .insert(Value::Integer(label::KID), Value::Bytes(b"kid".to_vec()));
+        encrypt0.protected_headers_original =
+            Value::Bytes(serde_cbor::to_vec(&encrypt0.protected_headers).unwrap());
+        assert!(encrypt0.decrypt(&key).is_err());
+
+        encrypt0.seal(b"payload", &key).unwrap();
+        assert_eq!(encrypt0.key_id(), Some(b"kid".to_vec()));
+        assert!(encrypt0.decrypt(&key).is_ok());
+
+        encrypt0
+            .unprotected_headers
+            .insert(Value::Integer(label::IV), Value::Bytes(vec![2u8; 8]));
+        assert!(encrypt0.decrypt(&key).is_err());
+    }
+}
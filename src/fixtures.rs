@@ -0,0 +1,13 @@
+//! Test data shared by the unit tests of several modules
+
+/// The `COSE_Sign1` example of [RFC-8392 Appendix A.3](https://tools.ietf.org/html/rfc8392#appendix-A.3)
+pub(crate) const RFC_TEST: &str = "d28443a10126a104524173796d6d657472696345434453413235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7158405427c1ff28d23fbad1f29c4c7c6a555e601d6fa29f9179bc3d7438bacaca5acd08c8d4d4f96131680c429a01f85951ecee743a52b9b63632c57209120e1c9e30";
+
+/// A swedish test certificate with a vaccination on 2021-04-21
+pub(crate) const SE_HCERT: &str = "d28450a3012603183d0448ab374d001ccc4a33a059013fa4061a6082927c041a6263c5fc01625858390103a101a46376657265312e302e30636e616da462666e756427417273c3b86e73202d2076616e2048616c656e62676e6e4672616ec3a76f69732d4a6f616e63666e7471444152534f4e533c56414e3c48414c454e63676e746d4652414e434f49533c4a4f414e63646f626a323030392d30322d3238617681aa627467693834303533393030366276706a31313139333439303037626d706c45552f312f32302f31353238626d616d4f52472d31303030333032313562646e02627364026264746a323032312d30342d323162636f624e4c626973782c4d696e6973747279206f66205075626c6963204865616c74682c2057656c6661726520616e642053706f7274626369782575726e3a757663693a30313a4e4c3a506c413855575336305a34525a5856414c6c3647415a5840084660e5f9454f2cfddc3d5e4c6e0a4968e9791df7ac823b837d620eed8d5ddbc39ce9cba44bd430a4b5339eb85306b66a76effecd56c36513165ca5435128e8";
+
+/// A base64 encoded swedish test signer certificate
+pub(crate) const CERT_SE: &str = "MIIBIzCByqADAgECAgQbc6tlMAoGCCqGSM49BAMCMBAxDjAMBgNVBAMMBUVDLU1lMB4XDTIxMDQyMDA3Mjg1MVoXDTIxMDUyMDA3Mjg1MVowEDEOMAwGA1UEAwwFRUMtTWUwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASAnF9trnoiLJxV8zkWDCv4jM9/ls3bC5vVt/+oXkgHCOndb7e/7stg1OP64Gh3l/k64MlTBdR448bQA1IPXgOcoxIwEDAOBgNVHQ8BAf8EBAMCBaAwCgYIKoZIzj0EAwIDSAAwRQIgcRqHvybuL5WlAlNusu++a+cR1onTcj9VeH9ymNsFnQUCIQDfs95vijEGiXZEz2D8LF2umf1zBHvTo2s9u8EW92NypA==";
+
+/// A base64 encoded 2048 bit PKCS#1 RSA private key
+pub(crate) const RSA_PRIVATE_KEY: &str = "MIIEowIBAAKCAQEAzFbQTlvm+IRJ/HwvIIW6xzye64rUMTBxaCUxD6EIzjDr6WkwzaLYpV5pgPPKsr5trTbN/NnaFSj9xeU8JM7AJRIvajvoJ7lRPWd7jIeQ9czGzTjwuo72mu3QzIu85y4tCDiopAVvApyzFUyuuU+JDdVIXHRBQKnMUndA6kVs9oJ5fuisZIrV4OCBsN94NNiomMaMeCD82g7Vo/O5P1KAfM2IHhTD6e7PrSCDERlHpnw8C1NIo0sWuHzA99go6vvkpJVm3zR3cJSX06r354g4w3JrPrnZOt+ikwYXpNXORQYn59VMezKjtKihKgaFy656Ezh8Zw+249ksPX3vhXjMMwIDAQABAoIBAAT+skTYFkNI6I4Vcwl2e2RXZQMY96rkJkGHdzWMmCyCIaIpAhpkuqsALdALJY9M/F1Zk5t0r9IImHt36Qp1S1kcorAUGs6txwCbQPfkcHSxtsJZe/jzea+71HjLiaiVBmDHEchAocAMmIwSvcql2RHPft9TsCymeeoiJCIV5F36M1Hw9RA19pEvzqy2XFtEftDETclWVKoYBLnzMJopptmuklZhEO7zxSH2IVvgdZ4Iax9G5S5VcrP2+eGYBZ5fWkojQYzeVKWikCkms0szoP3TTDoUvXHR7K/Ec7Yc38sxKZ2LLrQ1qltDDMpvJb649KnbR9Omkagu6PyFLpgB9hECgYEA7JVrjCmybdwjbJRCta3sTb+/xQZ1QZo4d87VnsQIKqdXKvg2CjT0tX7o1NC4saIXjg7HBB84D2madX2HvhcO9+M9s+Co3bUFfuccFS7GRSbB+Df5c4qmwgOAunXtm6G+ZVD5o5CG9xWMoRHrekE+GhrNbGbKnEmmUkr9VKFifl0CgYEA3Rvw+vg0U7MuPkHXE3IDWlOS/srSFU8RrmB6D2Yn+eU3FHJZj9YaXkxgsrmI778IxQvizmMOEaESlEFw0qKNQiuFxYLhAuL+/Xvr0XqbkZT4/YM9waTRPuRaTRzqcy/AcKig0FMe9alucunGALH1G3Ix55Uj+eIEpFhtkhJXK88CgYEAlJ/Nv3DWceOwsV52hhHr+G/0dj73XK4YuVNLqgC2rK0RuqHRuRnJfVdrx34T+Su+JnUsG2/NVQlfil3A7+8mbR3pvl3tV9KH0FA5uSj4T6roghoP8MDyv8FZlknNak7zAE0ddt6tmv2DqhBK4TOpYtbhpC7zK7ms7dfES+1SpdUCgYBiHRj0tJi7n2HgvGSMu2XjefxbVXKdhAWLhEIFfUY17FFhoFA/tDjlKS0rgYrTH8jrbxfIj0nZ5siQwu0men0GJLvqZeYk2sddgdSlkqtfkWRfUhJgUBuNtdSgLTmXvVO+agVaC9hMGE/ra/KxskXaVPTyF0rsgi+fIaIVVFAcHwKBgFmjrzI8fY3dol1U8NTf1rqiyTRnYJxKyBWOVZ+ozN8BFID0i+dpnrDatyC+PIhlbD+6nIwD51QVvBBNvlNDHMZZgC6gEeca8JRX/hiK1JfJampeAk/FylOujAjbUOlvaFKHLQEcmWkrU+kOJOuOsWbyh9DcxRGWWKwDi3fFH9GD";
@@ -0,0 +1,448 @@
+//! Typed EU Digital COVID Certificate as found in the `hcert` (c.f. the
+//! [DCC JSON schema](https://github.com/ehn-dcc-development/ehn-dcc-schema)).
+//!
+//! [CwtParsed::get_hcert] returns the raw CBOR map, [Hcert] decodes it into the vaccination, test and recovery
+//! groups. The field names follow the schema, so [Hcert::to_json] gives the JSON the schema and business rules
+//! operate on. Unknown fields are ignored when decoding.
+//!
+//! The date of birth, the sample collection time and the dates of vaccination and recovery are [PartialDate]s,
+//! which keep the original text.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_cbor::Value;
+
+use crate::claims::{days_from_civil, days_in_month, parse_rfc3339};
+use crate::CwtParsed;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The name of the holder. The standardised names use the ICAO 9303 MRZ alphabet, e.g. `DARSONS<VAN<HALEN`.
+pub struct Name {
+    #[serde(rename = "fn", default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(rename = "fnt")]
+    pub standardised_family_name: String,
+    #[serde(rename = "gn", default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(rename = "gnt", default, skip_serializing_if = "Option::is_none")]
+    pub standardised_given_name: Option<String>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+/// The known parts of a [PartialDate]
+pub enum DatePrecision {
+    /// The empty string
+    Unknown,
+    /// `YYYY`
+    Year,
+    /// `YYYY-MM`
+    Month,
+    /// `YYYY-MM-DD`
+    Day,
+    /// An RFC 3339 date/time, e.g. `2021-04-13T14:20:00+02:00`
+    Time,
+}
+
+#[derive(Debug, Clone)]
+/// A possibly partial date as allowed by the DCC schema. A partial date stands for the whole period it
+/// covers, so `1979` equals neither `1979-04` nor `1979-04-14`, and dates with overlapping periods are not
+/// ordered. Two dates are equal if they cover the same period, e.g. two date/times with different time zones.
+pub struct PartialDate {
+    text: String,
+    precision: DatePrecision,
+    /// First and last second of the period, `None` for [DatePrecision::Unknown]
+    period: Option<(i64, i64)>,
+}
+
+impl PartialDate {
+    /// Parse `YYYY`, `YYYY-MM`, `YYYY-MM-DD`, an RFC 3339 date/time or the empty string
+    pub fn parse(text: &str) -> Result<PartialDate, Box<dyn std::error::Error>> {
+        let invalid = || format!("Invalid date {}", text);
+        let number = |range: std::ops::Range<usize>| -> Result<u32, String> {
+            match text.get(range) {
+                Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                    digits.parse().map_err(|_| invalid())
+                }
+                _ => Err(invalid()),
+            }
+        };
+        let separators =
+            |positions: &[usize]| positions.iter().all(|&i| text.as_bytes()[i] == b'-');
+        let day_start =
+            |year: i64, month: u32, day: u32| days_from_civil(year, month, day) * 86_400;
+        let (precision, period) = match text.len() {
+            0 => (DatePrecision::Unknown, None),
+            4 => {
+                let year = number(0..4)? as i64;
+                (
+                    DatePrecision::Year,
+                    Some((day_start(year, 1, 1), day_start(year + 1, 1, 1) - 1)),
+                )
+            }
+            7 if separators(&[4]) => {
+                let (year, month) = (number(0..4)? as i64, number(5..7)?);
+                if !(1..=12).contains(&month) {
+                    return Err(invalid().into());
+                }
+                let end = day_start(year, month, days_in_month(year, month)) + 86_399;
+                (DatePrecision::Month, Some((day_start(year, month, 1), end)))
+            }
+            10 if separators(&[4, 7]) => {
+                let (year, month, day) = (number(0..4)? as i64, number(5..7)?, number(8..10)?);
+                if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+                    return Err(invalid().into());
+                }
+                let start = day_start(year, month, day);
+                (DatePrecision::Day, Some((start, start + 86_399)))
+            }
+            _ => {
+                let time = parse_rfc3339(text).map_err(|e| format!("{}: {}", invalid(), e))?;
+                (DatePrecision::Time, Some((time, time)))
+            }
+        };
+        Ok(PartialDate {
+            text: text.to_string(),
+            precision,
+            period,
+        })
+    }
+
+    /// The original text
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn precision(&self) -> DatePrecision {
+        self.precision
+    }
+
+    /// The first second of the period in seconds since the UNIX epoch, `None` if unknown
+    pub fn earliest(&self) -> Option<i64> {
+        self.period.map(|(start, _)| start)
+    }
+
+    /// The last second of the period in seconds since the UNIX epoch, `None` if unknown
+    pub fn latest(&self) -> Option<i64> {
+        self.period.map(|(_, end)| end)
+    }
+
+    /// Whether both dates could denote the same day or time, e.g. `1979-04` and `1979-04-14`. Unknown dates
+    /// overlap with nothing.
+    pub fn overlaps(&self, other: &PartialDate) -> bool {
+        match (self.period, other.period) {
+            (Some((start, end)), Some((other_start, other_end))) => {
+                start <= other_end && other_start <= end
+            }
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for PartialDate {
+    fn eq(&self, other: &Self) -> bool {
+        self.period == other.period
+    }
+}
+
+impl Eq for PartialDate {}
+
+impl PartialOrd for PartialDate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.period, other.period) {
+            (None, None) => Some(Ordering::Equal),
+            (Some(period), Some(other_period)) if period == other_period => Some(Ordering::Equal),
+            (Some((_, end)), Some((other_start, _))) if end < other_start => Some(Ordering::Less),
+            (Some((start, _)), Some((_, other_end))) if start > other_end => {
+                Some(Ordering::Greater)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for PartialDate {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        PartialDate::parse(text)
+    }
+}
+
+impl fmt::Display for PartialDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Serialize for PartialDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for PartialDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        PartialDate::parse(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `dob` may be partial, but must not contain a time
+pub(crate) fn date_of_birth<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PartialDate, D::Error> {
+    let date = PartialDate::deserialize(deserializer)?;
+    if date.precision() == DatePrecision::Time {
+        return Err(serde::de::Error::custom("dob must not contain a time"));
+    }
+    Ok(date)
+}
+
+/// `dt`, `fr`, `df` and `du` must be complete dates without time
+fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PartialDate, D::Error> {
+    let date = PartialDate::deserialize(deserializer)?;
+    if date.precision() != DatePrecision::Day {
+        return Err(serde::de::Error::custom("expected a date like 2021-04-21"));
+    }
+    Ok(date)
+}
+
+/// `sc` must be a complete date/time
+fn date_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PartialDate, D::Error> {
+    let date = PartialDate::deserialize(deserializer)?;
+    if date.precision() != DatePrecision::Time {
+        return Err(serde::de::Error::custom("expected an RFC 3339 date/time"));
+    }
+    Ok(date)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A vaccination entry (`v`)
+pub struct Vaccination {
+    /// Disease or agent targeted, e.g. `840539006` for COVID-19
+    pub tg: String,
+    /// Vaccine or prophylaxis
+    pub vp: String,
+    /// Medicinal product, e.g. `EU/1/20/1528`
+    pub mp: String,
+    /// Marketing authorisation holder or manufacturer
+    pub ma: String,
+    /// Dose number
+    pub dn: u32,
+    /// Total series of doses
+    pub sd: u32,
+    /// Date of vaccination
+    #[serde(deserialize_with = "date")]
+    pub dt: PartialDate,
+    pub co: String,
+    pub is: String,
+    pub ci: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A test entry (`t`)
+pub struct Test {
+    pub tg: String,
+    /// Type of test, e.g. `LP6464-4` for NAAT
+    pub tt: String,
+    /// Name of a NAAT test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nm: Option<String>,
+    /// Manufacturer of a rapid antigen test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ma: Option<String>,
+    /// Date and time of the sample collection
+    #[serde(deserialize_with = "date_time")]
+    pub sc: PartialDate,
+    /// Test result, e.g. `260415000` for not detected
+    pub tr: String,
+    /// Testing centre
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tc: Option<String>,
+    pub co: String,
+    pub is: String,
+    pub ci: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A recovery entry (`r`)
+pub struct Recovery {
+    pub tg: String,
+    /// Date of the first positive test
+    #[serde(deserialize_with = "date")]
+    pub fr: PartialDate,
+    pub co: String,
+    pub is: String,
+    /// Certificate valid from
+    #[serde(deserialize_with = "date")]
+    pub df: PartialDate,
+    /// Certificate valid until
+    #[serde(deserialize_with = "date")]
+    pub du: PartialDate,
+    pub ci: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The health certificate. Exactly one of `v`, `t` or `r` is expected, c.f. [Hcert::certificate_type].
+pub struct Hcert {
+    pub ver: String,
+    pub nam: Name,
+    /// Date of birth, possibly partial or empty
+    #[serde(deserialize_with = "date_of_birth")]
+    pub dob: PartialDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<Vec<Vaccination>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub t: Option<Vec<Test>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r: Option<Vec<Recovery>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The kind of certificate, named as in the business rules
+pub enum CertificateType {
+    Vaccination,
+    Test,
+    Recovery,
+}
+
+impl Hcert {
+    /// Decode the raw `hcert` map as returned by [CwtParsed::get_hcert]
+    pub fn from_map(hcert: &BTreeMap<Value, Value>) -> Result<Hcert, Box<dyn std::error::Error>> {
+        Ok(serde_cbor::value::from_value(Value::Map(hcert.clone()))?)
+    }
+
+    /// Encode into a raw `hcert` map
+    pub fn to_map(&self) -> Result<BTreeMap<Value, Value>, Box<dyn std::error::Error>> {
+        match serde_cbor::value::to_value(self)? {
+            Value::Map(map) => Ok(map),
+            _ => Err("hcert is not a map".into()),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// The type of the certificate, `None` if there is not exactly one non-empty group
+    pub fn certificate_type(&self) -> Option<CertificateType> {
+        let present = |group: Option<usize>| group.is_some_and(|len| len > 0);
+        match (
+            present(self.v.as_ref().map(Vec::len)),
+            present(self.t.as_ref().map(Vec::len)),
+            present(self.r.as_ref().map(Vec::len)),
+        ) {
+            (true, false, false) => Some(CertificateType::Vaccination),
+            (false, true, false) => Some(CertificateType::Test),
+            (false, false, true) => Some(CertificateType::Recovery),
+            _ => None,
+        }
+    }
+}
+
+impl CwtParsed {
+    /// Decode the `hcert`, c.f. [Hcert::from_map]
+    pub fn hcert(&self) -> Result<Hcert, Box<dyn std::error::Error>> {
+        Hcert::from_map(&self.get_hcert().ok_or("No hcert")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::get_payload;
+
+    #[test]
+    fn test_typed_hcert() {
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let hcert = cwt.hcert().unwrap();
+        assert_eq!(
+            hcert.nam.standardised_given_name.as_deref(),
+            Some("FRANCOIS<JOAN")
+        );
+        assert_eq!(hcert.dob.as_str(), "2009-02-28");
+        assert_eq!(hcert.certificate_type(), Some(CertificateType::Vaccination));
+        let vaccination = &hcert.v.as_ref().unwrap()[0];
+        assert_eq!((vaccination.dn, vaccination.sd), (2, 2));
+        assert_eq!(vaccination.mp, "EU/1/20/1528");
+
+        assert_eq!(hcert.to_map().unwrap(), cwt.get_hcert().unwrap());
+        let json = hcert.to_json().unwrap();
+        assert_eq!(json["v"][0]["co"], "NL");
+        assert!(json.get("t").is_none());
+    }
+
+    #[test]
+    fn test_partial_date() {
+        let date = |text: &str| PartialDate::parse(text).unwrap();
+        assert_eq!(date("1979-04").as_str(), "1979-04");
+        assert_eq!(date("1979").precision(), DatePrecision::Year);
+        assert_eq!(date("").precision(), DatePrecision::Unknown);
+        assert_eq!(
+            date("2021-04-13T14:20:00+02:00"),
+            date("2021-04-13T12:20:00Z")
+        );
+        assert_eq!(date("1980-02").latest(), Some(320_716_799));
+        assert!(date("1979-04-14") < date("1979-05"));
+        assert!(date("1980") > date("1979-12-31T23:59:59Z"));
+        assert_eq!(date("1979").partial_cmp(&date("1979-04-14")), None);
+        assert_ne!(date("1979"), date("1979-04-14"));
+        assert!(date("1979").overlaps(&date("1979-04-14")));
+        assert!(!date("").overlaps(&date("1979")));
+        assert!(!date("1979-04").overlaps(&date("1979-05-01")));
+
+        for invalid in &[
+            "79",
+            "1979-4",
+            "1979-13",
+            "1979-02-29",
+            "1979/04/14",
+            "14.04.1979",
+            "2021-04-13T14:20:00",
+        ] {
+            assert!(PartialDate::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_invalid_hcert() {
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let mut raw = cwt.get_hcert().unwrap();
+        raw.remove(&Value::Text("dob".to_string()));
+        assert!(Hcert::from_map(&raw).is_err());
+        for dob in &["1979-04-14T00:00:00Z", "14.04.1979"] {
+            raw.insert(Value::Text("dob".to_string()), Value::Text(dob.to_string()));
+            assert!(Hcert::from_map(&raw).is_err(), "{}", dob);
+        }
+        raw.insert(
+            Value::Text("dob".to_string()),
+            Value::Text("1979".to_string()),
+        );
+        assert_eq!(Hcert::from_map(&raw).unwrap().dob.as_str(), "1979");
+
+        let mut v = match raw.get(&Value::Text("v".to_string())) {
+            Some(Value::Array(v)) => v.clone(),
+            _ => unreachable!(),
+        };
+        for dt in &["21.04.2021", "2021-04", "2021-04-21T10:00:00Z"] {
+            if let Value::Map(entry) = &mut v[0] {
+                entry.insert(Value::Text("dt".to_string()), Value::Text(dt.to_string()));
+            }
+            raw.insert(Value::Text("v".to_string()), Value::Array(v.clone()));
+            assert!(Hcert::from_map(&raw).is_err(), "{}", dt);
+        }
+
+        let mut hcert = cwt.hcert().unwrap();
+        hcert.t = Some(vec![]);
+        assert_eq!(hcert.certificate_type(), Some(CertificateType::Vaccination));
+        hcert.v = None;
+        assert_eq!(hcert.certificate_type(), None);
+    }
+}
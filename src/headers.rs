@@ -0,0 +1,307 @@
+//! Typed view on the COSE header buckets ([RFC-8152 Section-3.1](https://tools.ietf.org/html/rfc8152#section-3.1)).
+//!
+//! The raw maps in [CwtParsed::protected_headers] and [CwtParsed::unprotected_headers] stay the source of truth, so
+//! deliberately malformed headers can still be produced. [CoseHeaders] is decoded from and encoded into those maps
+//! on request and reports wrongly typed values, [Headers::validate] checks the rules spanning both buckets.
+use std::collections::BTreeMap;
+
+use serde_cbor::Value;
+
+use crate::CwtParsed;
+
+/// Labels of the common header parameters
+pub mod label {
+    pub const ALG: i128 = 1;
+    pub const CRIT: i128 = 2;
+    pub const CONTENT_TYPE: i128 = 3;
+    pub const KID: i128 = 4;
+    pub const IV: i128 = 5;
+    pub const PARTIAL_IV: i128 = 6;
+    pub const COUNTER_SIGNATURE: i128 = 11;
+    pub const X5CHAIN: i128 = 33;
+    pub const X5T: i128 = 34;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Header labels, algorithms and content types can be integers or text
+pub enum Label {
+    Int(i128),
+    Text(String),
+}
+
+impl Label {
+    fn from_value(value: &Value) -> Option<Label> {
+        match value {
+            Value::Integer(i) => Some(Label::Int(*i)),
+            Value::Text(t) => Some(Label::Text(t.to_owned())),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Label::Int(i) => Value::Integer(*i),
+            Label::Text(t) => Value::Text(t.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The decoded content of one header bucket
+pub struct CoseHeaders {
+    pub alg: Option<Label>,
+    /// Labels the recipient has to understand, only allowed in the protected bucket
+    pub crit: Vec<Label>,
+    pub content_type: Option<Label>,
+    pub kid: Option<Vec<u8>>,
+    pub iv: Option<Vec<u8>>,
+    pub partial_iv: Option<Vec<u8>>,
+    /// DER encoded certificates, c.f. [crate::x5chain]
+    pub x5chain: Vec<Vec<u8>>,
+    /// All other labels, e.g. countersignatures or `x5t`
+    pub custom: BTreeMap<Value, Value>,
+}
+
+fn bytes(value: &Value, name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match value {
+        Value::Bytes(b) => Ok(b.to_owned()),
+        _ => Err(format!("{} must be a byte string", name).into()),
+    }
+}
+
+fn label(value: &Value, name: &str) -> Result<Label, Box<dyn std::error::Error>> {
+    Label::from_value(value).ok_or_else(|| format!("{} must be an integer or text", name).into())
+}
+
+impl CoseHeaders {
+    /// Decode a raw header map, failing on wrongly typed values of the known labels
+    pub fn from_map(
+        map: &BTreeMap<Value, Value>,
+    ) -> Result<CoseHeaders, Box<dyn std::error::Error>> {
+        let mut headers = CoseHeaders::default();
+        for (key, value) in map {
+            let key_label = match key {
+                Value::Integer(i) => *i,
+                Value::Text(_) => {
+                    headers.custom.insert(key.clone(), value.clone());
+                    continue;
+                }
+                _ => return Err("Header labels must be integers or text".into()),
+            };
+            match key_label {
+                label::ALG => headers.alg = Some(label(value, "alg")?),
+                label::CRIT => {
+                    headers.crit = match value {
+                        Value::Array(labels) if !labels.is_empty() => labels
+                            .iter()
+                            .map(|l| label(l, "crit entries"))
+                            .collect::<Result<_, _>>()?,
+                        _ => return Err("crit must be a non-empty array".into()),
+                    }
+                }
+                label::CONTENT_TYPE => headers.content_type = Some(label(value, "content type")?),
+                label::KID => headers.kid = Some(bytes(value, "kid")?),
+                label::IV => headers.iv = Some(bytes(value, "IV")?),
+                label::PARTIAL_IV => headers.partial_iv = Some(bytes(value, "Partial IV")?),
+                label::X5CHAIN => {
+                    headers.x5chain = match value {
+                        Value::Bytes(cert) => vec![cert.to_owned()],
+                        Value::Array(certs) if !certs.is_empty() => certs
+                            .iter()
+                            .map(|c| bytes(c, "x5chain entries"))
+                            .collect::<Result<_, _>>()?,
+                        _ => return Err("Invalid x5chain header".into()),
+                    }
+                }
+                _ => {
+                    headers.custom.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(headers)
+    }
+
+    /// Encode into a raw header map. Custom labels are copied as they are, even if they collide with a known label.
+    pub fn to_map(&self) -> BTreeMap<Value, Value> {
+        let mut map = self.custom.clone();
+        let mut insert = |key: i128, value: Value| {
+            map.insert(Value::Integer(key), value);
+        };
+        if let Some(alg) = &self.alg {
+            insert(label::ALG, alg.to_value());
+        }
+        if !self.crit.is_empty() {
+            insert(
+                label::CRIT,
+                Value::Array(self.crit.iter().map(Label::to_value).collect()),
+            );
+        }
+        if let Some(content_type) = &self.content_type {
+            insert(label::CONTENT_TYPE, content_type.to_value());
+        }
+        if let Some(kid) = &self.kid {
+            insert(label::KID, Value::Bytes(kid.to_owned()));
+        }
+        if let Some(iv) = &self.iv {
+            insert(label::IV, Value::Bytes(iv.to_owned()));
+        }
+        if let Some(partial_iv) = &self.partial_iv {
+            insert(label::PARTIAL_IV, Value::Bytes(partial_iv.to_owned()));
+        }
+        match self.x5chain.as_slice() {
+            [] => {}
+            [cert] => insert(label::X5CHAIN, Value::Bytes(cert.to_owned())),
+            certs => insert(
+                label::X5CHAIN,
+                Value::Array(certs.iter().map(|c| Value::Bytes(c.to_owned())).collect()),
+            ),
+        }
+        map
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Both header buckets of a COSE structure
+pub struct Headers {
+    pub protected: CoseHeaders,
+    pub unprotected: CoseHeaders,
+}
+
+impl Headers {
+    /// Decode both raw buckets
+    pub fn from_maps(
+        protected: &BTreeMap<Value, Value>,
+        unprotected: &BTreeMap<Value, Value>,
+    ) -> Result<Headers, Box<dyn std::error::Error>> {
+        Ok(Headers {
+            protected: CoseHeaders::from_map(protected)?,
+            unprotected: CoseHeaders::from_map(unprotected)?,
+        })
+    }
+
+    /// The `alg`, which has to be protected
+    pub fn alg(&self) -> Option<&Label> {
+        self.protected.alg.as_ref()
+    }
+
+    /// The `kid`, preferring the protected bucket
+    pub fn kid(&self) -> Option<&[u8]> {
+        self.protected
+            .kid
+            .as_deref()
+            .or(self.unprotected.kid.as_deref())
+    }
+
+    /// Check the rules of [RFC-8152 Section-3](https://tools.ietf.org/html/rfc8152#section-3): no label may occur in
+    /// both buckets, `crit` has to be protected and every label listed in `crit` has to be present in the
+    /// protected bucket. `IV` and `Partial IV` must not both be present.
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let protected = self.protected.to_map();
+        let unprotected = self.unprotected.to_map();
+        let duplicates: Vec<_> = protected
+            .keys()
+            .filter(|k| unprotected.contains_key(k))
+            .collect();
+        if !duplicates.is_empty() {
+            return Err(format!("Labels {:?} are in both header buckets", duplicates).into());
+        }
+        if !self.unprotected.crit.is_empty() {
+            return Err("crit has to be a protected header".into());
+        }
+        for critical in &self.protected.crit {
+            if !protected.contains_key(&critical.to_value()) {
+                return Err(format!("Critical header {:?} is missing", critical).into());
+            }
+        }
+        let has_iv = self.protected.iv.is_some() || self.unprotected.iv.is_some();
+        let has_partial_iv =
+            self.protected.partial_iv.is_some() || self.unprotected.partial_iv.is_some();
+        if has_iv && has_partial_iv {
+            return Err("IV and Partial IV must not both be present".into());
+        }
+        Ok(())
+    }
+}
+
+impl CwtParsed {
+    /// Decode the raw header maps, c.f. [Headers::validate] for the checks across both buckets
+    pub fn headers(&self) -> Result<Headers, Box<dyn std::error::Error>> {
+        Headers::from_maps(&self.protected_headers, &self.unprotected_headers)
+    }
+
+    /// Replace the raw header maps. The headers are not validated, so invalid combinations can be produced
+    /// on purpose. The protected headers are serialized when signing.
+    pub fn set_headers(&mut self, headers: &Headers) {
+        self.protected_headers = headers.protected.to_map();
+        self.unprotected_headers = headers.unprotected.to_map();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::get_payload;
+
+    #[test]
+    fn test_rfc_headers() {
+        let cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        let headers = cwt.headers().unwrap();
+        assert_eq!(headers.alg(), Some(&Label::Int(-7)));
+        assert_eq!(headers.kid(), Some(&b"AsymmetricECDSA256"[..]));
+        assert!(headers.unprotected.alg.is_none());
+        assert!(headers.validate().is_ok());
+        assert_eq!(headers.protected.to_map(), cwt.protected_headers);
+        assert_eq!(headers.unprotected.to_map(), cwt.unprotected_headers);
+    }
+
+    #[test]
+    fn test_header_validation() {
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        let mut headers = cwt.headers().unwrap();
+
+        // kid in both buckets
+        headers.protected.kid = Some(b"kid".to_vec());
+        assert!(headers.validate().is_err());
+        headers.unprotected.kid = None;
+        assert!(headers.validate().is_ok());
+
+        headers.protected.crit = vec![Label::Int(-70000)];
+        assert!(headers.validate().is_err());
+        headers
+            .protected
+            .custom
+            .insert(Value::Integer(-70000), Value::Bool(true));
+        assert!(headers.validate().is_ok());
+        headers.unprotected.crit = vec![Label::Int(label::KID)];
+        assert!(headers.validate().is_err());
+        headers.unprotected.crit.clear();
+
+        headers.unprotected.iv = Some(vec![0; 12]);
+        headers.protected.partial_iv = Some(vec![1]);
+        assert!(headers.validate().is_err());
+        headers.protected.partial_iv = None;
+
+        cwt.set_headers(&headers);
+        assert_eq!(cwt.headers().unwrap(), headers);
+        assert_eq!(cwt.key_id(), Some(b"kid".to_vec()));
+    }
+
+    #[test]
+    fn test_wrongly_typed_headers() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Integer(label::KID), Value::Text("kid".to_string()));
+        assert!(CoseHeaders::from_map(&map).is_err());
+        map.insert(Value::Integer(label::KID), Value::Bytes(vec![1]));
+        map.insert(Value::Integer(label::CRIT), Value::Array(vec![]));
+        assert!(CoseHeaders::from_map(&map).is_err());
+        map.insert(
+            Value::Integer(label::CRIT),
+            Value::Array(vec![Value::Text("x".to_string())]),
+        );
+        map.insert(Value::Text("x".to_string()), Value::Null);
+        let headers = CoseHeaders::from_map(&map).unwrap();
+        assert_eq!(headers.crit, vec![Label::Text("x".to_string())]);
+        assert_eq!(headers.to_map(), map);
+    }
+}
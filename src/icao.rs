@@ -0,0 +1,280 @@
+//! Transliteration of names into the machine readable form of [ICAO Doc 9303](https://www.icao.int/publications/Documents/9303_p3_cons_en.pdf)
+//! Part 3, as required for the standardised names `fnt` and `gnt` of the `hcert`.
+//!
+//! The standardised names only consist of `A-Z` and the filler `<`, which replaces spaces, hyphens and commas.
+//! Apostrophes and full stops are omitted. Some characters have more than one accepted transliteration, e.g.
+//! `Ü` can be written as `UE`, `UXX` or `U`, likewise `Ä` and `Ö`. [transliterate] produces the recommended form, [matches] accepts
+//! every combination of the alternatives.
+use crate::hcert::Name;
+
+/// Maximum length of `fnt` and `gnt` according to the DCC schema
+pub const MAX_LENGTH: usize = 80;
+/// The filler replacing separators
+pub const FILLER: char = '<';
+
+/// Transliterations of Latin characters with diacritics and of Cyrillic characters, the recommended one first
+const TABLE: &[(&str, &[&str])] = &[
+    ("ÀÁÂÃĀĂĄ", &["A"]),
+    ("Ä", &["AE", "AXX", "A"]),
+    ("Å", &["AA", "A"]),
+    ("Æ", &["AE"]),
+    ("ÇĆĈĊČ", &["C"]),
+    ("ĎĐÐ", &["D"]),
+    ("ÈÉÊËĒĔĖĘĚ", &["E"]),
+    ("ĜĞĠĢ", &["G"]),
+    ("ĤĦ", &["H"]),
+    ("ÌÍÎÏĨĪĬĮİ", &["I"]),
+    ("Ĳ", &["IJ"]),
+    ("Ĵ", &["J"]),
+    ("Ķ", &["K"]),
+    ("ĹĻĽĿŁ", &["L"]),
+    ("Ñ", &["N", "NXX"]),
+    ("ŃŅŇŊ", &["N"]),
+    ("ÒÓÔÕŌŎŐ", &["O"]),
+    ("Ö", &["OE", "OXX", "O"]),
+    ("Ø", &["OE", "O"]),
+    ("Œ", &["OE"]),
+    ("ŔŖŘ", &["R"]),
+    ("ŚŜŞŠȘ", &["S"]),
+    ("ẞ", &["SS"]),
+    ("ŢŤŦȚ", &["T"]),
+    ("Þ", &["TH"]),
+    ("ÙÚÛŨŪŬŮŰŲ", &["U"]),
+    ("Ü", &["UE", "UXX", "U"]),
+    ("Ŵ", &["W"]),
+    ("ÝŶŸ", &["Y"]),
+    ("ŹŻŽ", &["Z"]),
+    ("А", &["A"]),
+    ("Б", &["B"]),
+    ("В", &["V"]),
+    ("Г", &["G"]),
+    ("Д", &["D"]),
+    ("ЕЁЭ", &["E"]),
+    ("Ж", &["ZH"]),
+    ("З", &["Z"]),
+    ("ИЙ", &["I"]),
+    ("К", &["K"]),
+    ("Л", &["L"]),
+    ("М", &["M"]),
+    ("Н", &["N"]),
+    ("О", &["O"]),
+    ("П", &["P"]),
+    ("Р", &["R"]),
+    ("С", &["S"]),
+    ("Т", &["T"]),
+    ("У", &["U"]),
+    ("Ф", &["F"]),
+    ("Х", &["KH"]),
+    ("Ц", &["TS"]),
+    ("Ч", &["CH"]),
+    ("Ш", &["SH"]),
+    ("Щ", &["SHCH"]),
+    ("Ъ", &["IE"]),
+    ("Ы", &["Y"]),
+    ("Ь", &[""]),
+    ("Ю", &["IU"]),
+    ("Я", &["IA"]),
+];
+
+/// One character of the name together with its accepted transliterations
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Unit {
+    Letter(Vec<String>),
+    Filler,
+}
+
+fn alternatives(c: char) -> Option<Vec<String>> {
+    if c == 'ß' {
+        return Some(vec!["SS".to_string()]);
+    }
+    let mut upper = c.to_uppercase();
+    let c = match (upper.next(), upper.next()) {
+        (Some(upper), None) => upper,
+        _ => c,
+    };
+    if c.is_ascii_uppercase() {
+        return Some(vec![c.to_string()]);
+    }
+    TABLE
+        .iter()
+        .find(|(chars, _)| chars.contains(c))
+        .map(|(_, alternatives)| alternatives.iter().map(|a| a.to_string()).collect())
+}
+
+/// Split `name` into units, failing with the characters which cannot be transliterated
+fn units(name: &str) -> Result<Vec<Unit>, Vec<char>> {
+    let mut units = vec![];
+    let mut illegal = vec![];
+    for c in name.chars() {
+        match c {
+            ' ' | '-' | ',' | FILLER => {
+                if matches!(units.last(), Some(Unit::Letter(_))) {
+                    units.push(Unit::Filler);
+                }
+            }
+            '\'' | '’' | '`' | '.' => {}
+            _ => match alternatives(c) {
+                Some(alternatives) => units.push(Unit::Letter(alternatives)),
+                None => illegal.push(c),
+            },
+        }
+    }
+    if units.last() == Some(&Unit::Filler) {
+        units.pop();
+    }
+    if illegal.is_empty() {
+        Ok(units)
+    } else {
+        Err(illegal)
+    }
+}
+
+/// The recommended ICAO 9303 transliteration of `name`, e.g. `DARSOENS<VAN<HALEN` for `d'Arsøns - van Halen`
+pub fn transliterate(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let units = units(name).map_err(|illegal| format!("Cannot transliterate {:?}", illegal))?;
+    Ok(units
+        .iter()
+        .map(|unit| match unit {
+            Unit::Letter(alternatives) => alternatives[0].clone(),
+            Unit::Filler => FILLER.to_string(),
+        })
+        .collect())
+}
+
+/// Check that `standardised` only consists of `A-Z` and `<` and is not too long
+pub fn validate_standardised(standardised: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let illegal: Vec<_> = standardised
+        .chars()
+        .filter(|c| !c.is_ascii_uppercase() && *c != FILLER)
+        .collect();
+    if !illegal.is_empty() {
+        return Err(format!("Illegal characters {:?}", illegal).into());
+    }
+    if standardised.len() > MAX_LENGTH {
+        return Err(format!("Longer than {} characters", MAX_LENGTH).into());
+    }
+    Ok(())
+}
+
+/// Whether `standardised` is an accepted transliteration of `name`. Fails if `name` contains characters which
+/// cannot be transliterated.
+pub fn matches(name: &str, standardised: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let units = units(name).map_err(|illegal| format!("Cannot transliterate {:?}", illegal))?;
+    let target = standardised.as_bytes();
+    // reachable[j]: the units so far can produce the first j characters of the target
+    let mut reachable = vec![false; target.len() + 1];
+    reachable[0] = true;
+    for unit in &units {
+        let mut next = vec![false; target.len() + 1];
+        for start in (0..=target.len()).filter(|&j| reachable[j]) {
+            let produced: Vec<&str> = match unit {
+                Unit::Letter(alternatives) => alternatives.iter().map(String::as_str).collect(),
+                Unit::Filler => vec!["<"],
+            };
+            for alternative in produced {
+                if target[start..].starts_with(alternative.as_bytes()) {
+                    next[start + alternative.len()] = true;
+                }
+            }
+        }
+        reachable = next;
+    }
+    Ok(reachable[target.len()])
+}
+
+/// Check one pair of primary and standardised name
+fn check_pair(field: &str, name: Option<&str>, standardised: Option<&str>) -> Vec<String> {
+    let mut problems = vec![];
+    if let Some(standardised) = standardised {
+        if let Err(e) = validate_standardised(standardised) {
+            problems.push(format!("{}t: {}", field, e));
+        }
+    }
+    match (name, standardised) {
+        (Some(name), Some(standardised)) => match matches(name, standardised) {
+            Ok(true) => {}
+            Ok(false) => problems.push(format!(
+                "{}t {} does not match {} (expected {})",
+                field,
+                standardised,
+                name,
+                transliterate(name).unwrap_or_default()
+            )),
+            Err(e) => problems.push(format!("{}: {}", field, e)),
+        },
+        (Some(_), None) => problems.push(format!("{}t is missing", field)),
+        _ => {}
+    }
+    problems
+}
+
+impl Name {
+    /// Check the standardised names against the primary names, e.g. `MUELLER` for `Müller`
+    pub fn transliteration_problems(&self) -> Vec<String> {
+        let mut problems = check_pair(
+            "fn",
+            self.family_name.as_deref(),
+            Some(&self.standardised_family_name),
+        );
+        problems.extend(check_pair(
+            "gn",
+            self.given_name.as_deref(),
+            self.standardised_given_name.as_deref(),
+        ));
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transliterate() {
+        assert_eq!(transliterate("Müller").unwrap(), "MUELLER");
+        assert_eq!(transliterate("François-Joan").unwrap(), "FRANCOIS<JOAN");
+        assert_eq!(
+            transliterate("d'Arsøns - van Halen").unwrap(),
+            "DARSOENS<VAN<HALEN"
+        );
+        assert_eq!(transliterate("Straße").unwrap(), "STRASSE");
+        assert_eq!(transliterate("Гончаров").unwrap(), "GONCHAROV");
+        assert!(transliterate("王").is_err());
+
+        assert!(matches("d'Arsøns - van Halen", "DARSONS<VAN<HALEN").unwrap());
+        assert!(matches("Müller", "MUELLER").unwrap());
+        assert!(matches("Müller", "MUXXLLER").unwrap());
+        assert!(matches("Mäller", "MAXXLLER").unwrap());
+        assert!(matches("Möller", "MOXXLLER").unwrap());
+        assert!(!matches("Møller", "MOXXLLER").unwrap());
+        assert!(!matches("Müller", "MULLLER").unwrap());
+        assert!(matches("Frančois-Joan", "FRANCOIS<JOAN").unwrap());
+        assert!(!matches("Frančois-Joan", "FRANCOISJOAN").unwrap());
+    }
+
+    #[test]
+    fn test_name_problems() {
+        let mut name = Name {
+            family_name: Some("Müller".to_string()),
+            standardised_family_name: "MUELLER".to_string(),
+            given_name: Some("Céline".to_string()),
+            standardised_given_name: Some("CELINE".to_string()),
+        };
+        assert!(name.transliteration_problems().is_empty());
+
+        name.standardised_given_name = Some("Celine".to_string());
+        let problems = name.transliteration_problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("gnt: Illegal characters"));
+
+        name.standardised_given_name = None;
+        name.standardised_family_name = "MILLER".to_string();
+        assert_eq!(
+            name.transliteration_problems(),
+            vec![
+                "fnt MILLER does not match Müller (expected MUELLER)".to_string(),
+                "gnt is missing".to_string()
+            ]
+        );
+    }
+}
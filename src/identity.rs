@@ -0,0 +1,242 @@
+//! Matching the holder of a certificate against identity data, e.g. as read from an ID document at a door check.
+//!
+//! The names are compared on their standardised form, so the identity data can be given either as printed
+//! (`Müller`) or in the MRZ form (`MUELLER`), c.f. [crate::icao::matches]. Dates of birth may be partial on
+//! either side, e.g. `1998-02` or `1998`, in which case only the known parts are compared.
+use crate::hcert::{DatePrecision, Hcert, PartialDate};
+use crate::icao;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The identity to compare the holder against
+pub struct Identity {
+    pub family_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    /// `YYYY-MM-DD`, `YYYY-MM` or `YYYY`
+    pub date_of_birth: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// How to compare the given names
+pub enum GivenNameMatch {
+    /// All given names have to match
+    Full,
+    /// Only the first given name has to match, e.g. `ANNA` for `ANNA<MARIA`
+    First,
+    /// The given names are not compared
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MatchPolicy {
+    pub given_name: GivenNameMatch,
+    /// Compare the names without fillers, e.g. `VANHALEN` matches `VAN<HALEN`
+    pub ignore_fillers: bool,
+    /// Accept a date of birth with unknown month or day if the known parts match. An empty date of birth
+    /// never matches.
+    pub allow_partial_date_of_birth: bool,
+}
+
+impl Default for MatchPolicy {
+    fn default() -> Self {
+        MatchPolicy {
+            given_name: GivenNameMatch::Full,
+            ignore_fillers: false,
+            allow_partial_date_of_birth: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The outcome of [Hcert::match_identity] with one explanation per compared field
+pub struct IdentityMatch {
+    pub matched: bool,
+    pub explanation: Vec<String>,
+}
+
+fn without_fillers(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(*c, ' ' | '-' | ',' | icao::FILLER))
+        .collect()
+}
+
+fn first_name(name: &str) -> &str {
+    name.split([' ', '-', ',', icao::FILLER])
+        .find(|part| !part.is_empty())
+        .unwrap_or("")
+}
+
+/// Compare a name of the identity with the standardised name of the holder
+fn match_name(
+    field: &str,
+    name: &str,
+    standardised: &str,
+    policy: &MatchPolicy,
+) -> Result<String, String> {
+    let (name, standardised) = if policy.ignore_fillers {
+        (without_fillers(name), without_fillers(standardised))
+    } else {
+        (name.to_string(), standardised.to_string())
+    };
+    match icao::matches(&name, &standardised) {
+        Ok(true) => Ok(format!("{}: {} matches {}", field, name, standardised)),
+        Ok(false) => Err(format!(
+            "{}: {} does not match {}",
+            field, name, standardised
+        )),
+        Err(e) => Err(format!("{}: {}", field, e)),
+    }
+}
+
+fn match_date_of_birth(
+    dob: &PartialDate,
+    identity: &str,
+    policy: &MatchPolicy,
+) -> Result<String, String> {
+    let field = "date of birth";
+    let expected = PartialDate::parse(identity).map_err(|e| format!("{}: {}", field, e))?;
+    if dob.precision() == DatePrecision::Unknown || expected.precision() == DatePrecision::Unknown {
+        return Err(format!("{}: unknown", field));
+    }
+    if !dob.overlaps(&expected) {
+        return Err(format!("{}: {} does not match {}", field, identity, dob));
+    }
+    if dob != &expected && !policy.allow_partial_date_of_birth {
+        return Err(format!(
+            "{}: {} only partially matches {}",
+            field, identity, dob
+        ));
+    }
+    Ok(format!("{}: {} matches {}", field, identity, dob))
+}
+
+impl Hcert {
+    /// Compare the holder with `identity` using the standardised names `fnt` and `gnt` and the date of birth
+    pub fn match_identity(&self, identity: &Identity, policy: &MatchPolicy) -> IdentityMatch {
+        let mut results = vec![match_name(
+            "family name",
+            &identity.family_name,
+            &self.nam.standardised_family_name,
+            policy,
+        )];
+        let given_names = (
+            identity.given_name.as_deref(),
+            self.nam.standardised_given_name.as_deref(),
+        );
+        results.push(match (policy.given_name, given_names) {
+            (GivenNameMatch::Ignore, _) => Ok("given name: not compared".to_string()),
+            (_, (None, None)) => Ok("given name: both missing".to_string()),
+            (GivenNameMatch::Full, (Some(name), Some(standardised))) => {
+                match_name("given name", name, standardised, policy)
+            }
+            (GivenNameMatch::First, (Some(name), Some(standardised))) => match_name(
+                "first given name",
+                first_name(name),
+                first_name(standardised),
+                policy,
+            ),
+            (_, (None, Some(_))) => Err("given name: missing in the identity".to_string()),
+            (_, (Some(_), None)) => Err("given name: missing in the certificate".to_string()),
+        });
+        results.push(match_date_of_birth(
+            &self.dob,
+            &identity.date_of_birth,
+            policy,
+        ));
+        IdentityMatch {
+            matched: results.iter().all(Result::is_ok),
+            explanation: results
+                .into_iter()
+                .map(|result| result.unwrap_or_else(|e| e))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hcert::Name;
+
+    fn hcert(dob: &str) -> Hcert {
+        Hcert {
+            ver: "1.0.0".to_string(),
+            nam: Name {
+                family_name: Some("d'Arsøns - van Halen".to_string()),
+                standardised_family_name: "DARSONS<VAN<HALEN".to_string(),
+                given_name: Some("François-Joan".to_string()),
+                standardised_given_name: Some("FRANCOIS<JOAN".to_string()),
+            },
+            dob: dob.parse().unwrap(),
+            v: None,
+            t: None,
+            r: None,
+        }
+    }
+
+    #[test]
+    fn test_match_identity() {
+        let identity = Identity {
+            family_name: "D'Arsøns van Halen".to_string(),
+            given_name: Some("FRANCOIS<JOAN".to_string()),
+            date_of_birth: "2009-02-28".to_string(),
+        };
+        let policy = MatchPolicy::default();
+        let result = hcert("2009-02-28").match_identity(&identity, &policy);
+        assert!(result.matched, "{:?}", result.explanation);
+        assert_eq!(result.explanation.len(), 3);
+        assert!(hcert("2009-02").match_identity(&identity, &policy).matched);
+        assert!(hcert("2009").match_identity(&identity, &policy).matched);
+
+        let result = hcert("2009-03").match_identity(&identity, &policy);
+        assert!(!result.matched);
+        assert_eq!(
+            result.explanation[2],
+            "date of birth: 2009-02-28 does not match 2009-03"
+        );
+        assert!(!hcert("").match_identity(&identity, &policy).matched);
+        let malformed = Identity {
+            date_of_birth: "28.02.2009".to_string(),
+            ..identity
+        };
+        assert!(
+            !hcert("2009-02-28")
+                .match_identity(&malformed, &policy)
+                .matched
+        );
+    }
+
+    #[test]
+    fn test_match_policy() {
+        let identity = Identity {
+            family_name: "DARSONS VANHALEN".to_string(),
+            given_name: Some("François".to_string()),
+            date_of_birth: "2009-02-28".to_string(),
+        };
+        let result = hcert("2009-02").match_identity(&identity, &MatchPolicy::default());
+        assert!(!result.matched);
+        assert_eq!(
+            result.explanation[1],
+            "given name: François does not match FRANCOIS<JOAN"
+        );
+
+        let policy = MatchPolicy {
+            given_name: GivenNameMatch::First,
+            ignore_fillers: true,
+            allow_partial_date_of_birth: true,
+        };
+        assert!(hcert("2009-02").match_identity(&identity, &policy).matched);
+        let strict = MatchPolicy {
+            allow_partial_date_of_birth: false,
+            ..policy
+        };
+        let result = hcert("2009-02").match_identity(&identity, &strict);
+        assert!(!result.matched);
+        assert!(
+            hcert("2009-02-28")
+                .match_identity(&identity, &strict)
+                .matched
+        );
+    }
+}
@@ -0,0 +1,467 @@
+//! Import and export of [VerificationKey]s and [SigningKey]s as JSON Web Keys ([RFC-7517](https://tools.ietf.org/html/rfc7517)).
+//!
+//! Supported are `EC` keys on P-256, P-384 and P-521 ([RFC-7518 Section-6.2](https://tools.ietf.org/html/rfc7518#section-6.2)),
+//! `RSA` keys ([RFC-7518 Section-6.3](https://tools.ietf.org/html/rfc7518#section-6.3)) and `OKP` keys on Ed25519
+//! ([RFC-8037 Section-2](https://tools.ietf.org/html/rfc8037#section-2)).
+//!
+//! All binary values are exported as base64url without padding as required by the RFC. On import we also accept
+//! the standard base64 alphabet with padding, as some trust lists (and our older tooling) use it.
+use simple_asn1::{ASN1Block, BigInt, BigUint};
+use x509_parser::num_bigint::Sign;
+use zeroize::Zeroizing;
+
+use crate::keys::{RsaPrivateKey, RsaPublicKey, RsaScheme};
+use crate::{SigningKey, VerificationKey};
+
+#[derive(Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+/// A single JSON Web Key. Unknown members are ignored on import. The private members are redacted when
+/// debug printing the key.
+pub struct Jwk {
+    pub kty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#use: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dq: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qi: Option<String>,
+}
+
+impl std::fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "<redacted>");
+        f.debug_struct("Jwk")
+            .field("kty", &self.kty)
+            .field("kid", &self.kid)
+            .field("alg", &self.alg)
+            .field("use", &self.r#use)
+            .field("crv", &self.crv)
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("n", &self.n)
+            .field("e", &self.e)
+            .field("d", &redacted(&self.d))
+            .field("p", &redacted(&self.p))
+            .field("q", &redacted(&self.q))
+            .field("dp", &redacted(&self.dp))
+            .field("dq", &redacted(&self.dq))
+            .field("qi", &redacted(&self.qi))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+/// A JWK Set as described in [RFC-7517 Section-5](https://tools.ietf.org/html/rfc7517#section-5)
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl Jwk {
+    pub fn from_json(json: &str) -> Result<Jwk, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// The `kid` as bytes. DCC trust lists carry the `kid` as base64 encoded bytes.
+    pub fn key_id(&self) -> Option<Vec<u8>> {
+        self.kid.as_ref().and_then(|kid| decode(kid).ok())
+    }
+
+    /// Set the `kid` to the base64 encoding of `kid`, as it is done in the DCC trust lists
+    pub fn set_key_id(&mut self, kid: &[u8]) {
+        self.kid = Some(base64::encode(kid));
+    }
+
+    fn member(
+        &self,
+        value: &Option<String>,
+        name: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let value = value
+            .as_ref()
+            .ok_or_else(|| format!("JWK member `{}` missing", name))?;
+        decode(value)
+    }
+
+    /// Check that the `alg` member (if present) matches the one we expect for the key type
+    fn check_alg(&self, allowed: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.alg {
+            Some(alg) if !allowed.contains(&alg.as_str()) => {
+                Err(format!("alg {} not allowed for this key", alg).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decode the curve point for the given coordinate length, padding coordinates with leading zeros
+    fn ec_coordinates(&self, len: usize) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+        let x = left_pad(self.member(&self.x, "x")?, len)?;
+        let y = left_pad(self.member(&self.y, "y")?, len)?;
+        Ok((x, y))
+    }
+}
+
+impl JwkSet {
+    pub fn from_json(json: &str) -> Result<JwkSet, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Find the key with the given `kid`
+    pub fn find(&self, kid: &[u8]) -> Option<&Jwk> {
+        self.keys
+            .iter()
+            .find(|key| key.key_id().as_deref() == Some(kid))
+    }
+
+    /// All keys of the set which can be used for verification, together with their `kid`.
+    /// Keys which cannot be imported are skipped.
+    pub fn verification_keys(&self) -> Vec<(Option<Vec<u8>>, VerificationKey)> {
+        self.keys
+            .iter()
+            .filter_map(|jwk| {
+                VerificationKey::from_jwk(jwk)
+                    .ok()
+                    .map(|key| (jwk.key_id(), key))
+            })
+            .collect()
+    }
+}
+
+impl VerificationKey {
+    /// Import the public part of a JWK
+    pub fn from_jwk(jwk: &Jwk) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("EC", Some("P-256")) => {
+                jwk.check_alg(&["ES256"])?;
+                let (x, y) = jwk.ec_coordinates(32)?;
+                VerificationKey::es256(&x, &y)
+            }
+            ("EC", Some("P-384")) => {
+                jwk.check_alg(&["ES384"])?;
+                let (x, y) = jwk.ec_coordinates(48)?;
+                VerificationKey::es384(&x, &y)
+            }
+            ("EC", Some("P-521")) => {
+                jwk.check_alg(&["ES512"])?;
+                let (x, y) = jwk.ec_coordinates(66)?;
+                VerificationKey::es512(&x, &y)
+            }
+            ("OKP", Some("Ed25519")) => {
+                jwk.check_alg(&["EdDSA"])?;
+                VerificationKey::ed_dsa(&jwk.member(&jwk.x, "x")?)
+            }
+            ("RSA", _) => {
+                jwk.check_alg(&["PS256", "PS384", "PS512", "RS256", "RS384", "RS512"])?;
+                Ok(VerificationKey::Rsa(RsaPublicKey::from_n_and_e(
+                    &jwk.member(&jwk.n, "n")?,
+                    &jwk.member(&jwk.e, "e")?,
+                )?))
+            }
+            (kty, crv) => Err(format!("Unsupported key type {} ({:?})", kty, crv).into()),
+        }
+    }
+
+    /// Export the key as JWK. `RSA` keys are exported without `alg`, as both `PS256` and `RS256` signatures can
+    /// be verified with them. The `kid` is derived from the key itself (c.f. [VerificationKey::kid]); overwrite it
+    /// with [Jwk::set_key_id] if the `kid` of the signer certificate should be used instead.
+    pub fn to_jwk(&self) -> Result<Jwk, Box<dyn std::error::Error>> {
+        let mut jwk = match self {
+            VerificationKey::Es256(_) => ec_jwk("P-256", "ES256", self)?,
+            VerificationKey::Es384(_) => ec_jwk("P-384", "ES384", self)?,
+            VerificationKey::Es512(_) => ec_jwk("P-521", "ES512", self)?,
+            VerificationKey::EdDsa(key) => Jwk {
+                kty: "OKP".to_string(),
+                alg: Some("EdDSA".to_string()),
+                crv: Some("Ed25519".to_string()),
+                x: Some(encode(key.as_bytes())),
+                ..Default::default()
+            },
+            VerificationKey::Rsa(key) => Jwk {
+                kty: "RSA".to_string(),
+                n: Some(encode(key.modulus())),
+                e: Some(encode(key.exponent())),
+                ..Default::default()
+            },
+        };
+        jwk.r#use = Some("sig".to_string());
+        jwk.set_key_id(&self.kid()?);
+        Ok(jwk)
+    }
+}
+
+impl SigningKey {
+    /// Import a private JWK. The public members are required as well and have to match the private key, the `alg`
+    /// is checked as in [VerificationKey::from_jwk]. `RSA` keys need all CRT parameters (`p`, `q`, `dp`, `dq`, `qi`)
+    /// and sign with the scheme given by `alg` (`PS256` if missing).
+    pub fn from_jwk(jwk: &Jwk) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        let d = Zeroizing::new(jwk.member(&jwk.d, "d")?);
+        let key = match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("EC", Some("P-256")) => SigningKey::es256(&Zeroizing::new(left_pad(d.to_vec(), 32)?))?,
+            ("EC", Some("P-384")) => SigningKey::es384(&Zeroizing::new(left_pad(d.to_vec(), 48)?))?,
+            ("EC", Some("P-521")) => SigningKey::es512(&Zeroizing::new(left_pad(d.to_vec(), 66)?))?,
+            ("OKP", Some("Ed25519")) => SigningKey::ed_dsa(&d)?,
+            ("RSA", _) => {
+                let mut parameters = vec![ASN1Block::Integer(0, BigInt::from(0))];
+                for (value, name) in [
+                    (&jwk.n, "n"),
+                    (&jwk.e, "e"),
+                    (&jwk.d, "d"),
+                    (&jwk.p, "p"),
+                    (&jwk.q, "q"),
+                    (&jwk.dp, "dp"),
+                    (&jwk.dq, "dq"),
+                    (&jwk.qi, "qi"),
+                ] {
+                    parameters.push(integer(&jwk.member(value, name)?));
+                }
+                let der = Zeroizing::new(simple_asn1::to_der(&ASN1Block::Sequence(0, parameters))?);
+                let scheme = match &jwk.alg {
+                    Some(alg) => RsaScheme::from_jose_name(alg)?,
+                    None => RsaScheme::default(),
+                };
+                SigningKey::Rsa(RsaPrivateKey::from_pkcs1_der(&der)?.with_scheme(scheme))
+            }
+            (kty, crv) => return Err(format!("Unsupported key type {} ({:?})", kty, crv).into()),
+        };
+        // make sure the public part matches the private key
+        let public = key.verification_key()?.to_jwk()?;
+        let expected = VerificationKey::from_jwk(jwk)?.to_jwk()?;
+        if (&public.x, &public.y, &public.n, &public.e)
+            != (&expected.x, &expected.y, &expected.n, &expected.e)
+        {
+            return Err("Public key does not match private key".into());
+        }
+        Ok(key)
+    }
+
+    /// Export the key as private JWK, including the public members. The `alg` is the one the key signs with.
+    pub fn to_jwk(&self) -> Result<Jwk, Box<dyn std::error::Error>> {
+        let mut jwk = self.verification_key()?.to_jwk()?;
+        match self {
+            SigningKey::Es256(key) => jwk.d = Some(encode(&key.to_bytes())),
+            SigningKey::Es384(key) => jwk.d = Some(encode(&key.to_bytes())),
+            SigningKey::Es512(key) => jwk.d = Some(encode(&key.to_bytes())),
+            SigningKey::EdDsa(key) => jwk.d = Some(encode(key.seed())),
+            SigningKey::Rsa(key) => {
+                jwk.alg = Some(key.scheme().jose_name().to_string());
+                set_rsa_private_members(&mut jwk, key.as_der())?
+            }
+        }
+        Ok(jwk)
+    }
+}
+
+fn ec_jwk(crv: &str, alg: &str, key: &VerificationKey) -> Result<Jwk, Box<dyn std::error::Error>> {
+    let (x, y) = key.ec_coordinates().ok_or("Not an EC key")?;
+    Ok(Jwk {
+        kty: "EC".to_string(),
+        alg: Some(alg.to_string()),
+        crv: Some(crv.to_string()),
+        x: Some(encode(&x)),
+        y: Some(encode(&y)),
+        ..Default::default()
+    })
+}
+
+/// Set the private members of an RSA JWK from a DER encoded `RSAPrivateKey` ([RFC-3447 Appendix A.1.2](https://tools.ietf.org/html/rfc3447#appendix-A.1.2))
+fn set_rsa_private_members(jwk: &mut Jwk, der: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let integers = rsa_integers(der)?;
+    // version, n, e, d, p, q, dp, dq, qi
+    if integers.len() < 9 {
+        return Err("Not an RSAPrivateKey".into());
+    }
+    jwk.d = Some(encode(&integers[3]));
+    jwk.p = Some(encode(&integers[4]));
+    jwk.q = Some(encode(&integers[5]));
+    jwk.dp = Some(encode(&integers[6]));
+    jwk.dq = Some(encode(&integers[7]));
+    jwk.qi = Some(encode(&integers[8]));
+    Ok(())
+}
+
+/// The unsigned big endian integers of a DER encoded RSA key sequence
+fn rsa_integers(der: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    match simple_asn1::from_der(der)?.first() {
+        Some(ASN1Block::Sequence(_, items)) => Ok(items
+            .iter()
+            .filter_map(|item| match item {
+                ASN1Block::Integer(_, i) => Some(i.to_bytes_be().1),
+                _ => None,
+            })
+            .collect()),
+        _ => Err("Not an ASN.1 sequence".into()),
+    }
+}
+
+fn integer(bytes: &[u8]) -> ASN1Block {
+    ASN1Block::Integer(
+        0,
+        BigInt::from_biguint(Sign::Plus, BigUint::from_bytes_be(bytes)),
+    )
+}
+
+fn left_pad(bytes: Vec<u8>, len: usize) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if bytes.len() > len {
+        return Err("Coordinate too long".into());
+    }
+    let mut padded = vec![0u8; len - bytes.len()];
+    padded.extend(bytes);
+    Ok(padded)
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decode base64url or standard base64, with or without padding
+fn decode(value: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let value = value
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_");
+    Ok(base64::decode_config(&value, base64::URL_SAFE_NO_PAD)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{RFC_TEST, RSA_PRIVATE_KEY};
+    use crate::{decode_hex, get_payload};
+
+    // RFC-8392 Appendix A.3
+    const EC_JWK: &str = r#"{"kty":"EC","crv":"P-256","x":"sywOkABvb9E_dtBv-0mLzYmQSXun_PtjCEWVohQh7o0","y":"KwPYs5nYL0FCQE2i4ISNZabYIlc0Jjo3Tm4BRfz6clU","d":"jpsQnnGQmL-YBIffH1136cLv6iR1NC8ZuWmu5uH7dFk"}"#;
+
+    #[test]
+    fn test_ec_round_trip() {
+        let jwk = Jwk::from_json(EC_JWK).unwrap();
+        let signing_key = SigningKey::from_jwk(&jwk).unwrap();
+        let exported = signing_key.to_jwk().unwrap();
+        assert_eq!(exported.x, jwk.x);
+        assert_eq!(exported.y, jwk.y);
+        assert_eq!(exported.d, jwk.d);
+        assert_eq!(exported.alg.as_deref(), Some("ES256"));
+        assert_eq!(exported.key_id(), Some(signing_key.kid().unwrap()));
+
+        let verification_key = VerificationKey::from_jwk(&jwk).unwrap();
+        let exported = verification_key.to_jwk().unwrap();
+        assert_eq!(exported.d, None);
+        assert_eq!(
+            VerificationKey::from_jwk(&exported).unwrap().kid().unwrap(),
+            verification_key.kid().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_alg_mismatch() {
+        let mut jwk = Jwk::from_json(EC_JWK).unwrap();
+        jwk.alg = Some("ES384".to_string());
+        assert!(VerificationKey::from_jwk(&jwk).is_err());
+        jwk.alg = Some("ES512".to_string());
+        assert!(SigningKey::from_jwk(&jwk).is_err());
+    }
+
+    #[test]
+    fn test_private_jwk_checks() {
+        let jwk = Jwk::from_json(EC_JWK).unwrap();
+        assert!(!format!("{:?}", jwk).contains(jwk.d.as_deref().unwrap()));
+
+        // the public key has to be present and has to match as a whole
+        let other = SigningKey::es256(&[3u8; 32]).unwrap().to_jwk().unwrap();
+        for (x, y) in [
+            (None, jwk.y.clone()),
+            (jwk.x.clone(), other.y.clone()),
+            (other.x, other.y),
+        ] {
+            let broken = Jwk {
+                x,
+                y,
+                ..jwk.clone()
+            };
+            assert!(SigningKey::from_jwk(&broken).is_err());
+        }
+    }
+
+    #[test]
+    fn test_jwks_sign_and_verify() {
+        let keys = [
+            SigningKey::es384(&[7u8; 48]).unwrap(),
+            SigningKey::es512(&[1u8; 66]).unwrap(),
+            SigningKey::ed_dsa(&[42u8; 32]).unwrap(),
+        ];
+        let jwks = JwkSet {
+            keys: keys.iter().map(|k| k.to_jwk().unwrap()).collect(),
+        };
+        let jwks = JwkSet::from_json(&jwks.to_json().unwrap()).unwrap();
+        for (private, public) in keys.iter().zip(&jwks.keys) {
+            let imported = SigningKey::from_jwk(public).unwrap();
+            assert_eq!(imported.kid().unwrap(), private.kid().unwrap());
+
+            let mut cwt = get_payload(&decode_hex(RFC_TEST).unwrap()).unwrap();
+            cwt.sign_with_kid(private).unwrap();
+            let cwt = get_payload(&cwt.to_cbor().unwrap()).unwrap();
+            let kid = cwt.key_id().unwrap();
+            let key = VerificationKey::from_jwk(jwks.find(&kid).unwrap()).unwrap();
+            assert!(cwt.verify(&key).is_ok());
+        }
+        assert_eq!(jwks.verification_keys().len(), 3);
+    }
+
+    #[test]
+    fn test_rsa_round_trip() {
+        let rsa = "MIIBCgKCAQEAzFbQTlvm+IRJ/HwvIIW6xzye64rUMTBxaCUxD6EIzjDr6WkwzaLYpV5pgPPKsr5trTbN/NnaFSj9xeU8JM7AJRIvajvoJ7lRPWd7jIeQ9czGzTjwuo72mu3QzIu85y4tCDiopAVvApyzFUyuuU+JDdVIXHRBQKnMUndA6kVs9oJ5fuisZIrV4OCBsN94NNiomMaMeCD82g7Vo/O5P1KAfM2IHhTD6e7PrSCDERlHpnw8C1NIo0sWuHzA99go6vvkpJVm3zR3cJSX06r354g4w3JrPrnZOt+ikwYXpNXORQYn59VMezKjtKihKgaFy656Ezh8Zw+249ksPX3vhXjMMwIDAQAB";
+        let key = VerificationKey::from_pkcs1_der(&base64::decode(rsa).unwrap()).unwrap();
+        let jwk = key.to_jwk().unwrap();
+        assert_eq!(jwk.e.as_deref(), Some("AQAB"));
+        let imported = VerificationKey::from_jwk(&jwk).unwrap();
+        assert_eq!(imported.kid().unwrap(), key.kid().unwrap());
+
+        let private =
+            SigningKey::from_pkcs1_der(&base64::decode(RSA_PRIVATE_KEY).unwrap()).unwrap();
+        let private_jwk = private.to_jwk().unwrap();
+        assert_eq!(private_jwk.n, jwk.n);
+        assert_eq!(private_jwk.alg.as_deref(), Some("PS256"));
+        assert_eq!(jwk.alg, None);
+        let imported = SigningKey::from_jwk(&private_jwk).unwrap();
+        assert_eq!(imported.kid().unwrap(), key.kid().unwrap());
+
+        // the signing scheme survives the round trip
+        let private = private
+            .with_algorithm(crate::signer::algorithm::RS256)
+            .unwrap();
+        let private_jwk = private.to_jwk().unwrap();
+        assert_eq!(private_jwk.alg.as_deref(), Some("RS256"));
+        let imported = SigningKey::from_jwk(&private_jwk).unwrap();
+        assert_eq!(
+            crate::signer::Signer::algorithm(&imported),
+            crate::signer::algorithm::RS256
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! Parsed and validated key material used by [VerificationKey](crate::VerificationKey) and [SigningKey](crate::SigningKey).
+//!
+//! The `EC` keys are directly represented by the key types of the `p256`, `p384` and `p521` crates, which check that the
+//! point is on the curve (resp. that the scalar is in range) on construction. `RSA` and Ed25519 keys are handled by `ring`,
+//! which has no prepared public key type of its own, so the types in this module validate the key once (Ed25519 points
+//! are decompressed with `curve25519-dalek`) and keep what `ring` expects.
+//!
+//! Private key bytes kept by this module are wiped on drop, the `EC` signing keys do the same on their own.
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ring::signature::{
+    Ed25519KeyPair, KeyPair, RsaEncoding, RsaKeyPair, RsaParameters, RsaPublicKeyComponents,
+};
+use simple_asn1::{ASN1Block, BigInt};
+use x509_parser::num_bigint::Sign;
+use zeroize::Zeroizing;
+
+use crate::signer::algorithm;
+
+/// Smallest RSA modulus (in bits) accepted for verification
+pub const RSA_MIN_BITS: u64 = 2048;
+/// Largest RSA modulus (in bits) accepted for verification
+pub const RSA_MAX_BITS: u64 = 8192;
+
+#[derive(Clone, Debug)]
+/// An RSA public key, stored as DER encoded `RSAPublicKey` ([RFC-3447 Appendix A.1.1](https://tools.ietf.org/html/rfc3447#appendix-A.1.1))
+/// together with the parsed modulus and exponent `ring` verifies with.
+pub struct RsaPublicKey {
+    der: Vec<u8>,
+    components: RsaPublicKeyComponents<Vec<u8>>,
+}
+
+impl PartialEq for RsaPublicKey {
+    fn eq(&self, other: &RsaPublicKey) -> bool {
+        self.der == other.der
+    }
+}
+
+impl Eq for RsaPublicKey {}
+
+impl RsaPublicKey {
+    /// Parse a DER encoded `RSAPublicKey`. The modulus must have between [RSA_MIN_BITS] and [RSA_MAX_BITS] bits
+    /// and the public exponent must be odd and greater than one.
+    pub fn from_pkcs1_der(der: &[u8]) -> Result<RsaPublicKey, Box<dyn std::error::Error>> {
+        let (n, e) = match simple_asn1::from_der(der)?.as_slice() {
+            [ASN1Block::Sequence(_, items)] => match items.as_slice() {
+                [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] => (n.clone(), e.clone()),
+                _ => return Err("Not an RSAPublicKey".into()),
+            },
+            _ => return Err("Not an RSAPublicKey".into()),
+        };
+        if n.sign() != Sign::Plus || e.sign() != Sign::Plus {
+            return Err("RSA integers must be positive".into());
+        }
+        if n.bits() < RSA_MIN_BITS || n.bits() > RSA_MAX_BITS {
+            return Err(format!("Unsupported RSA modulus size {}", n.bits()).into());
+        }
+        if e <= BigInt::from(1) || (&e % 2u8) != BigInt::from(1) {
+            return Err("Invalid RSA public exponent".into());
+        }
+        Ok(RsaPublicKey {
+            der: der.to_vec(),
+            components: RsaPublicKeyComponents {
+                n: n.to_bytes_be().1,
+                e: e.to_bytes_be().1,
+            },
+        })
+    }
+
+    /// Build the key from the unsigned big endian modulus `n` and exponent `e`
+    pub fn from_n_and_e(n: &[u8], e: &[u8]) -> Result<RsaPublicKey, Box<dyn std::error::Error>> {
+        let sequence = ASN1Block::Sequence(
+            0,
+            vec![
+                ASN1Block::Integer(0, BigInt::from_bytes_be(Sign::Plus, n)),
+                ASN1Block::Integer(0, BigInt::from_bytes_be(Sign::Plus, e)),
+            ],
+        );
+        RsaPublicKey::from_pkcs1_der(&simple_asn1::to_der(&sequence)?)
+    }
+
+    /// The DER encoded `RSAPublicKey`
+    pub fn as_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The modulus `n` as unsigned big endian integer
+    pub fn modulus(&self) -> &[u8] {
+        &self.components.n
+    }
+
+    /// The public exponent `e` as unsigned big endian integer
+    pub fn exponent(&self) -> &[u8] {
+        &self.components.e
+    }
+
+    /// Whether `signature` is a valid signature of `message` with `scheme`
+    pub fn verify(&self, scheme: RsaScheme, message: &[u8], signature: &[u8]) -> bool {
+        self.components
+            .verify(scheme.verification(), message, signature)
+            .is_ok()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The RSA signature scheme. COSE distinguishes them by the algorithm, so the same key can produce both.
+pub enum RsaScheme {
+    /// `RSASSA-PSS` with SHA-256 (`PS256`), the scheme required by the DCC specification
+    #[default]
+    Pss,
+    /// `RSASSA-PKCS1-v1_5` with SHA-256 (`RS256`, [RFC-8812 Section-2](https://tools.ietf.org/html/rfc8812#section-2))
+    Pkcs1,
+}
+
+impl RsaScheme {
+    /// The scheme of the COSE algorithm `alg`, `-37` for `PS256` and `-257` for `RS256`
+    pub fn from_algorithm(alg: i128) -> Result<RsaScheme, Box<dyn std::error::Error>> {
+        match alg {
+            algorithm::PS256 => Ok(RsaScheme::Pss),
+            algorithm::RS256 => Ok(RsaScheme::Pkcs1),
+            alg => Err(format!("Algorithm {} cannot be used with RSA keys", alg).into()),
+        }
+    }
+
+    /// The COSE algorithm identifier
+    pub fn algorithm(self) -> i128 {
+        match self {
+            RsaScheme::Pss => algorithm::PS256,
+            RsaScheme::Pkcs1 => algorithm::RS256,
+        }
+    }
+
+    /// The JOSE algorithm name as used in the `alg` member of a JWK
+    pub fn jose_name(self) -> &'static str {
+        match self {
+            RsaScheme::Pss => "PS256",
+            RsaScheme::Pkcs1 => "RS256",
+        }
+    }
+
+    /// The scheme of the JOSE algorithm name `alg`, c.f. [RsaScheme::jose_name]
+    pub fn from_jose_name(alg: &str) -> Result<RsaScheme, Box<dyn std::error::Error>> {
+        match alg {
+            "PS256" => Ok(RsaScheme::Pss),
+            "RS256" => Ok(RsaScheme::Pkcs1),
+            alg => Err(format!("alg {} not supported for RSA keys", alg).into()),
+        }
+    }
+
+    pub(crate) fn verification(self) -> &'static RsaParameters {
+        match self {
+            RsaScheme::Pss => &ring::signature::RSA_PSS_2048_8192_SHA256,
+            RsaScheme::Pkcs1 => &ring::signature::RSA_PKCS1_2048_8192_SHA256,
+        }
+    }
+
+    fn signing(self) -> &'static dyn RsaEncoding {
+        match self {
+            RsaScheme::Pss => &ring::signature::RSA_PSS_SHA256,
+            RsaScheme::Pkcs1 => &ring::signature::RSA_PKCS1_SHA256,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// An Ed25519 public key as described in [RFC-8032 Section-5.1.5](https://tools.ietf.org/html/rfc8032#section-5.1.5).
+/// The point is decoded on construction, `ring` only gets the encoded bytes on verification.
+pub struct Ed25519PublicKey([u8; 32]);
+
+impl Ed25519PublicKey {
+    /// Parse the encoded point, failing if it is not on the curve
+    pub fn from_bytes(bytes: &[u8]) -> Result<Ed25519PublicKey, Box<dyn std::error::Error>> {
+        let mut key = [0u8; 32];
+        if bytes.len() != key.len() {
+            return Err("Ed25519 public keys have 32 bytes".into());
+        }
+        key.copy_from_slice(bytes);
+        if CompressedEdwardsY(key).decompress().is_none() {
+            return Err("Not a point on Ed25519".into());
+        }
+        Ok(Ed25519PublicKey(key))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// An Ed25519 private key. We keep the seed, as `ring` does not allow to export it again.
+pub struct Ed25519PrivateKey {
+    seed: Zeroizing<[u8; 32]>,
+    key_pair: Ed25519KeyPair,
+}
+
+impl Ed25519PrivateKey {
+    /// Create the key from the 32 byte seed as described in [RFC-8032 Section-5.1.5](https://tools.ietf.org/html/rfc8032#section-5.1.5)
+    pub fn from_seed(seed: &[u8]) -> Result<Ed25519PrivateKey, Box<dyn std::error::Error>> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|e| format!("KeyRejected {:?}", e))?;
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        bytes.copy_from_slice(seed);
+        Ok(Ed25519PrivateKey {
+            seed: bytes,
+            key_pair,
+        })
+    }
+
+    pub fn seed(&self) -> &[u8] {
+        self.seed.as_ref()
+    }
+
+    pub fn key_pair(&self) -> &Ed25519KeyPair {
+        &self.key_pair
+    }
+
+    pub fn public_key(&self) -> Ed25519PublicKey {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(self.key_pair.public_key().as_ref());
+        Ed25519PublicKey(key)
+    }
+}
+
+impl std::fmt::Debug for Ed25519PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ed25519PrivateKey")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// An RSA private key, stored as DER encoded `RSAPrivateKey` ([RFC-3447 Appendix A.1.2](https://tools.ietf.org/html/rfc3447#appendix-A.1.2))
+/// together with the prepared `ring` key pair and the [RsaScheme] used for signing ([RsaScheme::Pss] by default).
+pub struct RsaPrivateKey {
+    der: Zeroizing<Vec<u8>>,
+    key_pair: RsaKeyPair,
+    scheme: RsaScheme,
+}
+
+impl RsaPrivateKey {
+    /// Parse a DER encoded `RSAPrivateKey`
+    pub fn from_pkcs1_der(der: &[u8]) -> Result<RsaPrivateKey, Box<dyn std::error::Error>> {
+        let key_pair = RsaKeyPair::from_der(der).map_err(|e| format!("KeyRejected {:?}", e))?;
+        Ok(RsaPrivateKey {
+            der: Zeroizing::new(der.to_vec()),
+            key_pair,
+            scheme: RsaScheme::default(),
+        })
+    }
+
+    /// Parse a DER encoded PKCS#8 `PrivateKeyInfo` containing an `RSAPrivateKey`
+    pub fn from_pkcs8_der(pkcs8: &[u8]) -> Result<RsaPrivateKey, Box<dyn std::error::Error>> {
+        let key_pair = RsaKeyPair::from_pkcs8(pkcs8).map_err(|e| format!("KeyRejected {:?}", e))?;
+        // PrivateKeyInfo ::= SEQUENCE { version, privateKeyAlgorithm, privateKey OCTET STRING }
+        let der = match simple_asn1::from_der(pkcs8)?.first() {
+            Some(ASN1Block::Sequence(_, items)) => match items.get(2) {
+                Some(ASN1Block::OctetString(_, der)) => Zeroizing::new(der.to_owned()),
+                _ => return Err("Not a PKCS8 container".into()),
+            },
+            _ => return Err("Not a PKCS8 container".into()),
+        };
+        Ok(RsaPrivateKey {
+            der,
+            key_pair,
+            scheme: RsaScheme::default(),
+        })
+    }
+
+    /// The DER encoded `RSAPrivateKey`
+    pub fn as_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    pub fn key_pair(&self) -> &RsaKeyPair {
+        &self.key_pair
+    }
+
+    pub fn scheme(&self) -> RsaScheme {
+        self.scheme
+    }
+
+    /// Sign with `scheme` instead of the default [RsaScheme::Pss]
+    pub fn with_scheme(mut self, scheme: RsaScheme) -> RsaPrivateKey {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Sign `message` with the [RsaScheme] of this key
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let rng = ring::rand::SystemRandom::new();
+        let mut signature = vec![0; self.key_pair.public_modulus_len()];
+        self.key_pair
+            .sign(self.scheme.signing(), &rng, message, &mut signature)
+            .map_err(|_| "Could not sign")?;
+        Ok(signature)
+    }
+
+    pub fn public_key(&self) -> Result<RsaPublicKey, Box<dyn std::error::Error>> {
+        RsaPublicKey::from_pkcs1_der(self.key_pair.public_key().as_ref())
+    }
+}
+
+impl std::fmt::Debug for RsaPrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RsaPrivateKey")
+            .field("public_key", &self.public_key().ok())
+            .field("scheme", &self.scheme)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rsa_public_key_validation() {
+        // 2048 bit modulus
+        let n = [0xffu8; 256];
+        assert!(RsaPublicKey::from_n_and_e(&n, &[1, 0, 1]).is_ok());
+        // too short
+        assert!(RsaPublicKey::from_n_and_e(&n[..128], &[1, 0, 1]).is_err());
+        // even exponent
+        assert!(RsaPublicKey::from_n_and_e(&n, &[1, 0, 0]).is_err());
+        assert!(RsaPublicKey::from_pkcs1_der(&[0x30, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_ed25519_public_key_validation() {
+        assert!(Ed25519PublicKey::from_bytes(&[0u8; 31]).is_err());
+        // y = 2 has no x on the curve
+        let mut y = [0u8; 32];
+        y[0] = 2;
+        assert!(Ed25519PublicKey::from_bytes(&y).is_err());
+        let key = Ed25519PrivateKey::from_seed(&[42u8; 32]).unwrap();
+        assert_eq!(
+            Ed25519PublicKey::from_bytes(key.public_key().as_bytes()).unwrap(),
+            key.public_key()
+        );
+    }
+
+    #[test]
+    fn test_ec_point_must_be_on_curve() {
+        let key = crate::SigningKey::es256(&[3u8; 32]).unwrap();
+        let (x, mut y) = key.verification_key().unwrap().ec_coordinates().unwrap();
+        assert!(crate::VerificationKey::es256(&x, &y).is_ok());
+        y[31] ^= 1;
+        assert!(crate::VerificationKey::es256(&x, &y).is_err());
+        assert!(crate::VerificationKey::es256(&x[1..], &y).is_err());
+    }
+
+    #[test]
+    fn test_invalid_hex_is_rejected() {
+        assert_eq!(crate::decode_hex("00ff").unwrap(), vec![0, 255]);
+        assert!(crate::decode_hex("0g").is_err());
+        assert!(crate::decode_hex("abc").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Key identifiers (`kid`) as used by the DCC trust lists.
+//!
+//! According to the [DCC technical specification Volume 1 Section 3.3.1](https://ec.europa.eu/health/sites/health/files/ehealth/docs/digital-green-certificates_v1_en.pdf)
+//! the `kid` is the first 8 bytes of the SHA-256 fingerprint of the DER encoded signer certificate.
+use ring::digest::{digest, SHA256};
+
+/// Length of a DCC `kid` in bytes
+pub const KID_LENGTH: usize = 8;
+
+/// Compute the `kid` of a DER encoded X.509 signer certificate.
+pub fn kid_from_certificate(der: &[u8]) -> Vec<u8> {
+    digest(&SHA256, der).as_ref()[..KID_LENGTH].to_vec()
+}
+
+/// Compute a `kid` for a key which has no certificate (e.g. keys generated for test certificates).
+/// The fingerprint is taken over the DER encoded [SubjectPublicKeyInfo](https://tools.ietf.org/html/rfc5280#section-4.1)
+/// of the key, as no certificate bytes exist to hash.
+pub fn kid_from_public_key(spki_der: &[u8]) -> Vec<u8> {
+    digest(&SHA256, spki_der).as_ref()[..KID_LENGTH].to_vec()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// Outcome of comparing the `kid` found in the COSE headers with the `kid` of the key which verified the signature
+pub enum KidCheck {
+    /// The `kid` in the headers matches the verifying key
+    Match,
+    /// The signature verified, but the headers announce a different `kid`
+    Mismatch { header: Vec<u8>, expected: Vec<u8> },
+    /// The signature verified, but neither protected nor unprotected headers contain a `kid`
+    Missing { expected: Vec<u8> },
+}
+
+impl KidCheck {
+    /// Compare the `kid` taken from the headers with the `expected` one
+    pub fn compare(header: Option<Vec<u8>>, expected: &[u8]) -> KidCheck {
+        match header {
+            Some(header) if header == expected => KidCheck::Match,
+            Some(header) => KidCheck::Mismatch {
+                header,
+                expected: expected.to_vec(),
+            },
+            None => KidCheck::Missing {
+                expected: expected.to_vec(),
+            },
+        }
+    }
+
+    pub fn is_match(&self) -> bool {
+        matches!(self, KidCheck::Match)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::CERT_SE;
+    use crate::{get_payload, CwtParsed, SigningKey, VerificationKey};
+    use serde_cbor::Value;
+
+    const BIT_NEW: &str = "d28444a1013824a1044843415554494f4e21590117a401624348041b00701cd2fa9578ff061a6094d9a9390103a101a4617681aa626369782630313a43483a324137413633363544444644343433454138303430413236413245303442413462636f62434862646e016264746a323032312d30342d3237626973781942756e646573616d742066c3bc7220476573756e6468656974626d61781f4d6f6465726e6120537769747a65726c616e6420476d62482c20426173656c626d7065363832363762736402627467693834303533393030366276706a3131313933343930303763646f626a313934332d30322d3031636e616da462666e674dc3bc6c6c657262676e6743c3a96c696e6563666e74674d75656c6c657263676e746643656c696e656376657265312e302e30581d43415554494f4e212054686973206973206a75737420612066616b6521";
+    const PRIVATE_KEY: &str = "48483aca9813ef5eb42f0b6b1d4f583efef07aa6eb12922fc60d8d453ab81e3e";
+
+    fn test_cwt() -> CwtParsed {
+        let mut cwt = get_payload(&crate::decode_hex(BIT_NEW).unwrap()).unwrap();
+        cwt.protected_headers
+            .insert(Value::Integer(1), Value::Integer(-7));
+        cwt
+    }
+
+    #[test]
+    fn test_kid_from_certificate() {
+        let cert = base64::decode(CERT_SE).unwrap();
+        let kid = kid_from_certificate(&cert);
+        assert_eq!(crate::to_byte_string!(&kid), "f1346a94143151b4");
+        let key = VerificationKey::from_certificate_der(&cert).unwrap();
+        assert!(matches!(key, VerificationKey::Es256(_)));
+        let spki = key.subject_public_key_info().unwrap();
+        assert_eq!(
+            crate::to_byte_string!(&spki[..26]),
+            "3059301306072a8648ce3d020106082a8648ce3d030107034200"
+        );
+        assert_eq!(spki.len(), 91);
+    }
+
+    #[test]
+    fn test_sign_sets_kid() {
+        let key = SigningKey::es256(&crate::decode_hex(PRIVATE_KEY).unwrap()).unwrap();
+        let mut cwt = test_cwt();
+        cwt.sign_with_kid(&key).unwrap();
+        let kid = key.kid().unwrap();
+        assert_eq!(cwt.key_id(), Some(kid.clone()));
+
+        let bytes = cwt.to_cbor().unwrap();
+        let cwt = get_payload(&bytes).unwrap();
+        let verification_key = key.verification_key().unwrap();
+        assert_eq!(verification_key.kid().unwrap(), kid);
+        assert_eq!(
+            cwt.verify_with_kid(&verification_key, &kid).unwrap(),
+            KidCheck::Match
+        );
+    }
+
+    #[test]
+    fn test_kid_mismatch_is_flagged() {
+        let key = SigningKey::es256(&crate::decode_hex(PRIVATE_KEY).unwrap()).unwrap();
+        let mut cwt = test_cwt();
+        cwt.set_key_id(&[0, 0, 1, 1, 2, 2, 3, 3]);
+        cwt.sign(&key).unwrap();
+        let bytes = cwt.to_cbor().unwrap();
+        let cwt = get_payload(&bytes).unwrap();
+
+        let verification_key = key.verification_key().unwrap();
+        let expected = verification_key.kid().unwrap();
+        assert_eq!(
+            cwt.verify_with_kid(&verification_key, &expected).unwrap(),
+            KidCheck::Mismatch {
+                header: vec![0, 0, 1, 1, 2, 2, 3, 3],
+                expected
+            }
+        );
+    }
+}
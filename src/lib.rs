@@ -1,8 +1,36 @@
 use std::collections::BTreeMap;
-use image::{DynamicImage, GenericImageView, GrayImage, Rgba, RgbaImage};
+use image::RgbaImage;
 use serde_cbor::Value;
+use headers::label;
 
+pub mod acceptance;
 pub mod base45;
+pub mod certlogic;
+pub mod claims;
+pub mod cose_sign;
+pub mod countersign;
+pub mod encrypt;
+#[cfg(test)]
+mod fixtures;
+pub mod hcert;
+pub mod headers;
+pub mod icao;
+pub mod identity;
+pub mod jwk;
+pub mod keys;
+pub mod kid;
+pub mod light;
+pub mod mac;
+pub mod pem;
+pub mod remote;
+pub mod report;
+pub mod revocation;
+pub mod revocation_store;
+pub mod rules;
+pub mod signer;
+pub mod uvci;
+pub mod valuesets;
+pub mod x5chain;
 const HCERT_KEY: i128 = -260;
 const HCERT_V1: i128 = 1;
 
@@ -11,40 +39,36 @@ pub fn decode_qr(img: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
     let image = image::load_from_memory(img)?.to_luma8();
     let mut img = rqrr::PreparedImage::prepare(image);
     let grids = img.detect_grids();
-    for g in grids {
-        let (meta, content) = g.decode()?;
-        println!("{:?}", meta);
-        return Ok (content);
-    }
-    return Err("No data".into());
+    let g = grids.first().ok_or("No data")?;
+    let (meta, content) = g.decode()?;
+    println!("{:?}", meta);
+    Ok(content)
 }
 pub fn decode_qr_from_buffer(width: u32, height: u32, data: Vec<u8>) -> Result<String, Box<dyn std::error::Error>> {
     let image = RgbaImage::from_vec(width, height, data).ok_or("Not rgba")?;
     let image = image::DynamicImage::ImageRgba8(image).to_luma8();
      let mut img = rqrr::PreparedImage::prepare(image);
     let grids = img.detect_grids();
-    for g in grids {
-        let (meta, content) = g.decode()?;
-        println!("{:?}", meta);
-        return Ok (content);
-    }
-    return Err("No data".into());
+    let g = grids.first().ok_or("No data")?;
+    let (meta, content) = g.decode()?;
+    println!("{:?}", meta);
+    Ok(content)
 }
 
 pub fn get_qr_meta(img: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
     let image = image::load_from_memory(img)?.to_luma8();
     let mut img = rqrr::PreparedImage::prepare(image);
     let grids = img.detect_grids();
-    for g in grids {
-        let (meta, content) = g.decode()?;
-        println!("{:?}", meta);
-        return Ok (format!("{:?}", meta));
-    }
-    return Err("No data".into());
+    let g = grids.first().ok_or("No data")?;
+    let (meta, _) = g.decode()?;
+    println!("{:?}", meta);
+    Ok(format!("{:?}", meta))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::fixtures::RFC_TEST;
+
     #[test]
     fn test_qrcode() {
         let mut origs = vec![];
@@ -73,13 +97,47 @@ mod tests {
              println!("Android: {}", android);
          }
     }
+
+    #[test]
+    fn test_external_aad() {
+        let key = super::SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = super::get_payload(&super::decode_hex(RFC_TEST).unwrap()).unwrap();
+        cwt.sign_with_aad(&key, b"bound").unwrap();
+        let verification_key = key.verification_key().unwrap();
+        assert!(cwt.verify(&verification_key).is_err());
+        assert!(cwt.verify_with_aad(&verification_key, b"other", None).is_err());
+        assert!(cwt.verify_with_aad(&verification_key, b"bound", None).is_ok());
+    }
+
+    #[test]
+    fn test_detached_payload() {
+        let key = super::SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = super::get_payload(&super::decode_hex(RFC_TEST).unwrap()).unwrap();
+        cwt.sign(&key).unwrap();
+        let payload = cwt.detach_payload().unwrap();
+        assert!(cwt.detach_payload().is_err());
+
+        let mut detached = super::get_payload(&cwt.to_cbor().unwrap()).unwrap();
+        assert!(detached.is_detached());
+        assert!(detached.message.is_empty());
+        let verification_key = key.verification_key().unwrap();
+        assert!(detached.verify(&verification_key).is_err());
+        assert!(detached.verify_with_aad(&verification_key, &[], Some(&payload)).is_ok());
+        assert!(detached.verify_with_aad(&verification_key, &[], Some(&payload[1..])).is_err());
+
+        detached.attach_payload(&payload).unwrap();
+        assert_eq!(detached.message, cwt.message);
+        assert!(detached.verify(&verification_key).is_ok());
+        // a detached payload must not replace the attached one
+        assert!(detached.verify_with_aad(&verification_key, &[], Some(&payload)).is_err());
+    }
 }
 
 pub fn get_meta(raw_payload: &[u8]) -> Result<MetaInfo, Box<dyn std::error::Error>> {
-    let value: serde_cbor::Value = serde_cbor::from_reader(&raw_payload[..])?;
+    let value: serde_cbor::Value = serde_cbor::from_reader(raw_payload)?;
     let value = match value {
-        Value::Array(inner) => inner,
-        _ => return Err("not an array".into()),
+        Value::Array(inner) if inner.len() == 4 => inner,
+        _ => return Err("not an array of four".into()),
     };
     let protected_headers = match &value[0] {
         Value::Bytes(b) => b,
@@ -113,10 +171,25 @@ pub fn get_meta(raw_payload: &[u8]) -> Result<MetaInfo, Box<dyn std::error::Erro
                 },
                 _ => return Err("Should be integer".into()),
             };
-            let key_id = m
+            // without a kid, the kid of an embedded signer certificate is used
+            let x5chain = m
+                .get(&Value::Integer(label::X5CHAIN))
+                .or(unprotected_headers.get(&Value::Integer(label::X5CHAIN)));
+            let signer_cert = match x5chain {
+                Some(Value::Bytes(cert)) => Some(cert),
+                Some(Value::Array(certs)) => match certs.first() {
+                    Some(Value::Bytes(cert)) => Some(cert),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let key_id = match m
                 .get(&Value::Integer(4))
                 .or(unprotected_headers.get(&Value::Integer(4)))
-                .ok_or_else(|| "KeyId not found")?;
+            {
+                Some(key_id) => key_id.clone(),
+                None => Value::Bytes(kid::kid_from_certificate(signer_cert.ok_or("KeyId not found")?)),
+            };
             let key_id = match key_id {
                 Value::Bytes(b) => {
                     base64::encode(b)
@@ -130,38 +203,15 @@ pub fn get_meta(raw_payload: &[u8]) -> Result<MetaInfo, Box<dyn std::error::Erro
         }
         _ => return Err("Protected headers is not a map".into()),
     };
-    let exp = message.get(&Value::Integer(4)).and_then(|f|{
-        match f {
-            Value::Integer(i) => {
-                Some(*i)
-            }
-            _ => Some(0)
-        }
-    }).unwrap_or(0);
-    let iat = message.get(&Value::Integer(6)).and_then(|f|{
-        match f {
-            Value::Integer(i) => {
-                Some(*i)
-            }
-            _ => Some(0)
-        }
-    }).unwrap_or(0);
-
-    let iss = message.get(&Value::Integer(1)).and_then(|f|{
-        match f {
-            Value::Text(i) => {
-                Some(i.to_owned())
-            }
-            _ => Some(String::new())
-        }
-    }).unwrap_or(String::new());
+    let (claims, claim_errors) = claims::CwtClaims::from_map_lenient(&message);
 
     Ok(MetaInfo {
         alg,
         key_id,
-        exp: exp as u64,
-        iat: iat as u64,
-        iss
+        exp: claims.exp.map(|date| date.timestamp() as u64).unwrap_or(0),
+        iat: claims.iat.map(|date| date.timestamp() as u64).unwrap_or(0),
+        iss: claims.iss.unwrap_or_default(),
+        claim_errors,
     })
 }
 
@@ -170,7 +220,7 @@ pub fn get_meta(raw_payload: &[u8]) -> Result<MetaInfo, Box<dyn std::error::Erro
 /// This means we have a CBOR-Array like this, where the `Headers` defines the Algorithm used and `payload` contains
 /// CWT's claims. In our case the `hcert` will be one of those claims.
 /// ## COSE Sign struct
-/// ```
+/// ```text
 /// COSE_Sign = [
 ///       Headers,
 ///       payload : bstr / nil,
@@ -178,12 +228,12 @@ pub fn get_meta(raw_payload: &[u8]) -> Result<MetaInfo, Box<dyn std::error::Erro
 ///   ]
 ///```
 /// ## COSE Protected Headers and Unprotected Headers
-/// ```
+/// ```text
 /// Headers = (
 ///       protected : empty_or_serialized_map,
 ///       unprotected : header_map
 ///   )
-
+///
 ///   header_map = {
 ///       Generic_Headers,
 ///       * label => values
@@ -191,12 +241,11 @@ pub fn get_meta(raw_payload: &[u8]) -> Result<MetaInfo, Box<dyn std::error::Erro
 ///
 ///   empty_or_serialized_map = bstr .cbor header_map / bstr .size 0
 /// ```
-
 pub fn get_payload(raw_payload: &[u8]) -> Result<CwtParsed, Box<dyn std::error::Error>> {
-    let value: serde_cbor::Value = serde_cbor::from_reader(&raw_payload[..])?;
+    let value: serde_cbor::Value = serde_cbor::from_reader(raw_payload)?;
     let value = match value {
-        Value::Array(inner) => inner,
-        _ => return Err("not an array".into()),
+        Value::Array(inner) if inner.len() == 4 => inner,
+        _ => return Err("not an array of four".into()),
     };
     let protected_headers = match &value[0] {
         Value::Bytes(b) => b,
@@ -206,19 +255,20 @@ pub fn get_payload(raw_payload: &[u8]) -> Result<CwtParsed, Box<dyn std::error::
         Value::Map(m) => m,
         _ => return Err("No unprotected headers".into()),
     };
-    let message = match &value[2] {
-        Value::Bytes(b) => b,
+    let message: BTreeMap<Value, Value> = match &value[2] {
+        Value::Bytes(b) => serde_cbor::from_reader(&b[..])?,
+        // detached payload, c.f. [CwtParsed::attach_payload]
+        Value::Null => BTreeMap::new(),
         _ => return Err("no message".into()),
     };
 
     let signature = match &value[3] {
         Value::Bytes(a) => a,
+        Value::Array(_) => return Err("COSE_Sign structure, use cose_sign::get_cose_sign".into()),
         _ => return Err("no signature".into()),
     };
     let protected_headers: BTreeMap<Value, Value>  = serde_cbor::from_reader(&protected_headers[..])?;
 
-    let message: BTreeMap<Value, Value> = serde_cbor::from_reader(&message[..])?;
-
     Ok(CwtParsed {
         protected_headers: protected_headers.clone(),
         protected_headers_original: value[0].clone(),
@@ -229,18 +279,6 @@ pub fn get_payload(raw_payload: &[u8]) -> Result<CwtParsed, Box<dyn std::error::
     })
 }
 
-#[macro_export]
-/// Simple macro to produce a Vec<u8> from a byte string.
-macro_rules! from_byte_string {
-    ($s:expr) => {
-        $s.as_bytes()
-            .windows(2)
-            .step_by(2)
-            .map(|b| u8::from_str_radix(std::str::from_utf8(b).unwrap(), 16).unwrap_or(0))
-            .collect::<Vec<_>>()
-    };
-}
-
 #[macro_export]
 /// Simple macro to produce a byte string from a Vec<u8>
 macro_rules! to_byte_string {
@@ -251,13 +289,33 @@ macro_rules! to_byte_string {
             .join("")
     };
 }
+
+/// Decode a hex string, invalid input is an error.
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("Hex string has odd length".into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(|| format!("Invalid hex at position {}", i).into())
+        })
+        .collect()
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct MetaInfo {
     alg: String,
     key_id: String,
     iss: String,
+    /// Seconds since the UNIX epoch, fractions are truncated. `0` if missing or wrongly typed.
     exp: u64,
     iat: u64,
+    /// The errors of wrongly typed claims, c.f. [claims::CwtClaims::from_map_lenient]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    claim_errors: Vec<String>,
 }
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 /// The parsed CWT struct
@@ -277,12 +335,9 @@ pub struct CwtParsed {
     /// The signature as a byte string. In the case of `ECDSA` this is just `r || s`
     pub signature: Vec<u8>,
 }
-use p256::ecdsa::{
-    signature::{Signer, Verifier},
-    Signature,
-};
+use p256::ecdsa::{signature::Verifier, Signature};
 use std::convert::TryFrom;
-use x509_parser::num_bigint::Sign;
+use simple_asn1::{ASN1Block, BigUint, OID};
 impl CwtParsed {
     /// Verify the signature present in the CWT with the given [VerificationKey]
     pub fn verify(&self, key: &VerificationKey) -> Result<(), Box<dyn std::error::Error>> {
@@ -290,187 +345,515 @@ impl CwtParsed {
             Ok(v) => v,
             _ => return Err("Could not decode".into()),
         };
-        let success = match key {
-            VerificationKey::Es256 { x, y } => {
-                // #[cfg(not(target = "wasm32"))]
-                let mut pk = Vec::with_capacity(65);
-                pk.push(0x04u8);
-                pk.extend(from_byte_string!(x));
-                pk.extend(from_byte_string!(y));
+        key.verify_signature_with_algorithm(
+            algorithm(&self.protected_headers),
+            &message,
+            &self.signature,
+        )
+    }
 
-                let point = p256::EncodedPoint::from_bytes(&pk)?;
+    /// Sign the CWT with the given [signer::Signer], e.g. a [SigningKey]. Overwrites the `siganture` field of `self`
+    /// and sets the `alg` of the protected headers to the one of the signer.
+    pub fn sign<S: signer::Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.sign_with_aad(signer, &[])
+    }
 
-                let key = p256::ecdsa::VerifyingKey::from_encoded_point(&point)?;
+    /// Set the `kid` derived from the public part of `key` in the protected headers and sign the CWT.
+    /// Use [CwtParsed::sign_with_certificate] if a signer certificate exists, as DCC `kid`s are derived from the certificate.
+    pub fn sign_with_kid<S: signer::Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let kid = signer.kid()?;
+        self.set_key_id(&kid);
+        self.sign(signer)
+    }
 
-                let sig_bytes = self.signature.as_slice();
-                key.verify(&message, &Signature::try_from(sig_bytes)?)
-                    .is_ok()
-            }
-            VerificationKey::Rsa { key } => {
-                let key = from_byte_string!(key);
-
-                let pk = ring::signature::UnparsedPublicKey::new(
-                    &ring::signature::RSA_PSS_2048_8192_SHA256,
-                    &key,
-                );
-                pk.verify(&message, &self.signature).is_ok()
-            }
-        };
-        if success {
-            return Ok(());
-        } else {
-            return Err("verification error".into());
-        }
+    /// Set the `kid` of the DER encoded signer certificate `cert` in the protected headers and sign the CWT.
+    pub fn sign_with_certificate<S: signer::Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        cert: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_key_id(&kid::kid_from_certificate(cert));
+        self.sign(signer)
     }
 
-    /// Sign the CWT with the given [SigningKey]. Overwrites the `siganture` field of `self`.
-    pub fn sign(&mut self, key: &SigningKey) -> Result<(), Box<dyn std::error::Error>> {
-        match key {
-            SigningKey::Es256 { d } => {
-                // .map_err(|e| format!("KeyRejected {:?}", e))?;
-                let bytes_to_sign = self.get_signing_bytes()?;
-                // let rng = ring::rand::SystemRandom::new();
-                // let signature = key_pair
-                //     .sign(&rng, &bytes_to_sign)
-                //     .map_err(|_| format!("Could not sign"))?;
-
-                let key = p256::ecdsa::SigningKey::from_bytes(&from_byte_string!(d))?;
-                let signature = key.sign(&bytes_to_sign);
-                println!("{:?}", signature.r().to_bytes().to_vec());
-                println!("{}", signature.r().to_bytes().to_vec().len());
-                println!("{:?}", signature.s().to_bytes().to_vec());
-                println!("{}", signature.s().to_bytes().to_vec().len());
-                self.signature = signature.as_ref().to_vec();
-                println!("{}", self.signature.len());
-            }
-            SigningKey::RsaPkcs8 { pkcs8 } => {
-                let rsa_keypair = ring::signature::RsaKeyPair::from_pkcs8(&pkcs8)
-                    .map_err(|e| format!("KeyRejected {:?}", e))?;
-                let bytes_to_sign = self.get_signing_bytes()?;
-                let rng = ring::rand::SystemRandom::new();
-                let mut signature = vec![0; rsa_keypair.public_modulus_len()];
-
-                rsa_keypair
-                    .sign(
-                        &ring::signature::RSA_PKCS1_SHA256,
-                        &rng,
-                        &bytes_to_sign,
-                        &mut signature,
-                    )
-                    .map_err(|_| format!("Could not sign"))?;
-                self.signature = signature;
-            }
-            SigningKey::RsaDer { der } => {
-                let rsa_keypair = ring::signature::RsaKeyPair::from_der(&der)
-                    .map_err(|e| format!("KeyRejected {:?}", e))?;
-                let bytes_to_sign = self.get_signing_bytes()?;
-                let rng = ring::rand::SystemRandom::new();
-                let mut signature = vec![0; rsa_keypair.public_modulus_len()];
-
-                rsa_keypair
-                    .sign(
-                        &ring::signature::RSA_PKCS1_SHA256,
-                        &rng,
-                        &bytes_to_sign,
-                        &mut signature,
-                    )
-                    .map_err(|_| format!("Could not sign"))?;
-                self.signature = signature;
-            }
-        }
-        Ok(())
+    /// Verify the signature with `key` and compare the `kid` present in the headers with `expected_kid`.
+    /// A failing signature is an error, whereas a mismatching `kid` is reported in the returned [kid::KidCheck].
+    pub fn verify_with_kid(
+        &self,
+        key: &VerificationKey,
+        expected_kid: &[u8],
+    ) -> Result<kid::KidCheck, Box<dyn std::error::Error>> {
+        self.verify(key)?;
+        Ok(kid::KidCheck::compare(self.key_id(), expected_kid))
+    }
+
+    /// Verify the signature with the public key of the DER encoded signer certificate `cert`
+    /// and check that the `kid` in the headers matches the one of the certificate.
+    pub fn verify_with_certificate(
+        &self,
+        cert: &[u8],
+    ) -> Result<kid::KidCheck, Box<dyn std::error::Error>> {
+        let key = VerificationKey::from_certificate_der(cert)?;
+        self.verify_with_kid(&key, &kid::kid_from_certificate(cert))
+    }
+
+    /// The `kid` of the CWT. According to [RFC-8152 Section-3.1](https://tools.ietf.org/html/rfc8152#section-3.1) it
+    /// should be in the protected headers, but we fall back to the unprotected headers, as some issuers put it there.
+    pub fn key_id(&self) -> Option<Vec<u8>> {
+        key_id(&self.protected_headers, &self.unprotected_headers)
     }
+
+    /// Put `kid` into the protected headers and remove it from the unprotected ones, so only one `kid` is present.
+    pub fn set_key_id(&mut self, kid: &[u8]) {
+        self.unprotected_headers.remove(&Value::Integer(label::KID));
+        self.protected_headers
+            .insert(Value::Integer(label::KID), Value::Bytes(kid.to_vec()));
+    }
+
     /// Get the CBOR canoncial form for the bytes to sign according to [RFC-8152# Section-4.4](https://tools.ietf.org/html/rfc8152#section-4.4)
     pub fn get_verification_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut verification = vec![];
-        verification.push(Value::Text("Signature1".to_string()));
-        verification.push(self.protected_headers_original.clone());
-        verification.push(Value::Bytes(vec![]));
-        verification.push(self.original.clone());
+        self.get_verification_bytes_with(&[], None)
+    }
+
+    /// Like [CwtParsed::get_verification_bytes], but with the application supplied `external_aad` and, for a
+    /// detached payload, the serialized claims `detached_payload`. Giving `detached_payload` for a CWT with attached
+    /// payload is an error, as it would verify a payload different from the one carried by the CWT.
+    pub fn get_verification_bytes_with(
+        &self,
+        external_aad: &[u8],
+        detached_payload: Option<&[u8]>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let payload = match (detached_payload, &self.original) {
+            (Some(payload), Value::Null) => Value::Bytes(payload.to_vec()),
+            (Some(_), _) => return Err("The payload is not detached".into()),
+            (None, Value::Null) => return Err("The payload is detached".into()),
+            (None, original) => original.clone(),
+        };
+        let verification = vec![
+            Value::Text("Signature1".to_string()),
+            self.protected_headers_original.clone(),
+            Value::Bytes(external_aad.to_vec()),
+            payload,
+        ];
 
         Ok(serde_cbor::to_vec(&verification)?)
     }
 
     pub fn get_signing_bytes(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut verification = vec![];
+        self.get_signing_bytes_with(&[])
+    }
+
+    /// Serialize the current headers and claims and get the `Sig_structure` with `external_aad`. This attaches
+    /// a detached payload again, use [CwtParsed::detach_payload] after signing.
+    pub fn get_signing_bytes_with(
+        &mut self,
+        external_aad: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         self.protected_headers_original = Value::Bytes(serde_cbor::to_vec(&self.protected_headers)?);
         self.original = Value::Bytes(serde_cbor::to_vec(&self.message)?);
+        self.get_verification_bytes_with(external_aad, None)
+    }
+
+    /// Sign the CWT binding the application supplied `external_aad`, c.f. [CwtParsed::sign]
+    pub fn sign_with_aad<S: signer::Signer + ?Sized>(
+        &mut self,
+        signer: &S,
+        external_aad: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.unprotected_headers
+            .remove(&Value::Integer(label::ALG));
+        self.protected_headers.insert(
+            Value::Integer(label::ALG),
+            Value::Integer(signer.algorithm()),
+        );
+        let bytes_to_sign = self.get_signing_bytes_with(external_aad)?;
+        self.signature = signer.sign(&bytes_to_sign)?;
+        Ok(())
+    }
 
-        verification.push(Value::Text("Signature1".to_string()));
-        verification.push(self.protected_headers_original.clone());
-        verification.push(Value::Bytes(vec![]));
-        verification.push(self.original.clone());
+    /// Verify the signature with `external_aad` and, if the payload is detached, the serialized claims
+    /// `detached_payload`, c.f. [CwtParsed::get_verification_bytes_with]
+    pub fn verify_with_aad(
+        &self,
+        key: &VerificationKey,
+        external_aad: &[u8],
+        detached_payload: Option<&[u8]>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let message = self.get_verification_bytes_with(external_aad, detached_payload)?;
+        key.verify_signature_with_algorithm(
+            algorithm(&self.protected_headers),
+            &message,
+            &self.signature,
+        )
+    }
 
-        Ok(serde_cbor::to_vec(&verification)?)
+    /// Whether the payload is detached, i.e. `nil` in the `COSE_Sign1`
+    pub fn is_detached(&self) -> bool {
+        self.original == Value::Null
+    }
+
+    /// Remove the payload from the encoded CWT and return its bytes, which have to be transported separately.
+    /// The claims stay available in `message`.
+    pub fn detach_payload(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match std::mem::replace(&mut self.original, Value::Null) {
+            Value::Bytes(payload) => Ok(payload),
+            Value::Null => Err("The payload is already detached".into()),
+            _ => Err("Invalid payload".into()),
+        }
+    }
+
+    /// Attach the separately transported `payload` again, parsing the claims into `message`
+    pub fn attach_payload(&mut self, payload: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.message = serde_cbor::from_reader(payload)?;
+        self.original = Value::Bytes(payload.to_vec());
+        Ok(())
     }
 
     pub fn to_cbor(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut verification = vec![];
-        verification.push(self.protected_headers_original.clone());
-        verification.push(serde_cbor::Value::Map(self.unprotected_headers.clone()));
-        verification.push(self.original.clone());
-        verification.push(Value::Bytes(self.signature.clone()));
+        let verification = vec![
+            self.protected_headers_original.clone(),
+            serde_cbor::Value::Map(self.unprotected_headers.clone()),
+            self.original.clone(),
+            Value::Bytes(self.signature.clone()),
+        ];
         Ok(serde_cbor::to_vec(&verification)?)
     }
     /// The `hcert` is part of the claims in the CWT. The `hcert` itself is a container for multiple different certificates (c.f [Section 2.6.4](https://ec.europa.eu/health/sites/health/files/ehealth/docs/digital-green-certificates_v3_en.pdf)). For the Version 1 of the `DGC` the claim key `1` is used (c.f. [Section 3.3.1](https://ec.europa.eu/health/sites/health/files/ehealth/docs/digital-green-certificates_v1_en.pdf))
     pub fn get_hcert(&self) -> Option<BTreeMap<Value, Value>> {
-        match self.message.get(&Value::Integer(HCERT_KEY)) {
-            std::option::Option::Some(Value::Map(hcert)) => {
-                match hcert.get(&Value::Integer(HCERT_V1)) {
-                    Some(Value::Bytes(hcert)) => serde_cbor::from_reader(&hcert[..]).ok(),
-                    Some(Value::Map(m)) => Some(m.to_owned()),
-                    _ => None,
-                }
-            }
+        hcert_from_claims(&self.message)
+    }
+}
+
+/// Extract the `hcert` from the CWT claims, c.f. [CwtParsed::get_hcert]
+fn hcert_from_claims(claims: &BTreeMap<Value, Value>) -> Option<BTreeMap<Value, Value>> {
+    match claims.get(&Value::Integer(HCERT_KEY)) {
+        std::option::Option::Some(Value::Map(hcert)) => match hcert.get(&Value::Integer(HCERT_V1)) {
+            Some(Value::Bytes(hcert)) => serde_cbor::from_reader(&hcert[..]).ok(),
+            Some(Value::Map(m)) => Some(m.to_owned()),
             _ => None,
-        }
+        },
+        _ => None,
+    }
+}
+
+/// The `kid` from the protected headers with fallback to the unprotected ones, c.f. [CwtParsed::key_id]
+pub(crate) fn key_id(
+    protected_headers: &BTreeMap<Value, Value>,
+    unprotected_headers: &BTreeMap<Value, Value>,
+) -> Option<Vec<u8>> {
+    match protected_headers
+        .get(&Value::Integer(label::KID))
+        .or_else(|| unprotected_headers.get(&Value::Integer(label::KID)))
+    {
+        Some(Value::Bytes(kid)) => Some(kid.to_owned()),
+        _ => None,
     }
 }
 
-/// Possible verification keys. Either `RSA` or `EC`
+/// The COSE algorithm announced in the protected headers
+pub(crate) fn algorithm(protected_headers: &BTreeMap<Value, Value>) -> Option<i128> {
+    match protected_headers.get(&Value::Integer(label::ALG)) {
+        Some(Value::Integer(alg)) => Some(*alg),
+        _ => None,
+    }
+}
+
+fn object_identifier(components: &[u64]) -> OID {
+    OID::new(components.iter().map(|c| BigUint::from(*c)).collect())
+}
+
+/// Possible verification keys. Either `RSA`, `EC` or Ed25519. The keys are validated on construction
+/// (e.g. `EC` points have to be on the curve), so verifying many signatures with the same key does not
+/// parse the key again.
+#[derive(Clone)]
 pub enum VerificationKey {
-    /// The elliptic curve key, where `x` and `y` are byte strings of the respective curve point
-    Es256 { x: String, y: String },
-    /// RSA key in the `ASN.1` encoding as described in [RFC-3447 Appendix A 1.1](https://tools.ietf.org/html/rfc3447#appendix-A.1.1).
-    /// Note that OpenSSL usually encodes keys as a [SubjectPublicKeyInfo](https://tools.ietf.org/html/rfc5280#section-4.1).
-    /// Visit the [ring Documentation](https://briansmith.org/rustdoc/ring/signature/index.html#signing-and-verifying-with-rsa-pkcs1-15-padding) for more information on extracting the correct form.
-    Rsa { key: String },
+    /// The elliptic curve key on P-256
+    Es256(p256::ecdsa::VerifyingKey),
+    /// RSA key, c.f. [keys::RsaPublicKey].
+    /// Note that OpenSSL usually encodes keys as a [SubjectPublicKeyInfo](https://tools.ietf.org/html/rfc5280#section-4.1),
+    /// use [VerificationKey::from_pem] or [VerificationKey::from_der] to load those.
+    Rsa(keys::RsaPublicKey),
+    /// The elliptic curve key on P-384
+    Es384(p384::ecdsa::VerifyingKey),
+    /// The elliptic curve key on P-521
+    Es512(p521::ecdsa::VerifyingKey),
+    /// The Ed25519 key
+    EdDsa(keys::Ed25519PublicKey),
+}
+
+/// Build the uncompressed SEC1 point `0x04 || x || y`, checking the coordinate length
+fn sec1_point(x: &[u8], y: &[u8], len: usize) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if x.len() != len || y.len() != len {
+        return Err(format!("Coordinates must have {} bytes", len).into());
+    }
+    let mut point = Vec::with_capacity(1 + 2 * len);
+    point.push(0x04u8);
+    point.extend(x);
+    point.extend(y);
+    Ok(point)
 }
 
 impl VerificationKey {
+    /// Build a P-256 key from its big endian coordinates `x` and `y`. Fails if the point is not on the curve.
+    pub fn es256(x: &[u8], y: &[u8]) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        let point = sec1_point(x, y, 32)?;
+        Ok(VerificationKey::Es256(
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)?,
+        ))
+    }
+
+    /// Build a P-384 key from its big endian coordinates `x` and `y`. Fails if the point is not on the curve.
+    pub fn es384(x: &[u8], y: &[u8]) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        let point = sec1_point(x, y, 48)?;
+        Ok(VerificationKey::Es384(
+            p384::ecdsa::VerifyingKey::from_sec1_bytes(&point)?,
+        ))
+    }
+
+    /// Build a P-521 key from its big endian coordinates `x` and `y`. Fails if the point is not on the curve.
+    pub fn es512(x: &[u8], y: &[u8]) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        let point = sec1_point(x, y, 66)?;
+        Ok(VerificationKey::Es512(
+            p521::ecdsa::VerifyingKey::from_sec1_bytes(&point)?,
+        ))
+    }
+
+    /// Build an Ed25519 key from the 32 byte public key
+    pub fn ed_dsa(x: &[u8]) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        Ok(VerificationKey::EdDsa(keys::Ed25519PublicKey::from_bytes(x)?))
+    }
+
     pub fn rsa_from_n_and_e(
         n: &str,
         e: &str,
     ) -> Result<VerificationKey, Box<dyn std::error::Error>> {
         let n = base64::decode(n)?;
         let e = base64::decode(e)?;
+        Ok(VerificationKey::Rsa(keys::RsaPublicKey::from_n_and_e(&n, &e)?))
+    }
 
-        let sequence: simple_asn1::ASN1Block = simple_asn1::ASN1Block::Sequence(
-            0,
+    /// Extract the public key of a DER encoded X.509 certificate. `EC` keys on P-256, P-384 and P-521, Ed25519 and `RSA` keys are supported.
+    pub fn from_certificate_der(
+        cert: &[u8],
+    ) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        let (_, cert) = x509_parser::parse_x509_certificate(cert)?;
+        VerificationKey::from_spki(&cert.tbs_certificate.subject_pki)
+    }
+
+    /// Verify the raw `signature` (`r || s` for `ECDSA`) over `message`. `RSA` signatures are expected to be
+    /// `PS256`, use [VerificationKey::verify_signature_with_algorithm] for COSE signatures.
+    pub fn verify_signature(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.verify_signature_with_algorithm(None, message, signature)
+    }
+
+    /// Verify the raw `signature` over `message`, e.g. a COSE `Sig_structure`, made with the COSE algorithm
+    /// `alg`. The algorithm selects the scheme of `RSA` signatures (c.f. [keys::RsaScheme]), without it `PS256`
+    /// is assumed. An `alg` of another key type is an error.
+    pub fn verify_signature_with_algorithm(
+        &self,
+        alg: Option<i128>,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let expected = match self {
+            VerificationKey::Es256(_) => Some(signer::algorithm::ES256),
+            VerificationKey::Es384(_) => Some(signer::algorithm::ES384),
+            VerificationKey::Es512(_) => Some(signer::algorithm::ES512),
+            VerificationKey::EdDsa(_) => Some(signer::algorithm::EDDSA),
+            // checked when selecting the scheme
+            VerificationKey::Rsa(_) => None,
+        };
+        if let (Some(alg), Some(expected)) = (alg, expected) {
+            if alg != expected {
+                return Err(format!("Algorithm {} does not match the key", alg).into());
+            }
+        }
+        let success = match self {
+            VerificationKey::Es256(key) => key
+                .verify(message, &Signature::try_from(signature)?)
+                .is_ok(),
+            VerificationKey::Rsa(key) => {
+                let scheme = match alg {
+                    Some(alg) => keys::RsaScheme::from_algorithm(alg)?,
+                    None => keys::RsaScheme::default(),
+                };
+                key.verify(scheme, message, signature)
+            }
+            VerificationKey::Es384(key) => {
+                use p384::ecdsa::signature::Verifier;
+                let signature = p384::ecdsa::Signature::from_slice(signature)?;
+                key.verify(message, &signature).is_ok()
+            }
+            VerificationKey::Es512(key) => {
+                use p521::ecdsa::signature::Verifier;
+                let signature = p521::ecdsa::Signature::from_slice(signature)?;
+                key.verify(message, &signature).is_ok()
+            }
+            VerificationKey::EdDsa(key) => {
+                ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, key.as_bytes())
+                    .verify(message, signature)
+                    .is_ok()
+            }
+        };
+        if success {
+            Ok(())
+        } else {
+            Err("verification error".into())
+        }
+    }
+
+    /// The big endian coordinates `(x, y)` of `EC` keys
+    pub fn ec_coordinates(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let point = match self {
+            VerificationKey::Es256(key) => key.to_encoded_point(false).as_bytes().to_vec(),
+            VerificationKey::Es384(key) => key.to_encoded_point(false).as_bytes().to_vec(),
+            VerificationKey::Es512(key) => key.to_encoded_point(false).as_bytes().to_vec(),
+            _ => return None,
+        };
+        let (x, y) = point[1..].split_at((point.len() - 1) / 2);
+        Some((x.to_vec(), y.to_vec()))
+    }
+
+    /// The DER encoded [SubjectPublicKeyInfo](https://tools.ietf.org/html/rfc5280#section-4.1) of this key
+    pub fn subject_public_key_info(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let ec_algorithm = |curve: &[u64]| {
             vec![
-                simple_asn1::ASN1Block::Integer(
-                    0,
-                    simple_asn1::BigInt::from_bytes_be(Sign::Plus, &n),
-                ),
-                simple_asn1::ASN1Block::Integer(
+                ASN1Block::ObjectIdentifier(0, object_identifier(&[1, 2, 840, 10045, 2, 1])),
+                ASN1Block::ObjectIdentifier(0, object_identifier(curve)),
+            ]
+        };
+        let (algorithm, key) = match self {
+            VerificationKey::Es256(key) => (
+                ec_algorithm(&[1, 2, 840, 10045, 3, 1, 7]),
+                key.to_encoded_point(false).as_bytes().to_vec(),
+            ),
+            VerificationKey::Es384(key) => (
+                ec_algorithm(&[1, 3, 132, 0, 34]),
+                key.to_encoded_point(false).as_bytes().to_vec(),
+            ),
+            VerificationKey::Es512(key) => (
+                ec_algorithm(&[1, 3, 132, 0, 35]),
+                key.to_encoded_point(false).as_bytes().to_vec(),
+            ),
+            VerificationKey::Rsa(key) => (
+                vec![
+                    ASN1Block::ObjectIdentifier(
+                        0,
+                        object_identifier(&[1, 2, 840, 113549, 1, 1, 1]),
+                    ),
+                    ASN1Block::Null(0),
+                ],
+                key.as_der().to_vec(),
+            ),
+            VerificationKey::EdDsa(key) => (
+                vec![ASN1Block::ObjectIdentifier(
                     0,
-                    simple_asn1::BigInt::from_bytes_be(Sign::Plus, &e),
-                ),
+                    object_identifier(&[1, 3, 101, 112]),
+                )],
+                key.as_bytes().to_vec(),
+            ),
+        };
+        let spki = ASN1Block::Sequence(
+            0,
+            vec![
+                ASN1Block::Sequence(0, algorithm),
+                ASN1Block::BitString(0, key.len() * 8, key),
             ],
         );
-        let key = simple_asn1::to_der(&sequence)?;
-        Ok(VerificationKey::Rsa {
-            key: to_byte_string!(key),
-        })
+        Ok(simple_asn1::to_der(&spki)?)
+    }
+
+    /// The `kid` of this key if no signer certificate is available, c.f. [kid::kid_from_public_key]
+    pub fn kid(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(kid::kid_from_public_key(&self.subject_public_key_info()?))
     }
 }
 
-/// Possible SigningKeys. We allow `RSA` or `EC`.
+/// Possible SigningKeys. We allow `RSA`, `EC` or Ed25519. As with [VerificationKey] the keys are validated on construction.
+/// The private key material is wiped when the key is dropped and never part of the `Debug` output.
 pub enum SigningKey {
-    /// The `EC` private key
-    Es256 { d: String },
-    /// The RSA-Private key in the der format in a PKCS8 container.
-    RsaPkcs8 { pkcs8: Vec<u8> },
-    /// The RSA-Private key in the der format without a ccontainer.
-    RsaDer { der: Vec<u8> },
+    /// The `EC` private key on P-256
+    Es256(p256::ecdsa::SigningKey),
+    /// The `EC` private key on P-384
+    Es384(p384::ecdsa::SigningKey),
+    /// The `EC` private key on P-521
+    Es512(p521::ecdsa::SigningKey),
+    /// The Ed25519 private key
+    EdDsa(keys::Ed25519PrivateKey),
+    /// The RSA private key
+    Rsa(keys::RsaPrivateKey),
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SigningKey::Es256(_) => "Es256",
+            SigningKey::Es384(_) => "Es384",
+            SigningKey::Es512(_) => "Es512",
+            SigningKey::EdDsa(_) => "EdDsa",
+            SigningKey::Rsa(_) => "Rsa",
+        };
+        let kid = self.kid().map(|kid| to_byte_string!(kid)).unwrap_or_default();
+        f.debug_struct(name).field("kid", &kid).finish()
+    }
+}
+
+impl SigningKey {
+    /// Build a P-256 key from the big endian scalar `d`
+    pub fn es256(d: &[u8]) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        Ok(SigningKey::Es256(p256::ecdsa::SigningKey::from_slice(d)?))
+    }
+
+    /// Build a P-384 key from the big endian scalar `d`
+    pub fn es384(d: &[u8]) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        Ok(SigningKey::Es384(p384::ecdsa::SigningKey::from_slice(d)?))
+    }
+
+    /// Build a P-521 key from the big endian scalar `d`
+    pub fn es512(d: &[u8]) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        Ok(SigningKey::Es512(p521::ecdsa::SigningKey::from_slice(d)?))
+    }
+
+    /// Build an Ed25519 key from the 32 byte seed
+    pub fn ed_dsa(seed: &[u8]) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        Ok(SigningKey::EdDsa(keys::Ed25519PrivateKey::from_seed(seed)?))
+    }
+
+    /// Derive the public [VerificationKey] belonging to this private key
+    pub fn verification_key(&self) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        Ok(match self {
+            SigningKey::Es256(key) => VerificationKey::Es256(p256::ecdsa::VerifyingKey::from(key)),
+            SigningKey::Es384(key) => VerificationKey::Es384(*key.verifying_key()),
+            SigningKey::Es512(key) => VerificationKey::Es512(p521::ecdsa::VerifyingKey::from(key)),
+            SigningKey::EdDsa(key) => VerificationKey::EdDsa(key.public_key()),
+            SigningKey::Rsa(key) => VerificationKey::Rsa(key.public_key()?),
+        })
+    }
+
+    /// The `kid` of the public part of this key, c.f. [VerificationKey::kid]
+    pub fn kid(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.verification_key()?.kid()
+    }
+
+    /// Sign with the COSE algorithm `alg`. Only `RSA` keys can sign with more than one algorithm (`PS256` and
+    /// `RS256`), the other keys just check that `alg` is their own.
+    pub fn with_algorithm(self, alg: i128) -> Result<SigningKey, Box<dyn std::error::Error>> {
+        match self {
+            SigningKey::Rsa(key) => Ok(SigningKey::Rsa(
+                key.with_scheme(keys::RsaScheme::from_algorithm(alg)?),
+            )),
+            key if signer::Signer::algorithm(&key) == alg => Ok(key),
+            _ => Err(format!("Algorithm {} does not match the key", alg).into()),
+        }
+    }
 }
@@ -0,0 +1,358 @@
+//! Light certificates, which only carry the holder of a verified certificate (name and date of birth) and are
+//! valid for a short time, e.g. for domestic use.
+//!
+//! The light certificate is a CWT with `iss`, `iat`, `exp` and the claim `-250`
+//!
+//! ```text
+//! { "ver": "1.0.0", "nam": { "fn": ..., "fnt": ..., "gn": ..., "gnt": ... }, "dob": ... }
+//! ```
+//!
+//! and is encoded like the `hcert`, but with the prefix `LT1:`. [issue] creates light certificates, [verify]
+//! checks them against the keys of the light certificate issuer.
+use std::io::Write;
+
+use serde_cbor::Value;
+
+use crate::claims::{claim, CwtClaims, NumericDate};
+use crate::hcert::{Hcert, Name, PartialDate};
+use crate::headers::Label;
+use crate::jwk::JwkSet;
+use crate::report::{self, Step, Verdict, VerificationContext};
+use crate::signer::Signer;
+use crate::{get_payload, icao, CwtParsed, VerificationKey};
+
+/// Prefix of the QR code content of a light certificate
+pub const LT1_PREFIX: &str = "LT1:";
+/// The version of the light certificate
+pub const VERSION: &str = "1.0.0";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The content of the light certificate claim
+pub struct LightCert {
+    pub ver: String,
+    pub nam: Name,
+    #[serde(deserialize_with = "crate::hcert::date_of_birth")]
+    pub dob: PartialDate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// How [issue] derives the light certificate
+pub struct LightCertOptions {
+    /// Seconds the light certificate is valid after issuance, `None` to leave out `exp`
+    pub validity: Option<i64>,
+    /// The claim key of the light certificate
+    pub claim_key: i128,
+    /// Prefix of the QR code content
+    pub prefix: String,
+}
+
+impl Default for LightCertOptions {
+    fn default() -> Self {
+        LightCertOptions {
+            validity: Some(48 * 3600),
+            claim_key: claim::LIGHT_CERT,
+            prefix: LT1_PREFIX.to_string(),
+        }
+    }
+}
+
+impl LightCert {
+    /// The holder of `hcert`. A missing `gnt` is derived from `gn` if it can be transliterated.
+    pub fn from_hcert(hcert: &Hcert) -> LightCert {
+        let mut nam = hcert.nam.clone();
+        if nam.standardised_given_name.is_none() {
+            nam.standardised_given_name = nam
+                .given_name
+                .as_deref()
+                .and_then(|gn| icao::transliterate(gn).ok())
+                .filter(|gnt| !gnt.is_empty());
+        }
+        LightCert {
+            ver: VERSION.to_string(),
+            nam,
+            dob: hcert.dob.clone(),
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<LightCert, Box<dyn std::error::Error>> {
+        Ok(serde_cbor::value::from_value(value.clone())?)
+    }
+
+    pub fn to_value(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        Ok(serde_cbor::value::to_value(self)?)
+    }
+}
+
+/// Replace the claims of the verified `source` with the light certificate issued at `time`. The issuer is
+/// kept, the signature has to be renewed.
+pub fn derive(
+    mut source: CwtParsed,
+    time: i64,
+    options: &LightCertOptions,
+) -> Result<CwtParsed, Box<dyn std::error::Error>> {
+    let light_cert = LightCert::from_hcert(&source.hcert()?);
+    let claims = CwtClaims {
+        iss: source.claims()?.iss,
+        iat: Some(NumericDate::Integer(time)),
+        exp: options
+            .validity
+            .map(|validity| NumericDate::Integer(time + validity)),
+        ..CwtClaims::default()
+    };
+    let mut message = claims.to_map();
+    message.insert(Value::Integer(options.claim_key), light_cert.to_value()?);
+    source.message = message;
+    Ok(source)
+}
+
+/// zlib compress the `COSE_Sign1` and base45 encode it with `prefix`
+pub fn encode_qr_content(cose: &[u8], prefix: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut compressed = vec![];
+    let mut encoder =
+        flate2::write::ZlibEncoder::new(&mut compressed, flate2::Compression::default());
+    encoder.write_all(cose)?;
+    encoder.finish()?;
+    Ok(format!("{}{}", prefix, crate::base45::encode(&compressed)))
+}
+
+/// Verify the QR code `content` of a certificate with `context` and issue a light certificate for its holder,
+/// signed by `signer` at `context.time`. Returns the QR code content of the light certificate.
+pub fn issue<S: Signer + ?Sized>(
+    content: &str,
+    context: &VerificationContext,
+    options: &LightCertOptions,
+    signer: &S,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let verification = report::verify_qr(content, context);
+    if verification.has_failures()
+        || verification.get(Step::Signature).map(|check| check.verdict) != Some(Verdict::Passed)
+    {
+        let problems: Vec<String> = verification
+            .checks
+            .iter()
+            .filter(|(_, check)| check.verdict == Verdict::Failed)
+            .flat_map(|(step, check)| {
+                check
+                    .details
+                    .iter()
+                    .map(move |detail| format!("{:?}: {}", step, detail))
+            })
+            .collect();
+        return Err(format!(
+            "The certificate is not valid: {}",
+            if problems.is_empty() {
+                "signature not checked".to_string()
+            } else {
+                problems.join(", ")
+            }
+        )
+        .into());
+    }
+    let (cose, _) = report::decode_qr_content(content, report::HC1_PREFIX)?;
+    let mut cwt = derive(get_payload(&cose)?, context.time, options)?;
+    let mut headers = cwt.headers()?;
+    headers.protected.alg = Some(Label::Int(signer.algorithm()));
+    cwt.set_headers(&headers);
+    cwt.sign_with_kid(signer)?;
+    encode_qr_content(&cwt.to_cbor()?, &options.prefix)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A light certificate with a valid signature, as returned by [verify]
+pub struct VerifiedLightCert {
+    pub light_cert: LightCert,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// Seconds since the UNIX epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    /// Seconds since the UNIX epoch, `None` if the light certificate does not expire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+}
+
+impl CwtParsed {
+    /// Decode the light certificate found under `claim_key`, c.f. [CwtParsed::hcert] for the `hcert`
+    pub fn light_cert_with_key(
+        &self,
+        claim_key: i128,
+    ) -> Result<LightCert, Box<dyn std::error::Error>> {
+        LightCert::from_value(
+            self.message
+                .get(&Value::Integer(claim_key))
+                .ok_or("No light certificate")?,
+        )
+    }
+
+    /// Decode the light certificate (claim `-250`)
+    pub fn light_cert(&self) -> Result<LightCert, Box<dyn std::error::Error>> {
+        self.light_cert_with_key(claim::LIGHT_CERT)
+    }
+}
+
+/// Strip `prefix` from the QR code content, decode base45 and decompress, the inverse of [encode_qr_content]
+pub fn decode_qr_content(
+    content: &str,
+    prefix: &str,
+) -> Result<CwtParsed, Box<dyn std::error::Error>> {
+    if !content.trim().starts_with(prefix) {
+        return Err(format!("Expected the prefix {}", prefix).into());
+    }
+    let (cose, _) = report::decode_qr_content(content, prefix)?;
+    get_payload(&cose)
+}
+
+/// Decode the QR code `content` of a light certificate and verify it with the key of `keys` matching its `kid`
+/// at `time` (seconds since the UNIX epoch). The prefix and claim key are taken from `options`.
+pub fn verify(
+    content: &str,
+    keys: &JwkSet,
+    time: i64,
+    options: &LightCertOptions,
+) -> Result<VerifiedLightCert, Box<dyn std::error::Error>> {
+    let cwt = decode_qr_content(content, &options.prefix)?;
+    let kid = cwt.key_id().ok_or("No kid")?;
+    let key = keys.find(&kid).ok_or_else(|| {
+        format!(
+            "kid {} is not a light certificate key",
+            base64::encode(&kid)
+        )
+    })?;
+    cwt.verify(&VerificationKey::from_jwk(key)?)?;
+    let claims = cwt.claims()?;
+    let exp = claims.exp.as_ref().map(NumericDate::timestamp);
+    let iat = claims.iat.as_ref().map(NumericDate::timestamp);
+    if exp.is_some_and(|exp| exp <= time) {
+        return Err("Expired".into());
+    }
+    if iat.is_some_and(|iat| iat > time) {
+        return Err("Issued in the future".into());
+    }
+    Ok(VerifiedLightCert {
+        light_cert: cwt.light_cert_with_key(options.claim_key)?,
+        iss: claims.iss,
+        iat,
+        exp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::report::HC1_PREFIX;
+    use crate::SigningKey;
+
+    /// The SE certificate with the `hcert` modified by `edit`
+    fn source(edit: impl FnOnce(&mut Hcert)) -> CwtParsed {
+        let mut cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let mut hcert = cwt.hcert().unwrap();
+        edit(&mut hcert);
+        let mut claims = cwt.claims().unwrap();
+        let mut container = std::collections::BTreeMap::new();
+        container.insert(
+            Value::Integer(crate::HCERT_V1),
+            Value::Map(hcert.to_map().unwrap()),
+        );
+        claims.hcert = Some(container);
+        cwt.set_claims(&claims);
+        cwt
+    }
+
+    #[test]
+    fn test_derive() {
+        let cwt = source(|hcert| hcert.nam.standardised_given_name = None);
+        let options = LightCertOptions {
+            validity: Some(3600),
+            claim_key: -251,
+            ..LightCertOptions::default()
+        };
+        let light = derive(cwt, 1_620_000_000, &options).unwrap();
+        let claims = light.claims().unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("XX"));
+        assert_eq!(claims.exp, Some(NumericDate::Integer(1_620_003_600)));
+        assert!(claims.hcert.is_none());
+        let light_cert = LightCert::from_value(&claims.other[&Value::Integer(-251)]).unwrap();
+        assert_eq!(light_cert.dob.as_str(), "2009-02-28");
+        assert_eq!(
+            light_cert.nam.standardised_given_name.as_deref(),
+            Some("FRANCOIS<JOAN")
+        );
+    }
+
+    #[test]
+    fn test_issue() {
+        let key = SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = source(|_| {});
+        cwt.sign_with_kid(&key).unwrap();
+        let source = encode_qr_content(&cwt.to_cbor().unwrap(), HC1_PREFIX).unwrap();
+
+        let mut context = VerificationContext::new(1_630_000_000);
+        let options = LightCertOptions::default();
+        let e = issue(&source, &context, &options, &key).unwrap_err();
+        assert!(e.to_string().contains("is not trusted"), "{}", e);
+
+        context.add_key(&key.kid().unwrap(), key.verification_key().unwrap());
+        let content = issue(&source, &context, &options, &key).unwrap();
+        assert!(content.starts_with(LT1_PREFIX));
+        let cose = crate::base45::decode(&content[LT1_PREFIX.len()..]).unwrap();
+        let mut decompressed = vec![];
+        std::io::Read::read_to_end(
+            &mut flate2::read::ZlibDecoder::new(&cose[..]),
+            &mut decompressed,
+        )
+        .unwrap();
+        let light = get_payload(&decompressed).unwrap();
+        assert!(light.verify(&key.verification_key().unwrap()).is_ok());
+        assert_eq!(
+            light.claims().unwrap().exp,
+            Some(NumericDate::Integer(1_630_000_000 + 48 * 3600))
+        );
+
+        context.time = 1_700_000_000;
+        assert!(issue(&source, &context, &options, &key).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = source(|_| {});
+        cwt.sign_with_kid(&key).unwrap();
+        let mut light = derive(cwt, 1_630_000_000, &LightCertOptions::default()).unwrap();
+        light.sign_with_kid(&key).unwrap();
+        let content = encode_qr_content(&light.to_cbor().unwrap(), LT1_PREFIX).unwrap();
+        assert_eq!(
+            decode_qr_content(&content, LT1_PREFIX)
+                .unwrap()
+                .light_cert()
+                .unwrap()
+                .nam
+                .standardised_family_name,
+            "DARSONS<VAN<HALEN"
+        );
+
+        let mut jwk = key.verification_key().unwrap().to_jwk().unwrap();
+        jwk.set_key_id(&key.kid().unwrap());
+        let keys = JwkSet { keys: vec![jwk] };
+        let options = LightCertOptions::default();
+        let verified = verify(&content, &keys, 1_630_000_000, &options).unwrap();
+        assert_eq!(verified.iss.as_deref(), Some("XX"));
+        assert_eq!(verified.exp, Some(1_630_000_000 + 48 * 3600));
+        assert_eq!(verified.light_cert.dob.as_str(), "2009-02-28");
+
+        let e = verify(&content, &keys, 1_630_000_000 + 48 * 3600, &options).unwrap_err();
+        assert_eq!(e.to_string(), "Expired");
+        let other = SigningKey::es256(&[4u8; 32]).unwrap();
+        let mut jwk = other.verification_key().unwrap().to_jwk().unwrap();
+        jwk.set_key_id(&key.kid().unwrap());
+        let wrong = JwkSet { keys: vec![jwk] };
+        assert!(verify(&content, &wrong, 1_630_000_000, &options).is_err());
+        assert!(verify(&content, &JwkSet { keys: vec![] }, 1_630_000_000, &options).is_err());
+        assert!(decode_qr_content(&content, "HC1:").is_err());
+
+        // the dob must not contain a time, as in the hcert
+        let mut light_cert = verified.light_cert;
+        light_cert.dob = PartialDate::parse("2009-02-28T10:00:00Z").unwrap();
+        assert!(LightCert::from_value(&light_cert.to_value().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! `COSE_Mac0` structures ([RFC-8152 Section-6.2](https://tools.ietf.org/html/rfc8152#section-6.2)), i.e. CWTs
+//! protected by a shared secret instead of a signature.
+//!
+//! ```text
+//! COSE_Mac0 = [
+//!    Headers,
+//!    payload : bstr / nil,
+//!    tag : bstr,
+//! ]
+//! ```
+//!
+//! Only `HMAC` with SHA-256 is supported, either with the full tag ([algorithm::HMAC_256_256]) or truncated to
+//! 64 bits ([algorithm::HMAC_256_64]).
+use std::collections::BTreeMap;
+
+use ring::hmac;
+use serde_cbor::Value;
+
+use crate::cose_sign::protected_headers;
+use crate::headers::label;
+use crate::CwtParsed;
+
+/// CBOR tag of `COSE_Mac0` ([RFC-8152 Section-2](https://tools.ietf.org/html/rfc8152#section-2))
+pub const COSE_MAC0_TAG: u8 = 17;
+
+/// The COSE identifiers of the supported MAC algorithms
+pub mod algorithm {
+    /// `HMAC` with SHA-256, truncated to 64 bits
+    pub const HMAC_256_64: i128 = 4;
+    pub const HMAC_256_256: i128 = 5;
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// The parsed `COSE_Mac0` struct. The fields have the same meaning as for [CwtParsed], the signature is replaced
+/// by the `tag`.
+pub struct CoseMac0Parsed {
+    pub protected_headers: BTreeMap<Value, Value>,
+    pub protected_headers_original: Value,
+    pub unprotected_headers: BTreeMap<Value, Value>,
+    pub message: BTreeMap<Value, Value>,
+    original: Value,
+    /// The authentication tag, i.e. the (possibly truncated) `HMAC`
+    pub tag: Vec<u8>,
+}
+
+/// Parse a (tagged or untagged) `COSE_Mac0` structure. The tag is not validated, use [CoseMac0Parsed::validate].
+pub fn get_mac0(raw_payload: &[u8]) -> Result<CoseMac0Parsed, Box<dyn std::error::Error>> {
+    let value: serde_cbor::Value = serde_cbor::from_reader(raw_payload)?;
+    let value = match value {
+        Value::Array(inner) if inner.len() == 4 => inner,
+        _ => return Err("not an array of four".into()),
+    };
+    let (protected_headers_original, protected_headers) = protected_headers(&value[0])?;
+    let unprotected_headers = match &value[1] {
+        Value::Map(m) => m.clone(),
+        _ => return Err("No unprotected headers".into()),
+    };
+    let message = match &value[2] {
+        Value::Bytes(b) => serde_cbor::from_reader(&b[..])?,
+        _ => return Err("no message".into()),
+    };
+    let tag = match &value[3] {
+        Value::Bytes(b) => b.clone(),
+        _ => return Err("no tag".into()),
+    };
+    Ok(CoseMac0Parsed {
+        protected_headers,
+        protected_headers_original,
+        unprotected_headers,
+        message,
+        original: value[2].clone(),
+        tag,
+    })
+}
+
+impl CoseMac0Parsed {
+    /// Create an unauthenticated `COSE_Mac0` with the headers and claims of `cwt`. The `alg` header is
+    /// replaced by [algorithm::HMAC_256_256].
+    pub fn from_cwt(cwt: &CwtParsed) -> Result<CoseMac0Parsed, Box<dyn std::error::Error>> {
+        let mut protected_headers = cwt.protected_headers.clone();
+        protected_headers.insert(
+            Value::Integer(label::ALG),
+            Value::Integer(algorithm::HMAC_256_256),
+        );
+        Ok(CoseMac0Parsed {
+            protected_headers_original: Value::Bytes(serde_cbor::to_vec(&protected_headers)?),
+            protected_headers,
+            unprotected_headers: cwt.unprotected_headers.clone(),
+            original: Value::Bytes(serde_cbor::to_vec(&cwt.message)?),
+            message: cwt.message.clone(),
+            tag: vec![],
+        })
+    }
+
+    /// Compute the tag with the shared `key`. Like [CwtParsed::sign] this serializes the current
+    /// `protected_headers` and `message` first. The tag is truncated if the `alg` header asks for `HMAC 256/64`.
+    pub fn authenticate(&mut self, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.protected_headers_original =
+            Value::Bytes(serde_cbor::to_vec(&self.protected_headers)?);
+        self.original = Value::Bytes(serde_cbor::to_vec(&self.message)?);
+        let tag_length = self.tag_length()?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        self.tag = hmac::sign(&key, &self.get_mac_bytes()?).as_ref()[..tag_length].to_vec();
+        Ok(())
+    }
+
+    /// Check the tag with the shared `key`
+    pub fn validate(&self, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let tag_length = self.tag_length()?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        let expected = hmac::sign(&key, &self.get_mac_bytes()?);
+        ring::constant_time::verify_slices_are_equal(&expected.as_ref()[..tag_length], &self.tag)
+            .map_err(|_| "Invalid tag".into())
+    }
+
+    /// The length of the tag in bytes according to the `alg` header
+    fn tag_length(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        match self.protected_headers.get(&Value::Integer(label::ALG)) {
+            Some(Value::Integer(alg)) if *alg == algorithm::HMAC_256_64 => Ok(8),
+            Some(Value::Integer(alg)) if *alg == algorithm::HMAC_256_256 => Ok(32),
+            Some(Value::Integer(alg)) => Err(format!("Unsupported MAC algorithm {}", alg).into()),
+            _ => Err("No MAC algorithm".into()),
+        }
+    }
+
+    /// The `kid` of the shared key, c.f. [CwtParsed::key_id]
+    pub fn key_id(&self) -> Option<Vec<u8>> {
+        crate::key_id(&self.protected_headers, &self.unprotected_headers)
+    }
+
+    /// Get the `MAC_structure` according to [RFC-8152 Section-6.3](https://tools.ietf.org/html/rfc8152#section-6.3)
+    pub fn get_mac_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let structure = vec![
+            Value::Text("MAC0".to_string()),
+            self.protected_headers_original.clone(),
+            Value::Bytes(vec![]),
+            self.original.clone(),
+        ];
+        Ok(serde_cbor::to_vec(&structure)?)
+    }
+
+    /// c.f. [CwtParsed::get_hcert]
+    pub fn get_hcert(&self) -> Option<BTreeMap<Value, Value>> {
+        crate::hcert_from_claims(&self.message)
+    }
+
+    /// Encode as tagged `COSE_Mac0`
+    pub fn to_cbor(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let structure = vec![
+            self.protected_headers_original.clone(),
+            Value::Map(self.unprotected_headers.clone()),
+            self.original.clone(),
+            Value::Bytes(self.tag.clone()),
+        ];
+        // tags below 24 are encoded in the initial byte
+        let mut bytes = vec![0xc0 | COSE_MAC0_TAG];
+        bytes.extend(serde_cbor::to_vec(&structure)?);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::get_payload;
+
+    /// `COSE_Mac0` example of [RFC-8392 Appendix-A.4](https://tools.ietf.org/html/rfc8392#appendix-A.4)
+    const RFC_MAC0: &str = "d18443a10104a1044c53796d6d65747269633235365850a70175636f61703a2f2f61732e6578616d706c652e636f6d02656572696b77037818636f61703a2f2f6c696768742e6578616d706c652e636f6d041a5612aeb0051a5610d9f0061a5610d9f007420b7148093101ef6d789200";
+    const RFC_MAC0_KEY: &str = "403697de87af64611c1d32a05dab0fe1fcb715a86ab435f1ec99192d79569388";
+
+    #[test]
+    fn test_rfc_mac0() {
+        let key = crate::decode_hex(RFC_MAC0_KEY).unwrap();
+        let mac0 = get_mac0(&crate::decode_hex(RFC_MAC0).unwrap()).unwrap();
+        assert_eq!(mac0.key_id(), Some(b"Symmetric256".to_vec()));
+        assert!(mac0.validate(&key).is_ok());
+        assert!(mac0.validate(&key[1..]).is_err());
+    }
+
+    #[test]
+    fn test_mac0_round_trip() {
+        let key = [7u8; 32];
+        let cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        let mut mac0 = CoseMac0Parsed::from_cwt(&cwt).unwrap();
+        mac0.authenticate(&key).unwrap();
+        let bytes = mac0.to_cbor().unwrap();
+        assert_eq!(bytes[0], 0xd1);
+
+        let parsed = get_mac0(&bytes).unwrap();
+        assert_eq!(parsed.tag.len(), 32);
+        assert_eq!(parsed.message, cwt.message);
+        assert!(parsed.validate(&key).is_ok());
+        assert!(parsed.validate(&[8u8; 32]).is_err());
+
+        let mut tampered = parsed;
+        tampered.tag[0] ^= 1;
+        assert!(tampered.validate(&key).is_err());
+    }
+}
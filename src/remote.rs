@@ -0,0 +1,515 @@
+//! Signing via a remote HTTP signing service, so issuing code never handles private keys.
+//!
+//! The protocol is deliberately small. Keys are addressed by an identifier chosen by the service:
+//!
+//! ```text
+//! GET  {base}/keys/{key_id}       -> {"alg": -7, "kid": "<base64>", "jwk": {..public JWK..}}
+//! POST {base}/keys/{key_id}/sign  <- {"alg": -7, "data": "<base64 Sig_structure>"}
+//!                                 -> {"signature": "<base64>"}
+//! ```
+//!
+//! An optional bearer token is sent as `Authorization` header. Only plain `http://` is spoken, so the client
+//! only connects to loopback addresses; the service is expected to run next to the issuer (or behind a TLS
+//! terminating proxy on the same host). Key identifiers are limited to `A-Z`, `a-z`, `0-9`, `.`, `_` and `-`, as
+//! they become part of the request path. Every signature returned by the service is checked against the public
+//! key fetched on connect. [SigningServer] is a stand-in implementation of the service for tests and local
+//! development.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::jwk::Jwk;
+use crate::signer::Signer;
+use crate::VerificationKey;
+
+/// Default timeout for connecting, reading and writing
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest request or response we accept
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// Response to `GET {base}/keys/{key_id}`
+pub struct KeyInfo {
+    /// The COSE algorithm of the signatures
+    pub alg: i64,
+    /// The base64 encoded `kid` to put into the headers
+    pub kid: String,
+    /// The public key
+    pub jwk: Jwk,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// Body of `POST {base}/keys/{key_id}/sign`
+pub struct SignRequest {
+    /// The COSE algorithm the client expects
+    pub alg: i64,
+    /// The base64 encoded `Sig_structure`
+    pub data: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// Response to `POST {base}/keys/{key_id}/sign`
+pub struct SignResponse {
+    /// The base64 encoded signature
+    pub signature: String,
+}
+
+/// Where and how to reach a key of the signing service
+struct Endpoint {
+    host: String,
+    base_path: String,
+    key_id: String,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+/// A [Signer] which delegates to a remote signing service
+pub struct RemoteSigner {
+    endpoint: Endpoint,
+    algorithm: i128,
+    kid: Vec<u8>,
+    verification_key: VerificationKey,
+}
+
+impl RemoteSigner {
+    /// Connect to the service at `url` (e.g. `http://127.0.0.1:8080/signer`) and fetch the public part of `key_id`
+    pub fn connect(
+        url: &str,
+        key_id: &str,
+        token: Option<&str>,
+    ) -> Result<RemoteSigner, Box<dyn std::error::Error>> {
+        let valid_key_id = key_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if !valid_key_id || key_id.is_empty() || key_id == "." || key_id == ".." {
+            return Err(format!("Invalid key id {:?}", key_id).into());
+        }
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or("Only http:// URLs are supported")?;
+        let (host, base_path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+        if host.is_empty() {
+            return Err("URL without host".into());
+        }
+        let endpoint = Endpoint {
+            host: host.to_string(),
+            base_path: base_path.trim_end_matches('/').to_string(),
+            key_id: key_id.to_string(),
+            token: token.map(str::to_string),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let info: KeyInfo = serde_json::from_slice(&endpoint.request("GET", "", None)?)?;
+        Ok(RemoteSigner {
+            endpoint,
+            algorithm: info.alg.into(),
+            kid: base64::decode(&info.kid)?,
+            verification_key: VerificationKey::from_jwk(&info.jwk)?,
+        })
+    }
+
+    /// Change the timeout used for connecting, reading and writing
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.endpoint.timeout = timeout;
+    }
+}
+
+impl Endpoint {
+    fn request(
+        &self,
+        method: &str,
+        suffix: &str,
+        body: Option<&[u8]>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let address = std::net::ToSocketAddrs::to_socket_addrs(self.host.as_str())?
+            .next()
+            .ok_or("Could not resolve host")?;
+        // neither the token nor the data to sign may leave the host unencrypted
+        if !address.ip().is_loopback() {
+            return Err(format!("{} is not a loopback address", address).into());
+        }
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let body = body.unwrap_or_default();
+        let mut head = format!(
+            "{} {}/keys/{}{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            method,
+            self.base_path,
+            self.key_id,
+            suffix,
+            self.host,
+            body.len()
+        );
+        if let Some(token) = &self.token {
+            head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+
+        let mut response = vec![];
+        stream
+            .take(MAX_MESSAGE_SIZE as u64)
+            .read_to_end(&mut response)?;
+        let message = parse_message(&response)?;
+        let status = message
+            .start_line
+            .split(' ')
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .ok_or("Invalid status line")?;
+        if status != 200 {
+            return Err(format!(
+                "Signing service responded with {}: {}",
+                status,
+                String::from_utf8_lossy(&message.body)
+            )
+            .into());
+        }
+        Ok(message.body)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn algorithm(&self) -> i128 {
+        self.algorithm
+    }
+
+    fn verification_key(&self) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        Ok(self.verification_key.clone())
+    }
+
+    fn kid(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(self.kid.clone())
+    }
+
+    fn sign(&self, sig_structure: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let request = SignRequest {
+            alg: i64::try_from(self.algorithm)?,
+            data: base64::encode(sig_structure),
+        };
+        let response =
+            self.endpoint
+                .request("POST", "/sign", Some(&serde_json::to_vec(&request)?))?;
+        let response: SignResponse = serde_json::from_slice(&response)?;
+        let signature = base64::decode(&response.signature)?;
+        self.verification_key
+            .verify_signature_with_algorithm(Some(self.algorithm), sig_structure, &signature)
+            .map_err(|_| "The signing service returned an invalid signature")?;
+        Ok(signature)
+    }
+}
+
+/// A stand-in implementation of the signing service, backed by in-memory [Signer]s (usually [crate::SigningKey]s).
+/// Requests are handled one after the other on a background thread.
+#[derive(Default)]
+pub struct SigningServer {
+    signers: BTreeMap<String, Box<dyn Signer + Send>>,
+    token: Option<String>,
+}
+
+/// Handle of a running [SigningServer]. The server is stopped when the handle is dropped.
+pub struct SigningServerHandle {
+    url: String,
+    address: std::net::SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SigningServer {
+    pub fn new() -> SigningServer {
+        SigningServer::default()
+    }
+
+    /// Serve `signer` under `key_id`
+    pub fn add_signer(&mut self, key_id: &str, signer: Box<dyn Signer + Send>) {
+        self.signers.insert(key_id.to_string(), signer);
+    }
+
+    /// Require `token` as bearer token on every request
+    pub fn set_token(&mut self, token: &str) {
+        self.token = Some(token.to_string());
+    }
+
+    /// Listen on `address` (use port `0` for a random port) and serve requests on a background thread
+    pub fn spawn(self, address: &str) -> Result<SigningServerHandle, Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !thread_running.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(mut stream) = stream {
+                    let _ = self.serve(&mut stream);
+                }
+            }
+        });
+        Ok(SigningServerHandle {
+            url: format!("http://{}", address),
+            address,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    fn serve(&self, stream: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+        stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        let request = read_request(stream)?;
+        let (status, body) = match self.handle(&request) {
+            Ok(body) => (200, body),
+            Err((status, message)) => (status, message.into_bytes()),
+        };
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            _ => "Internal Server Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            status,
+            reason,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        Ok(())
+    }
+
+    fn handle(&self, request: &Message) -> Result<Vec<u8>, (u16, String)> {
+        let bad_request = |e: Box<dyn std::error::Error>| (400, e.to_string());
+        let internal = |e: Box<dyn std::error::Error>| (500, e.to_string());
+        if let Some(token) = &self.token {
+            let expected = format!("Bearer {}", token);
+            let authorization = request.header("authorization").map_or("", String::as_str);
+            if ring::constant_time::verify_slices_are_equal(
+                authorization.as_bytes(),
+                expected.as_bytes(),
+            )
+            .is_err()
+            {
+                return Err((401, "Invalid token".to_string()));
+            }
+        }
+        let mut parts = request.start_line.split(' ');
+        let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let path = &path[path.find("/keys/").ok_or((404, "Not found".to_string()))? + 6..];
+        let (key_id, action) = match path.split_once('/') {
+            Some((key_id, action)) => (key_id, action),
+            None => (path, ""),
+        };
+        let signer = self
+            .signers
+            .get(key_id)
+            .ok_or((404, format!("Unknown key {}", key_id)))?;
+        match (method, action) {
+            ("GET", "") => {
+                let info = KeyInfo {
+                    alg: i64::try_from(signer.algorithm()).map_err(|e| internal(e.into()))?,
+                    kid: base64::encode(signer.kid().map_err(internal)?),
+                    jwk: signer
+                        .verification_key()
+                        .and_then(|key| key.to_jwk())
+                        .map_err(internal)?,
+                };
+                serde_json::to_vec(&info).map_err(|e| internal(e.into()))
+            }
+            ("POST", "sign") => {
+                let sign_request: SignRequest =
+                    serde_json::from_slice(&request.body).map_err(|e| bad_request(e.into()))?;
+                if i128::from(sign_request.alg) != signer.algorithm() {
+                    return Err((
+                        400,
+                        format!("Key {} does not use alg {}", key_id, sign_request.alg),
+                    ));
+                }
+                let data = base64::decode(&sign_request.data).map_err(|e| bad_request(e.into()))?;
+                let signature = signer.sign(&data).map_err(internal)?;
+                serde_json::to_vec(&SignResponse {
+                    signature: base64::encode(signature),
+                })
+                .map_err(|e| internal(e.into()))
+            }
+            _ => Err((404, "Not found".to_string())),
+        }
+    }
+}
+
+impl SigningServerHandle {
+    /// The base URL of the server, to be passed to [RemoteSigner::connect]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for SigningServerHandle {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // wake up the blocking `accept`
+        let _ = TcpStream::connect(self.address);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A parsed HTTP/1.1 request or response
+struct Message {
+    start_line: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Message {
+    fn header(&self, name: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+}
+
+fn parse_message(bytes: &[u8]) -> Result<Message, Box<dyn std::error::Error>> {
+    let end = bytes
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("Incomplete HTTP message")?;
+    let head = std::str::from_utf8(&bytes[..end])?;
+    let mut lines = head.split("\r\n");
+    let start_line = lines.next().unwrap_or_default().to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+    let mut message = Message {
+        start_line,
+        headers,
+        body: bytes[end + 4..].to_vec(),
+    };
+    if message.header("transfer-encoding").is_some() {
+        return Err("Transfer-Encoding is not supported".into());
+    }
+    if let Some(length) = message.header("content-length") {
+        let length = length.parse::<usize>()?;
+        if message.body.len() < length {
+            return Err("Incomplete HTTP body".into());
+        }
+        message.body.truncate(length);
+    }
+    Ok(message)
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Message, Box<dyn std::error::Error>> {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if buffer.len() > MAX_MESSAGE_SIZE {
+            return Err("Request too large".into());
+        }
+        // the client keeps the connection open until it got the response, so we stop once the body is complete
+        if let Ok(message) = parse_message(&buffer) {
+            return Ok(message);
+        }
+    }
+    parse_message(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::RFC_TEST;
+    use crate::{get_payload, SigningKey};
+
+    fn server() -> (SigningServerHandle, VerificationKey) {
+        let key = SigningKey::es256(&[3u8; 32]).unwrap();
+        let verification_key = key.verification_key().unwrap();
+        let mut server = SigningServer::new();
+        server.add_signer("dsc-1", Box::new(key));
+        server.set_token("secret");
+        (server.spawn("127.0.0.1:0").unwrap(), verification_key)
+    }
+
+    #[test]
+    fn test_remote_signing() {
+        let (handle, verification_key) = server();
+        let url = format!("{}/", handle.url());
+        let signer = RemoteSigner::connect(&url, "dsc-1", Some("secret")).unwrap();
+        assert_eq!(signer.algorithm(), crate::signer::algorithm::ES256);
+        assert_eq!(signer.kid().unwrap(), verification_key.kid().unwrap());
+
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        cwt.sign_with_kid(&signer).unwrap();
+        let cwt = get_payload(&cwt.to_cbor().unwrap()).unwrap();
+        assert!(cwt
+            .verify_with_kid(&verification_key, &verification_key.kid().unwrap())
+            .unwrap()
+            .is_match());
+    }
+
+    #[test]
+    fn test_remote_errors() {
+        let (handle, _) = server();
+        assert!(RemoteSigner::connect(handle.url(), "dsc-1", None).is_err());
+        assert!(RemoteSigner::connect(handle.url(), "unknown", Some("secret")).is_err());
+        assert!(RemoteSigner::connect("https://localhost", "dsc-1", None).is_err());
+        for key_id in [
+            "dsc-1/sign",
+            "dsc-1?x",
+            "dsc 1",
+            "dsc-1\r\nX-Injected: 1",
+            "..",
+            "",
+        ] {
+            assert!(RemoteSigner::connect(handle.url(), key_id, Some("secret")).is_err());
+        }
+        assert!(RemoteSigner::connect("http://192.0.2.1:8080", "dsc-1", Some("secret")).is_err());
+    }
+
+    /// Signs something else than it was asked for, as a misbehaving service would
+    struct WrongSigner(SigningKey);
+
+    impl Signer for WrongSigner {
+        fn algorithm(&self) -> i128 {
+            self.0.algorithm()
+        }
+
+        fn verification_key(&self) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+            self.0.verification_key()
+        }
+
+        fn sign(&self, _: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            Signer::sign(&self.0, b"something else")
+        }
+    }
+
+    #[test]
+    fn test_invalid_remote_signature() {
+        let mut server = SigningServer::new();
+        server.add_signer(
+            "wrong",
+            Box::new(WrongSigner(SigningKey::es256(&[5u8; 32]).unwrap())),
+        );
+        let handle = server.spawn("127.0.0.1:0").unwrap();
+        let signer = RemoteSigner::connect(handle.url(), "wrong", None).unwrap();
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        assert!(cwt.sign_with_kid(&signer).is_err());
+    }
+}
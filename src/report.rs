@@ -0,0 +1,681 @@
+//! A detailed verification result for a complete health certificate.
+//!
+//! [verify_qr] runs every check it can on the QR code content and records a [Check] for each step in a
+//! [VerificationReport], instead of stopping at the first failure. Checks which need data not given in the
+//! [VerificationContext] (e.g. business rules or revocation lists) are [Verdict::Skipped], and callers running
+//! these checks elsewhere can record their result with [VerificationReport::set].
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use serde_cbor::Value;
+
+use crate::certlogic::{self, ExternalParameters, RuleResult};
+use crate::claims::CwtClaims;
+use crate::hcert::Name;
+use crate::jwk::JwkSet;
+use crate::revocation::RevocationList;
+use crate::rules::RuleSet;
+use crate::uvci::Uvci;
+use crate::valuesets::ValueSets;
+use crate::{get_payload, kid, CwtParsed, VerificationKey};
+
+/// Prefix of the QR code content of an EU DCC
+pub const HC1_PREFIX: &str = "HC1:";
+/// CBOR tag of `COSE_Sign1` ([RFC-8152 Section-2](https://tools.ietf.org/html/rfc8152#section-2))
+const COSE_SIGN1_TAG: u64 = 18;
+/// CBOR tag of a CWT ([RFC-8392 Section-6](https://tools.ietf.org/html/rfc8392#section-6))
+const CWT_TAG: u64 = 61;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Passed,
+    Failed,
+    /// The check was not run, e.g. because a previous step failed or data was missing
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The result of a single step together with human readable details
+pub struct Check {
+    pub verdict: Verdict,
+    pub details: Vec<String>,
+}
+
+impl Check {
+    pub fn passed(detail: impl Into<String>) -> Check {
+        Check {
+            verdict: Verdict::Passed,
+            details: vec![detail.into()],
+        }
+    }
+
+    pub fn failed(detail: impl Into<String>) -> Check {
+        Check {
+            verdict: Verdict::Failed,
+            details: vec![detail.into()],
+        }
+    }
+
+    pub fn skipped(detail: impl Into<String>) -> Check {
+        Check {
+            verdict: Verdict::Skipped,
+            details: vec![detail.into()],
+        }
+    }
+
+    /// Passed if there are no `problems`, failed with the problems as details otherwise
+    pub fn from_problems(problems: Vec<String>, success: impl Into<String>) -> Check {
+        if problems.is_empty() {
+            Check::passed(success)
+        } else {
+            Check {
+                verdict: Verdict::Failed,
+                details: problems,
+            }
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+/// The steps of the verification, in the order they are run
+pub enum Step {
+    /// Base45 decoding and zlib decompression
+    Encoding,
+    /// Well-formed CBOR without trailing data, the `COSE_Sign1` tag (if any) and deterministically encoded
+    /// protected headers
+    Cbor,
+    /// `COSE_Sign1` layout, headers and presence of the `hcert`
+    Structure,
+    /// The `kid` belongs to a trusted key
+    KeyTrust,
+    Signature,
+    /// `iat` and `exp` against the validation time
+    TimeValidity,
+    /// Required fields of the `hcert`
+    Schema,
+    BusinessRules,
+    Revocation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The verdicts of all verification steps
+pub struct VerificationReport {
+    pub checks: BTreeMap<Step, Check>,
+}
+
+impl Default for VerificationReport {
+    fn default() -> VerificationReport {
+        VerificationReport {
+            checks: [
+                Step::Encoding,
+                Step::Cbor,
+                Step::Structure,
+                Step::KeyTrust,
+                Step::Signature,
+                Step::TimeValidity,
+                Step::Schema,
+                Step::BusinessRules,
+                Step::Revocation,
+            ]
+            .iter()
+            .map(|step| (*step, Check::skipped("not checked")))
+            .collect(),
+        }
+    }
+}
+
+impl VerificationReport {
+    /// Record the result of `step`
+    pub fn set(&mut self, step: Step, check: Check) {
+        self.checks.insert(step, check);
+    }
+
+    pub fn get(&self, step: Step) -> Option<&Check> {
+        self.checks.get(&step)
+    }
+
+    /// Whether all steps passed. A skipped step does not count as passed.
+    pub fn is_valid(&self) -> bool {
+        self.checks
+            .values()
+            .all(|check| check.verdict == Verdict::Passed)
+    }
+
+    /// Whether any step failed. Without failures the certificate is valid as far as it was checked.
+    pub fn has_failures(&self) -> bool {
+        self.checks
+            .values()
+            .any(|check| check.verdict == Verdict::Failed)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// The trusted keys and the validation time used by [verify_qr]
+pub struct VerificationContext {
+    /// Trusted keys by `kid`
+    pub trusted_keys: BTreeMap<Vec<u8>, VerificationKey>,
+    /// The validation time in seconds since the UNIX epoch
+    pub time: i64,
+    /// The business rules, only evaluated if the arrival country is set
+    pub rules: RuleSet,
+    pub arrival_country: Option<String>,
+    /// The value sets passed to the business rules
+    pub value_sets: ValueSets,
+    /// Revoked certificates, only checked if the list is not empty
+    pub revocation: RevocationList,
+}
+
+impl VerificationContext {
+    pub fn new(time: i64) -> VerificationContext {
+        VerificationContext {
+            trusted_keys: BTreeMap::new(),
+            time,
+            rules: RuleSet::default(),
+            arrival_country: None,
+            value_sets: ValueSets::default(),
+            revocation: RevocationList::default(),
+        }
+    }
+
+    pub fn add_key(&mut self, kid: &[u8], key: VerificationKey) {
+        self.trusted_keys.insert(kid.to_vec(), key);
+    }
+
+    /// Trust the key of the DER encoded signer certificate `cert` under its DCC `kid`
+    pub fn add_certificate(&mut self, cert: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let key = VerificationKey::from_certificate_der(cert)?;
+        self.add_key(&kid::kid_from_certificate(cert), key);
+        Ok(())
+    }
+
+    /// Trust all keys of the set which carry a `kid` and can be imported
+    pub fn add_jwk_set(&mut self, jwk_set: &JwkSet) {
+        for (kid, key) in jwk_set.verification_keys() {
+            if let Some(kid) = kid {
+                self.add_key(&kid, key);
+            }
+        }
+    }
+}
+
+const BASE45_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Strip the `prefix` (usually [HC1_PREFIX]) if present, decode base45 and decompress. Returns the `COSE` bytes
+/// and a description of the encoding.
+pub fn decode_qr_content(
+    content: &str,
+    prefix: &str,
+) -> Result<(Vec<u8>, String), Box<dyn std::error::Error>> {
+    let name = prefix.trim_end_matches(':');
+    let content = content.trim();
+    let (content, mut detail) = match content.strip_prefix(prefix) {
+        Some(content) => (content, format!("{} prefix, ", name)),
+        None => (content, format!("no {} prefix, ", name)),
+    };
+    if let Some(c) = content.chars().find(|c| !BASE45_ALPHABET.contains(*c)) {
+        return Err(format!("Invalid base45 character {:?}", c).into());
+    }
+    if content.len() % 3 == 1 {
+        return Err("Invalid base45 length".into());
+    }
+    let compressed = crate::base45::decode(content).map_err(|e| format!("{:?}", e))?;
+    // zlib streams start with the CMF byte 0x78
+    if compressed.first() != Some(&0x78) {
+        detail.push_str("not compressed");
+        return Ok((compressed, detail));
+    }
+    let mut decompressed = vec![];
+    flate2::read::ZlibDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .map_err(|e| format!("Invalid zlib stream: {}", e))?;
+    detail.push_str("zlib compressed");
+    Ok((decompressed, detail))
+}
+
+/// Run all checks on the content of a QR code (usually starting with `HC1:`)
+pub fn verify_qr(content: &str, context: &VerificationContext) -> VerificationReport {
+    let mut report = VerificationReport::default();
+    match decode_qr_content(content, HC1_PREFIX) {
+        Ok((cose, detail)) => {
+            report.set(Step::Encoding, Check::passed(detail));
+            verify_cose_into(&cose, context, &mut report);
+        }
+        Err(e) => report.set(Step::Encoding, Check::failed(e.to_string())),
+    }
+    report
+}
+
+/// Run all checks on an already decoded `COSE_Sign1`. The encoding step is skipped.
+pub fn verify_cose(cose: &[u8], context: &VerificationContext) -> VerificationReport {
+    let mut report = VerificationReport::default();
+    report.set(Step::Encoding, Check::skipped("raw COSE given"));
+    verify_cose_into(cose, context, &mut report);
+    report
+}
+
+fn verify_cose_into(cose: &[u8], context: &VerificationContext, report: &mut VerificationReport) {
+    if let Err(e) = serde_cbor::from_slice::<Value>(cose) {
+        report.set(Step::Cbor, Check::failed(e.to_string()));
+        return;
+    }
+    report.set(Step::Cbor, cbor_check(cose));
+
+    let cwt = match get_payload(cose) {
+        Ok(cwt) => cwt,
+        Err(e) => {
+            report.set(Step::Structure, Check::failed(e.to_string()));
+            return;
+        }
+    };
+    report.set(Step::Structure, structure_check(&cwt));
+
+    match cwt.key_id() {
+        None => report.set(Step::KeyTrust, Check::failed("No kid")),
+        Some(kid) => match context.trusted_keys.get(&kid) {
+            None => report.set(
+                Step::KeyTrust,
+                Check::failed(format!("kid {} is not trusted", base64::encode(&kid))),
+            ),
+            Some(key) => {
+                report.set(
+                    Step::KeyTrust,
+                    Check::passed(format!("kid {}", base64::encode(&kid))),
+                );
+                report.set(
+                    Step::Signature,
+                    match cwt.verify(key) {
+                        Ok(()) => Check::passed("valid"),
+                        Err(e) => Check::failed(e.to_string()),
+                    },
+                );
+            }
+        },
+    }
+
+    match cwt.claims() {
+        Ok(claims) => report.set(Step::TimeValidity, time_check(&claims, context.time)),
+        Err(e) => report.set(Step::TimeValidity, Check::failed(e.to_string())),
+    }
+    if let Some(hcert) = cwt.get_hcert() {
+        report.set(Step::Schema, schema_check(&hcert));
+    }
+    if !context.revocation.is_empty() {
+        report.set(
+            Step::Revocation,
+            match context.revocation.revoked(&cwt) {
+                Ok(revoked) if revoked.is_empty() => Check::passed("not revoked"),
+                Ok(revoked) => Check::failed(format!("revoked by {:?} hash", revoked)),
+                Err(e) => Check::failed(e.to_string()),
+            },
+        );
+    }
+    if let Some(country) = &context.arrival_country {
+        report.set(
+            Step::BusinessRules,
+            match business_rules(&cwt, context, country) {
+                Ok(results) => certlogic::report_check(&results),
+                Err(e) => Check::failed(e.to_string()),
+            },
+        );
+    }
+}
+
+fn business_rules(
+    cwt: &CwtParsed,
+    context: &VerificationContext,
+    country: &str,
+) -> Result<Vec<RuleResult>, Box<dyn std::error::Error>> {
+    let mut external = ExternalParameters::from_cwt(cwt, context.time, country)?;
+    external.value_sets = context.value_sets.codes();
+    context.rules.evaluate(&cwt.hcert()?, &external)
+}
+
+/// Check the tags in front of the well-formed `cose` and the encoding of the protected headers
+fn cbor_check(cose: &[u8]) -> Check {
+    let mut problems = vec![];
+    let tags = leading_tags(cose);
+    let detail = match tags.as_slice() {
+        [] => "untagged",
+        [COSE_SIGN1_TAG] => "tagged COSE_Sign1",
+        [CWT_TAG, COSE_SIGN1_TAG] => "CWT tagged COSE_Sign1",
+        _ => {
+            let names: Vec<_> = tags.iter().map(|tag| tag_name(*tag)).collect();
+            problems.push(format!("Unexpected tags {}", names.join(", ")));
+            ""
+        }
+    };
+    if let Ok(Value::Array(items)) = serde_cbor::from_slice(cose) {
+        if let Some(Value::Bytes(protected)) = items.first() {
+            if !protected.is_empty() && !is_deterministic_map(protected) {
+                problems.push("Protected headers are not deterministically encoded".to_string());
+            }
+        }
+    }
+    Check::from_problems(problems, detail)
+}
+
+fn tag_name(tag: u64) -> String {
+    let name = match tag {
+        t if t == crate::encrypt::COSE_ENCRYPT0_TAG as u64 => "COSE_Encrypt0",
+        t if t == crate::mac::COSE_MAC0_TAG as u64 => "COSE_Mac0",
+        t if t == crate::cose_sign::COSE_SIGN_TAG as u64 => "COSE_Sign",
+        COSE_SIGN1_TAG => "COSE_Sign1",
+        CWT_TAG => "CWT",
+        _ => return tag.to_string(),
+    };
+    format!("{} ({})", tag, name)
+}
+
+/// The CBOR tags in front of the data item in `bytes`
+fn leading_tags(mut bytes: &[u8]) -> Vec<u64> {
+    let mut tags = vec![];
+    while let Some(first) = bytes.first() {
+        // major type 6, the argument follows in 0, 1, 2, 4 or 8 bytes
+        let len = match first {
+            0xc0..=0xd7 => 0,
+            0xd8 => 1,
+            0xd9 => 2,
+            0xda => 4,
+            0xdb => 8,
+            _ => break,
+        };
+        if bytes.len() <= len {
+            break;
+        }
+        let tag = match len {
+            0 => (first & 0x1f) as u64,
+            _ => bytes[1..=len]
+                .iter()
+                .fold(0u64, |tag, b| tag << 8 | *b as u64),
+        };
+        tags.push(tag);
+        bytes = &bytes[len + 1..];
+    }
+    tags
+}
+
+/// Whether `encoded` is a map in the deterministic encoding ([RFC-8949 Section-4.2.1](https://www.rfc-editor.org/rfc/rfc8949#section-4.2.1)),
+/// i.e. with definite lengths, the shortest form of arguments and the keys sorted by their encoding
+fn is_deterministic_map(encoded: &[u8]) -> bool {
+    let map: BTreeMap<Value, Value> = match serde_cbor::from_slice(encoded) {
+        Ok(map) => map,
+        Err(_) => return false,
+    };
+    let mut entries = vec![];
+    for (key, value) in &map {
+        match (serde_cbor::to_vec(key), serde_cbor::to_vec(value)) {
+            (Ok(key), Ok(value)) => entries.push((key, value)),
+            _ => return false,
+        }
+    }
+    entries.sort();
+    let mut expected = match entries.len() {
+        len if len < 24 => vec![0xa0 | len as u8],
+        len if len < 256 => vec![0xb8, len as u8],
+        len => vec![0xb9, (len >> 8) as u8, len as u8],
+    };
+    for (key, value) in entries {
+        expected.extend(key);
+        expected.extend(value);
+    }
+    expected == encoded
+}
+
+fn structure_check(cwt: &CwtParsed) -> Check {
+    let mut problems = vec![];
+    match cwt.headers() {
+        Ok(headers) => {
+            if let Err(e) = headers.validate() {
+                problems.push(e.to_string());
+            }
+            if headers.alg().is_none() {
+                problems.push("No protected alg".to_string());
+            }
+        }
+        Err(e) => problems.push(e.to_string()),
+    }
+    if cwt.is_detached() {
+        problems.push("Detached payload".to_string());
+    }
+    if cwt.get_hcert().is_none() {
+        problems.push("No hcert".to_string());
+    }
+    Check::from_problems(problems, "COSE_Sign1 with hcert")
+}
+
+fn time_check(claims: &CwtClaims, time: i64) -> Check {
+    let mut problems = vec![];
+    match &claims.exp {
+        Some(exp) if exp.timestamp() <= time => problems.push("Expired".to_string()),
+        Some(_) => {}
+        None => problems.push("No exp".to_string()),
+    }
+    match &claims.iat {
+        Some(iat) if iat.timestamp() > time => problems.push("Issued in the future".to_string()),
+        Some(_) => {}
+        None => problems.push("No iat".to_string()),
+    }
+    Check::from_problems(problems, "within iat and exp")
+}
+
+/// Parse and validate the `ci` of a vaccination, test or recovery entry. Returns whether it contains lower case
+/// letters, which is reported without failing.
+fn uvci_check(entry: &Value) -> Result<bool, Box<dyn std::error::Error>> {
+    match entry {
+        Value::Map(entry) => match entry.get(&Value::Text("ci".to_string())) {
+            Some(Value::Text(ci)) => {
+                let uvci = Uvci::parse(ci)?;
+                uvci.validate()?;
+                Ok(uvci.has_lower_case())
+            }
+            _ => Err("must be a string".into()),
+        },
+        _ => Err("entry must be a map".into()),
+    }
+}
+
+fn schema_check(hcert: &BTreeMap<Value, Value>) -> Check {
+    let field = |name: &str| hcert.get(&Value::Text(name.to_string()));
+    let mut problems = vec![];
+    if !matches!(field("ver"), Some(Value::Text(_))) {
+        problems.push("ver must be a string".to_string());
+    }
+    if !matches!(field("dob"), Some(Value::Text(_))) {
+        problems.push("dob must be a string".to_string());
+    }
+    match field("nam") {
+        Some(Value::Map(nam)) => {
+            let text = |name: &str| match nam.get(&Value::Text(name.to_string())) {
+                Some(Value::Text(text)) => Some(text.clone()),
+                _ => None,
+            };
+            match text("fnt") {
+                Some(fnt) => {
+                    let name = Name {
+                        family_name: text("fn"),
+                        standardised_family_name: fnt,
+                        given_name: text("gn"),
+                        standardised_given_name: text("gnt"),
+                    };
+                    for problem in name.transliteration_problems() {
+                        problems.push(format!("nam.{}", problem));
+                    }
+                }
+                None => problems.push("nam.fnt must be a string".to_string()),
+            }
+        }
+        _ => problems.push("nam must be a map".to_string()),
+    }
+    let groups: Vec<_> = ["v", "t", "r"]
+        .iter()
+        .filter(|group| field(group).is_some())
+        .collect();
+    match groups.as_slice() {
+        [group] => match field(group) {
+            Some(Value::Array(entries)) if entries.len() == 1 => {}
+            _ => problems.push(format!("{} must be an array with one entry", group)),
+        },
+        _ => problems.push("Exactly one of v, t or r is required".to_string()),
+    }
+    let mut notes = vec![];
+    for group in &["v", "t", "r"] {
+        if let Some(Value::Array(entries)) = field(group) {
+            for (i, entry) in entries.iter().enumerate() {
+                match uvci_check(entry) {
+                    Ok(true) => {
+                        notes.push(format!("{}.{}.ci: contains lower case letters", group, i))
+                    }
+                    Ok(false) => {}
+                    Err(e) => problems.push(format!("{}.{}.ci: {}", group, i, e)),
+                }
+            }
+        }
+    }
+    let mut check = Check::from_problems(problems, "required fields present");
+    check.details.extend(notes);
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::SigningKey;
+    use std::io::Write;
+
+    /// The hcert of the Swiss test certificate, re-signed for the tests
+    const HCERT: &str = "d2844da204489a024e6b59bf42ce0126a0590220a4041a625eef58061a607dbbd801624154390103a101590207bf63737562bf62676e684761627269656c6562666e6a4d75737465726672617563646f626a313939382d30322d32366367656e6666656d616c656269649fbf61746350504e61696c31323334354142432d333231ffffff637661639fbf6364697369383430353339303036637661706a31313139333035303035636d65706c45552f312f32302f31353238636175746d4f52472d313030303330323135637365710163746f7402636c6f746d4332322d38363246462d303031636461746a323032312d30322d31386361646d781c56616363696e6174696f6e2063656e747265205669656e6e6120323363636f75624154ffbf6364697369383430353339303036637661706a31313139333035303035636d65706c45552f312f32302f31353238636175746d4f52472d313030303330323135637365710263746f7402636c6f746d4332322d48363246462d303130636461746a323032312d30332d31326361646d781c56616363696e6174696f6e2063656e747265205669656e6e6120323363636f75624154ffff6463657274bf626973781b4d696e6973747279206f66204865616c74682c204175737472696162696478273031415434323139363536303237353233303432373430323437303235363532303235303034326276666a323032312d30342d30346276756a323032312d31302d303462636f6241546276726476312e30ffff5840cea198d7da5cb609f9b1d0622d3d2824d5a05b0a8cbd0f112ec8be8860be3944ab9f0a4614521328db093570bd64bc2c1d88decdd597578a329b2aa96fbfe906";
+
+    fn qr_content(cwt: &mut CwtParsed) -> String {
+        let mut compressed = vec![];
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(&mut compressed, flate2::Compression::default());
+        encoder.write_all(&cwt.to_cbor().unwrap()).unwrap();
+        encoder.finish().unwrap();
+        format!("{}{}", HC1_PREFIX, crate::base45::encode(&compressed))
+    }
+
+    #[test]
+    fn test_report() {
+        let key = SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = get_payload(&crate::decode_hex(HCERT).unwrap()).unwrap();
+        cwt.sign_with_kid(&key).unwrap();
+        let content = qr_content(&mut cwt);
+
+        let mut context = VerificationContext::new(1_630_000_000);
+        let report = verify_qr(&content, &context);
+        assert_eq!(report.get(Step::Encoding).unwrap().verdict, Verdict::Passed);
+        assert_eq!(
+            report.get(Step::Structure).unwrap().verdict,
+            Verdict::Passed
+        );
+        assert_eq!(report.get(Step::KeyTrust).unwrap().verdict, Verdict::Failed);
+        assert_eq!(
+            report.get(Step::Signature).unwrap().verdict,
+            Verdict::Skipped
+        );
+        assert!(report.has_failures());
+
+        context.add_key(&key.kid().unwrap(), key.verification_key().unwrap());
+        let report = verify_qr(&content, &context);
+        assert_eq!(
+            report.get(Step::Signature).unwrap().verdict,
+            Verdict::Passed
+        );
+        assert_eq!(
+            report.get(Step::TimeValidity).unwrap().verdict,
+            Verdict::Passed
+        );
+        assert_eq!(report.get(Step::Schema).unwrap().verdict, Verdict::Failed);
+        assert!(report.to_json().unwrap().contains("\"time_validity\""));
+        assert_eq!(
+            report.get(Step::BusinessRules).unwrap().verdict,
+            Verdict::Skipped
+        );
+
+        // the pre-release hcert cannot be decoded for the business rules
+        context.arrival_country = Some("AT".to_string());
+        let report = verify_qr(&content, &context);
+        assert_eq!(
+            report.get(Step::BusinessRules).unwrap().verdict,
+            Verdict::Failed
+        );
+
+        context.time = 1_700_000_000;
+        let report = verify_qr(&content, &context);
+        assert_eq!(
+            report.get(Step::TimeValidity).unwrap().details,
+            vec!["Expired".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_lower_case_uvci() {
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let check = schema_check(&cwt.get_hcert().unwrap());
+        assert_eq!(check.verdict, Verdict::Passed);
+        assert!(check
+            .details
+            .contains(&"v.0.ci: contains lower case letters".to_string()));
+    }
+
+    #[test]
+    fn test_report_encoding() {
+        let context = VerificationContext::new(0);
+        let report = verify_qr("HC1:abc", &context);
+        assert_eq!(report.get(Step::Encoding).unwrap().verdict, Verdict::Failed);
+        assert_eq!(report.get(Step::Cbor).unwrap().verdict, Verdict::Skipped);
+
+        let report = verify_cose(&[0xd2, 0x84], &context);
+        assert_eq!(report.get(Step::Cbor).unwrap().verdict, Verdict::Failed);
+        assert!(!report.is_valid());
+
+        // well-formed CBOR, but too short for a COSE_Sign1
+        for cose in [&[0x80][..], &[0x81, 0x40], &[0xd2, 0x82, 0x40, 0xa0]] {
+            let report = verify_cose(cose, &context);
+            assert_eq!(report.get(Step::Cbor).unwrap().verdict, Verdict::Passed);
+            assert_eq!(
+                report.get(Step::Structure).unwrap().verdict,
+                Verdict::Failed
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_cbor() {
+        let context = VerificationContext::new(0);
+        let key = SigningKey::es256(&[3u8; 32]).unwrap();
+        let mut cwt = get_payload(&crate::decode_hex(HCERT).unwrap()).unwrap();
+        let check = cbor_check(&crate::decode_hex(HCERT).unwrap());
+        assert_eq!(
+            check.details,
+            vec!["Protected headers are not deterministically encoded".to_string()]
+        );
+
+        cwt.sign_with_kid(&key).unwrap();
+        let untagged = cwt.to_cbor().unwrap();
+        let report = verify_cose(&untagged, &context);
+        assert_eq!(report.get(Step::Cbor).unwrap(), &Check::passed("untagged"));
+        for (tag, expected) in [
+            (&[0xd2][..], Ok("tagged COSE_Sign1")),
+            (&[0xd8, 0x3d, 0xd2], Ok("CWT tagged COSE_Sign1")),
+            (&[0xd1], Err("Unexpected tags 17 (COSE_Mac0)")),
+            (&[0xd8, 0x62], Err("Unexpected tags 98 (COSE_Sign)")),
+        ] {
+            let mut tagged = tag.to_vec();
+            tagged.extend(&untagged);
+            let expected = match expected {
+                Ok(detail) => Check::passed(detail),
+                Err(problem) => Check::failed(problem),
+            };
+            assert_eq!(cbor_check(&tagged), expected);
+        }
+    }
+}
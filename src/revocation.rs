@@ -0,0 +1,473 @@
+//! Checking certificates against the hash based revocation lists of the EU DCC revocation scheme.
+//!
+//! Revoked certificates are published as truncated SHA-256 hashes of one of
+//!
+//! - the UCI (the `ci` of the certificate entry),
+//! - the issuing country (the `iss` claim) followed by the UCI,
+//! - the signature, where only the `r` value is hashed for `ECDSA` signatures.
+//!
+//! The hashes are grouped into [Partition]s by the `kid` of the signer and a prefix of the hash. Each partition
+//! consists of chunks, which are either a sorted list of hashes or a [BloomFilter] as delivered by the gateway.
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+
+use ring::digest;
+
+use crate::hcert::Hcert;
+use crate::signer::algorithm;
+use crate::CwtParsed;
+
+/// Hashes are truncated to their first 128 bit
+pub const HASH_LENGTH: usize = 16;
+/// A truncated revocation hash
+pub type Hash = [u8; HASH_LENGTH];
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HashType {
+    Signature,
+    Uci,
+    CountryCodeUci,
+}
+
+fn truncated_sha256(data: &[u8]) -> Hash {
+    let mut hash = [0u8; HASH_LENGTH];
+    hash.copy_from_slice(&digest::digest(&digest::SHA256, data).as_ref()[..HASH_LENGTH]);
+    hash
+}
+
+/// The `ci` of the first vaccination, test or recovery entry
+fn uci(hcert: &Hcert) -> Option<&str> {
+    let v = hcert.v.iter().flatten().map(|v| &v.ci);
+    let t = hcert.t.iter().flatten().map(|t| &t.ci);
+    let r = hcert.r.iter().flatten().map(|r| &r.ci);
+    v.chain(t).chain(r).next().map(String::as_str)
+}
+
+impl CwtParsed {
+    /// The revocation hash of the given type
+    pub fn revocation_hash(&self, hash_type: HashType) -> Result<Hash, Box<dyn std::error::Error>> {
+        match hash_type {
+            HashType::Signature => {
+                let ecdsa = matches!(
+                    self.headers()?.alg(),
+                    Some(crate::headers::Label::Int(alg))
+                        if [algorithm::ES256, algorithm::ES384, algorithm::ES512].contains(alg)
+                );
+                let signature = if ecdsa {
+                    &self.signature[..self.signature.len() / 2]
+                } else {
+                    &self.signature[..]
+                };
+                if signature.is_empty() {
+                    return Err("No signature".into());
+                }
+                Ok(truncated_sha256(signature))
+            }
+            HashType::Uci => {
+                let hcert = self.hcert()?;
+                Ok(truncated_sha256(uci(&hcert).ok_or("No UCI")?.as_bytes()))
+            }
+            HashType::CountryCodeUci => {
+                let hcert = self.hcert()?;
+                let country = self.claims()?.iss.ok_or("No issuing country")?;
+                let uci = uci(&hcert).ok_or("No UCI")?;
+                Ok(truncated_sha256(
+                    format!("{}{}", country.to_uppercase(), uci).as_bytes(),
+                ))
+            }
+        }
+    }
+
+    /// All revocation hashes which can be calculated for this certificate
+    pub fn revocation_hashes(&self) -> BTreeMap<HashType, Hash> {
+        [HashType::Signature, HashType::Uci, HashType::CountryCodeUci]
+            .iter()
+            .filter_map(|t| self.revocation_hash(*t).ok().map(|hash| (*t, hash)))
+            .collect()
+    }
+}
+
+/// Version of the serialized [BloomFilter]
+const BLOOM_FILTER_VERSION: u16 = 1;
+/// Upper bound for the number of hash functions, as each one costs a SHA-256 per lookup
+pub const MAX_HASH_COUNT: u32 = 64;
+/// Length of the header of a serialized [BloomFilter]
+const BLOOM_FILTER_HEADER_LENGTH: usize = 26;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A bloom filter over revocation hashes in the format of the EU DCC revocation gateway, i.e. of its reference
+/// implementation `dgca-bloomfilter`. The `i`-th bit index of an element is `SHA-256(element || i)`, with `i` as
+/// 4 byte big endian integer, read as unsigned big endian integer modulo the number of bits. Bit `j` is stored in
+/// the 32 bit word `j / 32` with the value `1 << (j % 32)`.
+///
+/// Serialized big endian as version (2 bytes), number of hash functions (4 bytes), false positive probability
+/// (4 byte float), expected number of elements (4 bytes), number of elements (4 bytes), number of bits (4 bytes)
+/// and number of words (4 bytes), followed by the words of 4 bytes each.
+pub struct BloomFilter {
+    hash_count: u32,
+    /// The bits of the `f32`, so the filter can be compared
+    probability: u32,
+    capacity: u32,
+    element_count: u32,
+    bit_count: u32,
+    words: Vec<u32>,
+}
+
+impl BloomFilter {
+    /// An empty filter. Fails without bits or for a number of hash functions outside `1..=`[MAX_HASH_COUNT].
+    pub fn new(hash_count: u32, bit_count: u32) -> Result<BloomFilter, Box<dyn std::error::Error>> {
+        if bit_count == 0 {
+            return Err("Bloom filter without bits".into());
+        }
+        if hash_count == 0 || hash_count > MAX_HASH_COUNT {
+            return Err(format!("Unsupported number of hash functions {}", hash_count).into());
+        }
+        Ok(BloomFilter {
+            hash_count,
+            probability: 0,
+            capacity: 0,
+            element_count: 0,
+            bit_count,
+            words: vec![0; bit_count.div_ceil(32) as usize],
+        })
+    }
+
+    /// An empty filter for `capacity` elements with the false positive `probability`, sized like the gateway does
+    pub fn with_capacity(
+        capacity: u32,
+        probability: f32,
+    ) -> Result<BloomFilter, Box<dyn std::error::Error>> {
+        if capacity == 0 || !(probability > 0.0 && probability < 1.0) {
+            return Err("Bloom filter needs a capacity and a probability between 0 and 1".into());
+        }
+        let ln2 = std::f64::consts::LN_2;
+        let bits =
+            (capacity as f64 * (probability as f64).ln() / (1.0 / 2f64.powf(ln2)).ln()).ceil();
+        if bits > u32::MAX as f64 {
+            return Err("Bloom filter too large".into());
+        }
+        let hash_count = ((bits / capacity as f64 * ln2).round() as u32).max(1);
+        let mut filter = BloomFilter::new(hash_count, bits as u32)?;
+        filter.probability = probability.to_bits();
+        filter.capacity = capacity;
+        Ok(filter)
+    }
+
+    pub fn hash_count(&self) -> u32 {
+        self.hash_count
+    }
+
+    pub fn bit_count(&self) -> u32 {
+        self.bit_count
+    }
+
+    /// The false positive probability the filter was sized for
+    pub fn probability(&self) -> f32 {
+        f32::from_bits(self.probability)
+    }
+
+    /// The number of inserted elements as stated by the filter
+    pub fn element_count(&self) -> u32 {
+        self.element_count
+    }
+
+    fn indices<'a>(&'a self, element: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        (0..self.hash_count).map(move |i| {
+            let mut context = digest::Context::new(&digest::SHA256);
+            context.update(element);
+            context.update(&i.to_be_bytes());
+            let digest = context.finish();
+            let index = digest.as_ref().iter().fold(0u64, |index, b| {
+                ((index << 8) | *b as u64) % self.bit_count as u64
+            });
+            index as usize
+        })
+    }
+
+    pub fn insert(&mut self, element: &[u8]) {
+        let indices: Vec<_> = self.indices(element).collect();
+        for index in indices {
+            self.words[index / 32] |= 1 << (index % 32);
+        }
+        self.element_count = self.element_count.saturating_add(1);
+    }
+
+    /// Whether `element` may be contained. False positives are possible.
+    pub fn contains(&self, element: &[u8]) -> bool {
+        self.indices(element)
+            .all(|index| self.words[index / 32] & (1 << (index % 32)) != 0)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BLOOM_FILTER_VERSION.to_be_bytes().to_vec();
+        bytes.extend(&self.hash_count.to_be_bytes());
+        bytes.extend(&self.probability.to_be_bytes());
+        bytes.extend(&self.capacity.to_be_bytes());
+        bytes.extend(&self.element_count.to_be_bytes());
+        bytes.extend(&self.bit_count.to_be_bytes());
+        bytes.extend(&(self.words.len() as u32).to_be_bytes());
+        for word in &self.words {
+            bytes.extend(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<BloomFilter, Box<dyn std::error::Error>> {
+        if bytes.len() < BLOOM_FILTER_HEADER_LENGTH {
+            return Err("Bloom filter too short".into());
+        }
+        let u32_at =
+            |offset: usize| u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let version = u16::from_be_bytes(bytes[0..2].try_into()?);
+        if version != BLOOM_FILTER_VERSION {
+            return Err(format!("Unsupported bloom filter version {}", version).into());
+        }
+        let mut filter = BloomFilter::new(u32_at(2), u32_at(18))?;
+        filter.probability = u32_at(6);
+        filter.capacity = u32_at(10);
+        filter.element_count = u32_at(14);
+        let words = &bytes[BLOOM_FILTER_HEADER_LENGTH..];
+        if u32_at(22) as usize != filter.words.len() || words.len() != filter.words.len() * 4 {
+            return Err("Bloom filter length does not match the number of bits".into());
+        }
+        for (word, bytes) in filter.words.iter_mut().zip(words.chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into()?);
+        }
+        Ok(filter)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+/// The format of the chunks of a partition
+pub enum ChunkType {
+    /// Concatenated hashes of [HASH_LENGTH] bytes
+    HashList,
+    BloomFilter,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk {
+    HashList(BTreeSet<Hash>),
+    BloomFilter(BloomFilter),
+}
+
+impl Chunk {
+    pub fn from_bytes(
+        chunk_type: ChunkType,
+        bytes: &[u8],
+    ) -> Result<Chunk, Box<dyn std::error::Error>> {
+        match chunk_type {
+            ChunkType::HashList => {
+                if !bytes.len().is_multiple_of(HASH_LENGTH) {
+                    return Err("Hash list length is not a multiple of the hash length".into());
+                }
+                Ok(Chunk::HashList(
+                    bytes
+                        .chunks(HASH_LENGTH)
+                        .map(|hash| hash.try_into().unwrap())
+                        .collect(),
+                ))
+            }
+            ChunkType::BloomFilter => Ok(Chunk::BloomFilter(BloomFilter::from_bytes(bytes)?)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Chunk::HashList(hashes) => hashes.iter().flatten().copied().collect(),
+            Chunk::BloomFilter(filter) => filter.to_bytes(),
+        }
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        match self {
+            Chunk::HashList(hashes) => hashes.contains(hash),
+            Chunk::BloomFilter(filter) => filter.contains(hash),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The revoked hashes of one type, signed with the key `kid`, starting with `prefix`
+pub struct Partition {
+    pub kid: Vec<u8>,
+    pub hash_type: HashType,
+    /// The common start of all hashes of the partition, empty for all hashes
+    pub prefix: Vec<u8>,
+    pub chunks: Vec<Chunk>,
+}
+
+impl Partition {
+    pub fn contains(&self, hash: &Hash) -> bool {
+        hash.starts_with(&self.prefix) && self.chunks.iter().any(|chunk| chunk.contains(hash))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevocationList {
+    pub partitions: Vec<Partition>,
+}
+
+impl RevocationList {
+    pub fn add_partition(&mut self, partition: Partition) {
+        self.partitions.push(partition);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    /// The hash types under which `cwt` is revoked. Partitions of other `kid`s are not considered.
+    pub fn revoked(&self, cwt: &CwtParsed) -> Result<Vec<HashType>, Box<dyn std::error::Error>> {
+        let kid = cwt.key_id().ok_or("No kid")?;
+        let hashes = cwt.revocation_hashes();
+        let mut revoked: Vec<_> = self
+            .partitions
+            .iter()
+            .filter(|partition| partition.kid == kid)
+            .filter(|partition| {
+                hashes
+                    .get(&partition.hash_type)
+                    .is_some_and(|hash| partition.contains(hash))
+            })
+            .map(|partition| partition.hash_type)
+            .collect();
+        revoked.sort();
+        revoked.dedup();
+        Ok(revoked)
+    }
+
+    pub fn is_revoked(&self, cwt: &CwtParsed) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(!self.revoked(cwt)?.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::get_payload;
+
+    #[test]
+    fn test_revocation_hashes() {
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let hashes = cwt.revocation_hashes();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(
+            hashes[&HashType::Uci],
+            truncated_sha256(b"urn:uvci:01:NL:PlA8UWS60Z4RZXVALl6GAZ")
+        );
+        assert_eq!(
+            hashes[&HashType::CountryCodeUci],
+            truncated_sha256(b"XXurn:uvci:01:NL:PlA8UWS60Z4RZXVALl6GAZ")
+        );
+        // ES256, only the r value is hashed
+        assert_eq!(
+            hashes[&HashType::Signature],
+            truncated_sha256(&cwt.signature[..32])
+        );
+    }
+
+    #[test]
+    fn test_gateway_bloom_filter() {
+        // a chunk as written by the gateway for one element with 3 hash functions and 64 bits. The index is the
+        // digest modulo 64, i.e. the lowest 6 bits of its last byte.
+        let hash = truncated_sha256(b"URN:UVCI:01:AT:10807843F94AEE0EE5093FBC254BD813#B");
+        let mut words = [0u32; 2];
+        for i in 0u32..3 {
+            let digest = digest::digest(&digest::SHA256, &[&hash[..], &i.to_be_bytes()].concat());
+            let index = (digest.as_ref()[31] & 63) as usize;
+            words[index / 32] |= 1 << (index % 32);
+        }
+        let mut chunk = vec![0, 1, 0, 0, 0, 3];
+        chunk.extend(&0.01f32.to_be_bytes());
+        chunk.extend(&[0, 0, 0, 10, 0, 0, 0, 1, 0, 0, 0, 64, 0, 0, 0, 2]);
+        chunk.extend(&words[0].to_be_bytes());
+        chunk.extend(&words[1].to_be_bytes());
+
+        let filter = match Chunk::from_bytes(ChunkType::BloomFilter, &chunk).unwrap() {
+            Chunk::BloomFilter(filter) => filter,
+            chunk => panic!("{:?}", chunk),
+        };
+        assert!(filter.contains(&hash));
+        assert_eq!(filter.element_count(), 1);
+        assert_eq!(filter.to_bytes(), chunk);
+
+        let mut inserted = BloomFilter::new(3, 64).unwrap();
+        inserted.insert(&hash);
+        assert_eq!(inserted.words, words);
+
+        let sized = BloomFilter::with_capacity(1000, 0.01).unwrap();
+        assert_eq!((sized.bit_count(), sized.hash_count()), (9586, 7));
+    }
+
+    #[test]
+    fn test_revocation_list() {
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let kid = cwt.key_id().unwrap();
+        let hashes = cwt.revocation_hashes();
+        let uci = hashes[&HashType::Uci];
+
+        let mut list = RevocationList::default();
+        list.add_partition(Partition {
+            kid: kid.clone(),
+            hash_type: HashType::Uci,
+            prefix: vec![uci[0] ^ 1],
+            chunks: vec![Chunk::HashList(std::iter::once(uci).collect())],
+        });
+        assert!(!list.is_revoked(&cwt).unwrap());
+
+        let mut filter = BloomFilter::new(7, 1024).unwrap();
+        filter.insert(&hashes[&HashType::Signature]);
+        let filter = BloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!(!filter.contains(&uci));
+        assert!(BloomFilter::new(7, 0).is_err());
+        assert!(BloomFilter::new(0, 1024).is_err());
+        assert!(BloomFilter::new(MAX_HASH_COUNT + 1, 1024).is_err());
+        let header = |hash_count: u32, bit_count: u32, words: u32| {
+            [
+                &BLOOM_FILTER_VERSION.to_be_bytes()[..],
+                &hash_count.to_be_bytes(),
+                &0.01f32.to_be_bytes(),
+                &[0, 0, 0, 1, 0, 0, 0, 1],
+                &bit_count.to_be_bytes(),
+                &words.to_be_bytes(),
+            ]
+            .concat()
+        };
+        assert!(BloomFilter::from_bytes(&header(7, 0, 0)).is_err());
+        assert!(BloomFilter::from_bytes(&[header(u32::MAX, 32, 1), vec![0; 4]].concat()).is_err());
+        assert!(BloomFilter::from_bytes(&[header(7, 64, 1), vec![0; 4]].concat()).is_err());
+        assert!(BloomFilter::from_bytes(&[header(7, 64, 2), vec![0; 4]].concat()).is_err());
+        list.add_partition(Partition {
+            kid: b"other".to_vec(),
+            hash_type: HashType::Signature,
+            prefix: vec![],
+            chunks: vec![Chunk::BloomFilter(filter.clone())],
+        });
+        assert!(!list.is_revoked(&cwt).unwrap());
+
+        let hash_list = Chunk::from_bytes(ChunkType::HashList, &[uci, [0u8; 16]].concat()).unwrap();
+        assert!(Chunk::from_bytes(ChunkType::HashList, &[0u8; 17]).is_err());
+        list.add_partition(Partition {
+            kid: kid.clone(),
+            hash_type: HashType::Uci,
+            prefix: uci[..1].to_vec(),
+            chunks: vec![hash_list],
+        });
+        list.add_partition(Partition {
+            kid,
+            hash_type: HashType::Signature,
+            prefix: vec![],
+            chunks: vec![Chunk::BloomFilter(filter)],
+        });
+        assert_eq!(
+            list.revoked(&cwt).unwrap(),
+            vec![HashType::Signature, HashType::Uci]
+        );
+    }
+}
@@ -0,0 +1,694 @@
+//! A local store for revocation partitions (c.f. [crate::revocation]) with incremental updates.
+//!
+//! Every partition is kept in its own file in the store directory. The file holds the partition key (`kid`,
+//! hash type and prefix), the ETag of the last applied update and the chunks in their compact binary form,
+//! followed by the SHA-256 of all preceding bytes, which is checked on every read. [RevocationStore::revoked] only
+//! reads the partitions which can contain the hashes of the checked certificate.
+//!
+//! Updates come in signed batches: a CBOR encoded [UpdateBatch] together with a detached signature over the
+//! CBOR bytes. An update either replaces a partition (no `previous_etag`) or appends chunks to the partition
+//! stored with `previous_etag`. Updates for a different ETag are rejected, so the caller can fall back to a
+//! full download. The store remembers the sequence number of the last applied batch and rejects batches with
+//! a number which is not larger, so an old batch cannot be replayed to roll back the store.
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ring::digest;
+use serde_cbor::Value;
+
+use crate::revocation::{Chunk, ChunkType, HashType, Partition, RevocationList};
+use crate::signer::Signer;
+use crate::{CwtParsed, VerificationKey};
+
+/// Start of every partition file
+const MAGIC: &[u8] = b"DRS1";
+const EXTENSION: &str = "partition";
+/// File holding the sequence number of the last applied batch
+const SEQUENCE_FILE: &str = "batch.sequence";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// New chunks for one partition
+pub struct PartitionUpdate {
+    pub kid: Vec<u8>,
+    pub hash_type: HashType,
+    pub prefix: Vec<u8>,
+    /// The ETag of the partition after applying the update
+    pub etag: String,
+    /// The ETag the update is based on, `None` for a full replacement
+    pub previous_etag: Option<String>,
+    pub chunk_type: ChunkType,
+    /// The serialized chunks, c.f. [Chunk::from_bytes]
+    pub chunks: Vec<Vec<u8>>,
+}
+
+fn hash_type_code(hash_type: HashType) -> u8 {
+    match hash_type {
+        HashType::Signature => 0,
+        HashType::Uci => 1,
+        HashType::CountryCodeUci => 2,
+    }
+}
+
+fn hash_type_from_code(code: u8) -> Result<HashType, Box<dyn std::error::Error>> {
+    match code {
+        0 => Ok(HashType::Signature),
+        1 => Ok(HashType::Uci),
+        2 => Ok(HashType::CountryCodeUci),
+        _ => Err(format!("Unknown hash type {}", code).into()),
+    }
+}
+
+fn chunk_type_code(chunk: &Chunk) -> u8 {
+    match chunk {
+        Chunk::HashList(_) => 0,
+        Chunk::BloomFilter(_) => 1,
+    }
+}
+
+fn chunk_type_from_code(code: u8) -> Result<ChunkType, Box<dyn std::error::Error>> {
+    match code {
+        0 => Ok(ChunkType::HashList),
+        1 => Ok(ChunkType::BloomFilter),
+        _ => Err(format!("Unknown chunk type {}", code).into()),
+    }
+}
+
+fn get<'a>(
+    map: &'a BTreeMap<Value, Value>,
+    key: &str,
+) -> Result<&'a Value, Box<dyn std::error::Error>> {
+    map.get(&Value::Text(key.to_string()))
+        .ok_or_else(|| format!("{} is missing", key).into())
+}
+
+fn bytes(value: &Value, name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match value {
+        Value::Bytes(b) => Ok(b.to_owned()),
+        _ => Err(format!("{} must be a byte string", name).into()),
+    }
+}
+
+fn text(value: &Value, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    match value {
+        Value::Text(t) => Ok(t.to_owned()),
+        _ => Err(format!("{} must be a text string", name).into()),
+    }
+}
+
+impl PartitionUpdate {
+    fn from_value(value: &Value) -> Result<PartitionUpdate, Box<dyn std::error::Error>> {
+        let map = match value {
+            Value::Map(map) => map,
+            _ => return Err("Updates must be maps".into()),
+        };
+        let hash_type = text(get(map, "hashType")?, "hashType")?;
+        let chunk_type = text(get(map, "chunkType")?, "chunkType")?;
+        Ok(PartitionUpdate {
+            kid: bytes(get(map, "kid")?, "kid")?,
+            hash_type: serde_json::from_value(serde_json::Value::String(hash_type))?,
+            prefix: bytes(get(map, "prefix")?, "prefix")?,
+            etag: text(get(map, "etag")?, "etag")?,
+            previous_etag: match get(map, "previousEtag")? {
+                Value::Null => None,
+                etag => Some(text(etag, "previousEtag")?),
+            },
+            chunk_type: serde_json::from_value(serde_json::Value::String(chunk_type))?,
+            chunks: match get(map, "chunks")? {
+                Value::Array(chunks) => chunks
+                    .iter()
+                    .map(|c| bytes(c, "chunks"))
+                    .collect::<Result<_, _>>()?,
+                _ => return Err("chunks must be an array".into()),
+            },
+        })
+    }
+
+    fn to_value(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        let name = |value: serde_json::Value| match value {
+            serde_json::Value::String(name) => Value::Text(name),
+            _ => Value::Null,
+        };
+        let mut map = BTreeMap::new();
+        let mut insert = |key: &str, value: Value| {
+            map.insert(Value::Text(key.to_string()), value);
+        };
+        insert("kid", Value::Bytes(self.kid.clone()));
+        insert("hashType", name(serde_json::to_value(self.hash_type)?));
+        insert("prefix", Value::Bytes(self.prefix.clone()));
+        insert("etag", Value::Text(self.etag.clone()));
+        insert(
+            "previousEtag",
+            self.previous_etag.clone().map_or(Value::Null, Value::Text),
+        );
+        insert("chunkType", name(serde_json::to_value(self.chunk_type)?));
+        insert(
+            "chunks",
+            Value::Array(self.chunks.iter().cloned().map(Value::Bytes).collect()),
+        );
+        Ok(Value::Map(map))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Updates which are signed and applied together. The `sequence` has to increase with every batch.
+pub struct UpdateBatch {
+    pub sequence: u64,
+    pub updates: Vec<PartitionUpdate>,
+}
+
+impl UpdateBatch {
+    /// Decode a CBOR encoded batch
+    pub fn decode(batch: &[u8]) -> Result<UpdateBatch, Box<dyn std::error::Error>> {
+        let map = match serde_cbor::from_slice(batch)? {
+            Value::Map(map) => map,
+            _ => return Err("A batch must be a map".into()),
+        };
+        let sequence = match get(&map, "sequence")? {
+            Value::Integer(sequence) => {
+                u64::try_from(*sequence).map_err(|_| "sequence is out of range")?
+            }
+            _ => return Err("sequence must be an integer".into()),
+        };
+        let updates = match get(&map, "updates")? {
+            Value::Array(updates) => updates
+                .iter()
+                .map(PartitionUpdate::from_value)
+                .collect::<Result<_, _>>()?,
+            _ => return Err("updates must be an array".into()),
+        };
+        Ok(UpdateBatch { sequence, updates })
+    }
+
+    /// Encode the batch as CBOR
+    pub fn encode(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let updates = self
+            .updates
+            .iter()
+            .map(PartitionUpdate::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut map = BTreeMap::new();
+        map.insert(
+            Value::Text("sequence".to_string()),
+            Value::Integer(self.sequence as i128),
+        );
+        map.insert(Value::Text("updates".to_string()), Value::Array(updates));
+        Ok(serde_cbor::to_vec(&Value::Map(map))?)
+    }
+
+    /// Encode and sign the batch, c.f. [RevocationStore::apply_signed_batch]
+    pub fn sign<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+    ) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+        let batch = self.encode()?;
+        let signature = signer.sign(&batch)?;
+        Ok((batch, signature))
+    }
+}
+
+/// A stored partition together with the ETag of its last update
+struct StoredPartition {
+    partition: Partition,
+    etag: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn read_slice<'a>(
+    bytes: &mut &'a [u8],
+    length: usize,
+) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+    if bytes.len() < length {
+        return Err("Partition file is truncated".into());
+    }
+    let (head, tail) = bytes.split_at(length);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, Box<dyn std::error::Error>> {
+    Ok(u32::from_be_bytes(read_slice(bytes, 4)?.try_into()?))
+}
+
+impl StoredPartition {
+    fn to_bytes(&self) -> Vec<u8> {
+        let partition = &self.partition;
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&(partition.kid.len() as u32).to_be_bytes());
+        bytes.extend(&partition.kid);
+        bytes.push(hash_type_code(partition.hash_type));
+        bytes.extend(&(partition.prefix.len() as u32).to_be_bytes());
+        bytes.extend(&partition.prefix);
+        bytes.extend(&(self.etag.len() as u32).to_be_bytes());
+        bytes.extend(self.etag.as_bytes());
+        bytes.extend(&(partition.chunks.len() as u32).to_be_bytes());
+        for chunk in &partition.chunks {
+            let chunk_bytes = chunk.to_bytes();
+            bytes.push(chunk_type_code(chunk));
+            bytes.extend(&(chunk_bytes.len() as u32).to_be_bytes());
+            bytes.extend(chunk_bytes);
+        }
+        let checksum = digest::digest(&digest::SHA256, &bytes);
+        bytes.extend(checksum.as_ref());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<StoredPartition, Box<dyn std::error::Error>> {
+        let checksum_start = bytes
+            .len()
+            .checked_sub(digest::SHA256.output_len)
+            .ok_or("Partition file is truncated")?;
+        let (content, checksum) = bytes.split_at(checksum_start);
+        if digest::digest(&digest::SHA256, content).as_ref() != checksum {
+            return Err("Partition file is corrupted".into());
+        }
+        let mut rest = content;
+        if read_slice(&mut rest, MAGIC.len())? != MAGIC {
+            return Err("Not a partition file".into());
+        }
+        let length = read_u32(&mut rest)? as usize;
+        let kid = read_slice(&mut rest, length)?.to_vec();
+        let hash_type = hash_type_from_code(read_slice(&mut rest, 1)?[0])?;
+        let length = read_u32(&mut rest)? as usize;
+        let prefix = read_slice(&mut rest, length)?.to_vec();
+        let length = read_u32(&mut rest)? as usize;
+        let etag = String::from_utf8(read_slice(&mut rest, length)?.to_vec())?;
+        let mut chunks = vec![];
+        for _ in 0..read_u32(&mut rest)? {
+            let chunk_type = chunk_type_from_code(read_slice(&mut rest, 1)?[0])?;
+            let length = read_u32(&mut rest)? as usize;
+            chunks.push(Chunk::from_bytes(
+                chunk_type,
+                read_slice(&mut rest, length)?,
+            )?);
+        }
+        Ok(StoredPartition {
+            partition: Partition {
+                kid,
+                hash_type,
+                prefix,
+                chunks,
+            },
+            etag,
+        })
+    }
+}
+
+/// A directory of revocation partitions
+pub struct RevocationStore {
+    directory: PathBuf,
+}
+
+impl RevocationStore {
+    /// Open the store in `directory`, creating the directory if necessary
+    pub fn open<P: AsRef<Path>>(
+        directory: P,
+    ) -> Result<RevocationStore, Box<dyn std::error::Error>> {
+        fs::create_dir_all(&directory)?;
+        Ok(RevocationStore {
+            directory: directory.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, kid: &[u8], hash_type: HashType, prefix: &[u8]) -> PathBuf {
+        self.directory.join(format!(
+            "{}_{}_{}.{}",
+            to_hex(kid),
+            hash_type_code(hash_type),
+            to_hex(prefix),
+            EXTENSION
+        ))
+    }
+
+    fn read(
+        &self,
+        kid: &[u8],
+        hash_type: HashType,
+        prefix: &[u8],
+    ) -> Result<Option<StoredPartition>, Box<dyn std::error::Error>> {
+        let path = self.path(kid, hash_type, prefix);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(StoredPartition::from_bytes(&fs::read(path)?)?))
+    }
+
+    /// The ETag of the stored partition, `None` if it is not stored
+    pub fn etag(
+        &self,
+        kid: &[u8],
+        hash_type: HashType,
+        prefix: &[u8],
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(self.read(kid, hash_type, prefix)?.map(|stored| stored.etag))
+    }
+
+    /// The partition after applying `update` to `stored`. Fails if `previous_etag` does not match.
+    fn updated(
+        update: &PartitionUpdate,
+        stored: Option<StoredPartition>,
+    ) -> Result<StoredPartition, Box<dyn std::error::Error>> {
+        let new_chunks = update
+            .chunks
+            .iter()
+            .map(|chunk| Chunk::from_bytes(update.chunk_type, chunk))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut chunks = match (&update.previous_etag, stored) {
+            (None, _) => vec![],
+            (Some(previous), Some(stored)) if *previous == stored.etag => stored.partition.chunks,
+            (Some(previous), stored) => {
+                return Err(format!(
+                    "Update based on ETag {} but the store has {:?}",
+                    previous,
+                    stored.map(|s| s.etag)
+                )
+                .into())
+            }
+        };
+        chunks.extend(new_chunks);
+        Ok(StoredPartition {
+            partition: Partition {
+                kid: update.kid.clone(),
+                hash_type: update.hash_type,
+                prefix: update.prefix.clone(),
+                chunks,
+            },
+            etag: update.etag.clone(),
+        })
+    }
+
+    /// Write `content` to a temporary file next to `path`, which is renamed by the caller
+    fn write_temporary(path: &Path, content: &[u8]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, content)?;
+        Ok(temporary)
+    }
+
+    /// Apply a single update. Fails without changes if `previous_etag` does not match the stored ETag.
+    ///
+    /// The update is **not authenticated**: there is no signature and the batch sequence number is neither
+    /// checked nor advanced. Only use it for updates whose origin was verified otherwise, e.g. when rebuilding
+    /// the store from a verified full download, and [RevocationStore::apply_signed_batch] for everything else.
+    pub fn apply(&self, update: &PartitionUpdate) -> Result<(), Box<dyn std::error::Error>> {
+        let stored = self.read(&update.kid, update.hash_type, &update.prefix)?;
+        let stored = RevocationStore::updated(update, stored)?;
+        // write a temporary file first, so an interrupted update leaves the previous state
+        let path = self.path(&update.kid, update.hash_type, &update.prefix);
+        let temporary = RevocationStore::write_temporary(&path, &stored.to_bytes())?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    /// The sequence number of the last applied batch, `None` if no batch was applied yet
+    pub fn sequence(&self) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let path = self.directory.join(SEQUENCE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?.trim().parse()?))
+    }
+
+    /// Verify the `signature` over the CBOR encoded `batch` and apply all updates. Returns the number of
+    /// applied updates.
+    ///
+    /// Fails without changes if the signature is invalid, if the batch is not newer than the last applied one or
+    /// if any update cannot be applied, as all updates are checked and written to temporary files before the first
+    /// partition is replaced. Replacing the partitions is not atomic as a whole though: if renaming a temporary
+    /// file fails, the partitions renamed before stay replaced and the sequence number is not updated. The store
+    /// should then be rebuilt from a full download.
+    pub fn apply_signed_batch(
+        &self,
+        batch: &[u8],
+        signature: &[u8],
+        key: &VerificationKey,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        key.verify_signature(batch, signature)
+            .map_err(|_| "Invalid signature of the update batch")?;
+        let batch = UpdateBatch::decode(batch)?;
+        if let Some(sequence) = self.sequence()? {
+            if batch.sequence <= sequence {
+                return Err(format!(
+                    "Batch {} is not newer than the applied batch {}",
+                    batch.sequence, sequence
+                )
+                .into());
+            }
+        }
+        // later updates of the same partition are based on the earlier ones of the batch
+        let mut pending: BTreeMap<PathBuf, StoredPartition> = BTreeMap::new();
+        for update in &batch.updates {
+            let path = self.path(&update.kid, update.hash_type, &update.prefix);
+            let stored = match pending.remove(&path) {
+                Some(stored) => Some(stored),
+                None => self.read(&update.kid, update.hash_type, &update.prefix)?,
+            };
+            pending.insert(path, RevocationStore::updated(update, stored)?);
+        }
+
+        let mut temporaries = vec![];
+        for (path, stored) in &pending {
+            match RevocationStore::write_temporary(path, &stored.to_bytes()) {
+                Ok(temporary) => temporaries.push((temporary, path)),
+                Err(e) => {
+                    for (temporary, _) in temporaries {
+                        let _ = fs::remove_file(temporary);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        for (temporary, path) in temporaries {
+            fs::rename(temporary, path)?;
+        }
+        let path = self.directory.join(SEQUENCE_FILE);
+        let temporary =
+            RevocationStore::write_temporary(&path, batch.sequence.to_string().as_bytes())?;
+        fs::rename(temporary, path)?;
+        Ok(batch.updates.len())
+    }
+
+    pub fn remove(
+        &self,
+        kid: &[u8],
+        hash_type: HashType,
+        prefix: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.path(kid, hash_type, prefix);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The hash types under which `cwt` is revoked, like [RevocationList::revoked]. Only the partitions of the
+    /// `kid` of `cwt` whose prefix matches one of its hashes are read.
+    pub fn revoked(&self, cwt: &CwtParsed) -> Result<Vec<HashType>, Box<dyn std::error::Error>> {
+        let kid = cwt.key_id().ok_or("No kid")?;
+        let mut revoked = vec![];
+        for (hash_type, hash) in cwt.revocation_hashes() {
+            for length in 0..=hash.len() {
+                if let Some(stored) = self.read(&kid, hash_type, &hash[..length])? {
+                    if stored.partition.contains(&hash) {
+                        revoked.push(hash_type);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(revoked)
+    }
+
+    pub fn is_revoked(&self, cwt: &CwtParsed) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(!self.revoked(cwt)?.is_empty())
+    }
+
+    /// Read and check all stored partitions
+    pub fn load(&self) -> Result<RevocationList, Box<dyn std::error::Error>> {
+        let mut list = RevocationList::default();
+        let mut paths = vec![];
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == EXTENSION) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let stored = StoredPartition::from_bytes(&fs::read(&path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            list.add_partition(stored.partition);
+        }
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::revocation::{BloomFilter, HASH_LENGTH};
+    use crate::{get_payload, SigningKey};
+
+    fn store(name: &str) -> RevocationStore {
+        let directory =
+            std::env::temp_dir().join(format!("rust-dgc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        RevocationStore::open(directory).unwrap()
+    }
+
+    fn update(etag: &str, previous_etag: Option<&str>, hashes: &[u8]) -> PartitionUpdate {
+        PartitionUpdate {
+            kid: b"kid".to_vec(),
+            hash_type: HashType::Uci,
+            prefix: vec![0xab],
+            etag: etag.to_string(),
+            previous_etag: previous_etag.map(str::to_string),
+            chunk_type: ChunkType::HashList,
+            chunks: vec![hashes.to_vec()],
+        }
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        let store = store("incremental");
+        let first = [
+            [0xab; HASH_LENGTH],
+            [0xab, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        ];
+        store.apply(&update("1", None, &first.concat())).unwrap();
+        assert_eq!(
+            store.etag(b"kid", HashType::Uci, &[0xab]).unwrap(),
+            Some("1".to_string())
+        );
+
+        let mut second = [0u8; HASH_LENGTH];
+        second[..2].copy_from_slice(&[0xab, 0xcd]);
+        assert!(store.apply(&update("3", Some("2"), &second)).is_err());
+        store.apply(&update("2", Some("1"), &second)).unwrap();
+
+        let list = store.load().unwrap();
+        assert_eq!(list.partitions.len(), 1);
+        let partition = &list.partitions[0];
+        assert_eq!(partition.chunks.len(), 2);
+        assert!(partition.contains(&first[1]));
+        assert!(partition.contains(&second));
+
+        // a full update replaces all chunks
+        store.apply(&update("4", None, &first[0])).unwrap();
+        assert!(!store.load().unwrap().partitions[0].contains(&first[1]));
+
+        store.remove(b"kid", HashType::Uci, &[0xab]).unwrap();
+        assert!(store.load().unwrap().is_empty());
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn test_partition_lookup() {
+        let store = store("lookup");
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let uci = cwt.revocation_hash(HashType::Uci).unwrap();
+        let mut revoked = update("1", None, &uci);
+        revoked.kid = cwt.key_id().unwrap();
+        revoked.prefix = uci[..1].to_vec();
+        let mut other_prefix = revoked.clone();
+        other_prefix.prefix = vec![uci[0] ^ 1];
+        store.apply(&other_prefix).unwrap();
+        assert!(!store.is_revoked(&cwt).unwrap());
+
+        store.apply(&revoked).unwrap();
+        assert_eq!(store.revoked(&cwt).unwrap(), vec![HashType::Uci]);
+        assert_eq!(
+            store.revoked(&cwt).unwrap(),
+            store.load().unwrap().revoked(&cwt).unwrap()
+        );
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn test_signed_batches() {
+        let store = store("signed");
+        let signer = SigningKey::es256(&[3u8; 32]).unwrap();
+        let key = signer.verification_key().unwrap();
+        let mut filter = BloomFilter::new(5, 256).unwrap();
+        filter.insert(&[1u8; HASH_LENGTH]);
+        let mut bloom = update("1", None, &filter.to_bytes());
+        bloom.chunk_type = ChunkType::BloomFilter;
+        bloom.hash_type = HashType::Signature;
+        let updates = vec![update("1", None, &[0xab; HASH_LENGTH]), bloom];
+
+        let batch = UpdateBatch {
+            sequence: 1,
+            updates,
+        };
+        let (encoded, signature) = batch.sign(&signer).unwrap();
+        assert_eq!(UpdateBatch::decode(&encoded).unwrap(), batch);
+        let batch = encoded;
+        let mut tampered = batch.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(store
+            .apply_signed_batch(&tampered, &signature, &key)
+            .is_err());
+        assert!(store.load().unwrap().is_empty());
+
+        assert_eq!(
+            store.apply_signed_batch(&batch, &signature, &key).unwrap(),
+            2
+        );
+        assert_eq!(store.load().unwrap().partitions.len(), 2);
+        assert_eq!(store.sequence().unwrap(), Some(1));
+        // replaying a batch must not roll back the store
+        assert!(store.apply_signed_batch(&batch, &signature, &key).is_err());
+
+        // a batch with an update which cannot be applied changes nothing
+        let (failing, signature) = UpdateBatch {
+            sequence: 2,
+            updates: vec![
+                update("2", Some("1"), &[0xab; HASH_LENGTH]),
+                update("3", Some("x"), &[0xab; HASH_LENGTH]),
+            ],
+        }
+        .sign(&signer)
+        .unwrap();
+        assert!(store
+            .apply_signed_batch(&failing, &signature, &key)
+            .is_err());
+        assert_eq!(
+            store.etag(b"kid", HashType::Uci, &[0xab]).unwrap(),
+            Some("1".to_string())
+        );
+        assert_eq!(store.sequence().unwrap(), Some(1));
+
+        // later updates of a batch build on earlier ones
+        let (chained, signature) = UpdateBatch {
+            sequence: 3,
+            updates: vec![
+                update("2", Some("1"), &[0xab; HASH_LENGTH]),
+                update("3", Some("2"), &[0xab; HASH_LENGTH]),
+            ],
+        }
+        .sign(&signer)
+        .unwrap();
+        assert_eq!(
+            store
+                .apply_signed_batch(&chained, &signature, &key)
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            store.etag(b"kid", HashType::Uci, &[0xab]).unwrap(),
+            Some("3".to_string())
+        );
+
+        // corrupt a stored partition
+        let path = store.path(b"kid", HashType::Signature, &[0xab]);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[20] ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert!(store.load().is_err());
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+//! Loading and selection of business rule sets.
+//!
+//! A [RuleSet] is the JSON array of [Rule]s as downloaded from a national backend. Signed rule sets carry a
+//! detached signature over the exact JSON bytes, so they can be stored and checked again offline. For a
+//! validation only the newest valid version of each rule is used:
+//!
+//! - acceptance rules of the arrival country (and region, if given),
+//! - invalidation rules of the issuing country,
+//! - for the type of the certificate or `General`,
+//! - with `ValidFrom <= time < ValidTo`.
+use std::collections::BTreeMap;
+
+use crate::certlogic::{parse_date, ExternalParameters, Rule, RuleResult};
+use crate::hcert::{CertificateType, Hcert};
+use crate::signer::Signer;
+use crate::VerificationKey;
+
+/// Rule type of rules set by the arrival country
+pub const ACCEPTANCE: &str = "Acceptance";
+/// Rule type of rules set by the issuing country
+pub const INVALIDATION: &str = "Invalidation";
+/// Certificate type of rules applying to all certificates
+pub const GENERAL: &str = "General";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The parameters selecting the applicable rules
+pub struct RuleSelection {
+    pub arrival_country: String,
+    /// Region rules (e.g. of a federal state) are only used if the region is given
+    pub region: Option<String>,
+    /// The issuing country, selecting the invalidation rules
+    pub issuer_country: Option<String>,
+    pub certificate_type: CertificateType,
+    /// The validation time in seconds since the UNIX epoch
+    pub time: i64,
+}
+
+impl RuleSelection {
+    /// Select by the `hcert` and the parameters the rules are evaluated with
+    pub fn for_hcert(
+        hcert: &Hcert,
+        external: &ExternalParameters,
+    ) -> Result<RuleSelection, Box<dyn std::error::Error>> {
+        Ok(RuleSelection {
+            arrival_country: external.country_code.clone(),
+            region: None,
+            issuer_country: Some(external.issuer_country_code.clone())
+                .filter(|country| !country.is_empty()),
+            certificate_type: hcert
+                .certificate_type()
+                .ok_or("The hcert has no unique certificate type")?,
+            time: parse_date(&external.validation_clock)?,
+        })
+    }
+
+    /// Whether `rule` applies, not taking other versions of the rule into account
+    pub fn matches(&self, rule: &Rule) -> bool {
+        let country = match rule.rule_type.as_str() {
+            ACCEPTANCE => Some(&self.arrival_country),
+            INVALIDATION => self.issuer_country.as_ref(),
+            _ => None,
+        };
+        let certificate_type = format!("{:?}", self.certificate_type);
+        let valid = match (parse_date(&rule.valid_from), parse_date(&rule.valid_to)) {
+            (Ok(from), Ok(to)) => from <= self.time && self.time < to,
+            _ => false,
+        };
+        country.is_some_and(|country| rule.country.eq_ignore_ascii_case(country))
+            && (rule.region.is_none() || rule.region == self.region)
+            && (rule.certificate_type == certificate_type || rule.certificate_type == GENERAL)
+            && valid
+    }
+}
+
+/// Compare semantic versions like `1.0.10` numerically. Missing or non-numeric parts count as `0`.
+fn version(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// All versions of the rules of a backend
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /// Load a JSON array of rules
+    pub fn from_json(json: &str) -> Result<RuleSet, Box<dyn std::error::Error>> {
+        Ok(RuleSet {
+            rules: serde_json::from_str(json)?,
+        })
+    }
+
+    /// Load a JSON array of rules after verifying the raw `signature` (`r || s` for `ECDSA`) over the `json` bytes
+    pub fn from_signed_json(
+        json: &[u8],
+        signature: &[u8],
+        key: &VerificationKey,
+    ) -> Result<RuleSet, Box<dyn std::error::Error>> {
+        key.verify_signature(json, signature)
+            .map_err(|_| "Invalid signature of the rule set")?;
+        RuleSet::from_json(std::str::from_utf8(json)?)
+    }
+
+    /// Produce the detached signature of a serialized rule set, c.f. [RuleSet::from_signed_json]
+    pub fn sign_json<S: Signer + ?Sized>(
+        json: &[u8],
+        signer: &S,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        signer.sign(json)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(&self.rules)?)
+    }
+
+    /// The newest matching version of every applicable rule, ordered by identifier
+    pub fn select(&self, selection: &RuleSelection) -> Vec<&Rule> {
+        let mut selected: BTreeMap<&str, &Rule> = BTreeMap::new();
+        for rule in self.rules.iter().filter(|rule| selection.matches(rule)) {
+            let newer = selected
+                .get(rule.identifier.as_str())
+                .is_none_or(|other| version(&rule.version) > version(&other.version));
+            if newer {
+                selected.insert(&rule.identifier, rule);
+            }
+        }
+        selected.into_values().collect()
+    }
+
+    /// Select the rules for `hcert` and evaluate them. The results name the rule versions used.
+    pub fn evaluate(
+        &self,
+        hcert: &Hcert,
+        external: &ExternalParameters,
+    ) -> Result<Vec<RuleResult>, Box<dyn std::error::Error>> {
+        let selection = RuleSelection::for_hcert(hcert, external)?;
+        Ok(self
+            .select(&selection)
+            .into_iter()
+            .map(|rule| rule.evaluate(hcert, external))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certlogic::Outcome;
+    use crate::fixtures::SE_HCERT;
+    use crate::{get_payload, SigningKey};
+
+    fn rule(
+        identifier: &str,
+        rule_type: &str,
+        country: &str,
+        version: &str,
+        certificate_type: &str,
+        valid_from: &str,
+        logic: serde_json::Value,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "Identifier": identifier,
+            "Type": rule_type,
+            "Country": country,
+            "Version": version,
+            "SchemaVersion": "1.0.0",
+            "Engine": "CERTLOGIC",
+            "EngineVersion": "0.7.5",
+            "CertificateType": certificate_type,
+            "Description": [{"lang": "en", "desc": identifier}],
+            "ValidFrom": valid_from,
+            "ValidTo": "2030-01-01T00:00:00Z",
+            "AffectedFields": [],
+            "Logic": logic,
+        })
+    }
+
+    fn rule_set_json() -> String {
+        let dose = serde_json::json!({">=": [{"var": "payload.v.0.dn"}, 2]});
+        serde_json::json!([
+            rule(
+                "VR-AT-0001",
+                ACCEPTANCE,
+                "AT",
+                "1.0.0",
+                "Vaccination",
+                "2021-01-01T00:00:00Z",
+                dose.clone()
+            ),
+            rule(
+                "VR-AT-0001",
+                ACCEPTANCE,
+                "AT",
+                "1.0.10",
+                "Vaccination",
+                "2021-05-01T00:00:00Z",
+                serde_json::json!(false)
+            ),
+            rule(
+                "VR-AT-0001",
+                ACCEPTANCE,
+                "AT",
+                "1.0.9",
+                "Vaccination",
+                "2021-01-01T00:00:00Z",
+                dose
+            ),
+            rule(
+                "GR-AT-0001",
+                ACCEPTANCE,
+                "AT",
+                "1.0.0",
+                GENERAL,
+                "2021-01-01T00:00:00Z",
+                serde_json::json!(true)
+            ),
+            rule(
+                "TR-AT-0001",
+                ACCEPTANCE,
+                "AT",
+                "1.0.0",
+                "Test",
+                "2021-01-01T00:00:00Z",
+                serde_json::json!(false)
+            ),
+            rule(
+                "VR-DE-0001",
+                ACCEPTANCE,
+                "DE",
+                "1.0.0",
+                "Vaccination",
+                "2021-01-01T00:00:00Z",
+                serde_json::json!(false)
+            ),
+            rule(
+                "IR-XX-0001",
+                INVALIDATION,
+                "XX",
+                "1.0.0",
+                GENERAL,
+                "2021-01-01T00:00:00Z",
+                serde_json::json!({"!": [{"in": [{"var": "payload.v.0.ci"}, ["revoked"]]}]})
+            ),
+        ])
+        .to_string()
+    }
+
+    #[test]
+    fn test_select_rules() {
+        let rules = RuleSet::from_json(&rule_set_json()).unwrap();
+        let mut selection = RuleSelection {
+            arrival_country: "AT".to_string(),
+            region: None,
+            issuer_country: Some("XX".to_string()),
+            certificate_type: CertificateType::Vaccination,
+            time: parse_date("2021-04-01").unwrap(),
+        };
+        let selected: Vec<_> = rules
+            .select(&selection)
+            .iter()
+            .map(|rule| (rule.identifier.as_str(), rule.version.as_str()))
+            .collect();
+        assert_eq!(
+            selected,
+            vec![
+                ("GR-AT-0001", "1.0.0"),
+                ("IR-XX-0001", "1.0.0"),
+                ("VR-AT-0001", "1.0.9")
+            ]
+        );
+
+        // the newer version becomes valid
+        selection.time = parse_date("2021-06-01").unwrap();
+        assert_eq!(rules.select(&selection)[2].version, "1.0.10");
+
+        selection.arrival_country = "FR".to_string();
+        selection.issuer_country = None;
+        assert!(rules.select(&selection).is_empty());
+    }
+
+    #[test]
+    fn test_signed_rules() {
+        let signer = SigningKey::es256(&[3u8; 32]).unwrap();
+        let json = rule_set_json();
+        let signature = RuleSet::sign_json(json.as_bytes(), &signer).unwrap();
+        let key = signer.verification_key().unwrap();
+        let rules = RuleSet::from_signed_json(json.as_bytes(), &signature, &key).unwrap();
+        assert_eq!(rules.rules.len(), 7);
+
+        let tampered = json.replace("\"1.0.9\"", "\"1.0.99\"");
+        assert!(RuleSet::from_signed_json(tampered.as_bytes(), &signature, &key).is_err());
+
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let hcert = cwt.hcert().unwrap();
+        let mut external = ExternalParameters::from_cwt(&cwt, 1_617_235_200, "AT").unwrap();
+        external.issuer_country_code = "XX".to_string();
+        let results = rules.evaluate(&hcert, &external).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.outcome == Outcome::Passed));
+        assert_eq!(results[2].version, "1.0.9");
+    }
+}
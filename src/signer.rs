@@ -0,0 +1,177 @@
+//! Abstraction over everything which can produce COSE signatures.
+//!
+//! [CwtParsed::sign](crate::CwtParsed::sign) only hands the `Sig_structure` ([RFC-8152 Section-4.4](https://tools.ietf.org/html/rfc8152#section-4.4))
+//! to a [Signer], so the private key does not need to be known to the library. Implement the trait for handles to
+//! a PKCS#11 token, a signing daemon or similar. [SigningKey] implements it for keys held in memory.
+use crate::{SigningKey, VerificationKey};
+
+/// COSE algorithm identifiers ([RFC-8152 Section-8](https://tools.ietf.org/html/rfc8152#section-8))
+pub mod algorithm {
+    pub const ES256: i128 = -7;
+    pub const ES384: i128 = -35;
+    pub const ES512: i128 = -36;
+    pub const EDDSA: i128 = -8;
+    pub const PS256: i128 = -37;
+    /// `RSASSA-PKCS1-v1_5` with SHA-256 ([RFC-8812 Section-2](https://tools.ietf.org/html/rfc8812#section-2))
+    pub const RS256: i128 = -257;
+}
+
+/// Something which signs COSE `Sig_structure`s without exposing its key material
+pub trait Signer {
+    /// The COSE algorithm identifier of the produced signatures, c.f. [algorithm]
+    fn algorithm(&self) -> i128;
+
+    /// The public key belonging to the signer
+    fn verification_key(&self) -> Result<VerificationKey, Box<dyn std::error::Error>>;
+
+    /// The `kid` of the signer. Defaults to the `kid` of the public key (c.f. [VerificationKey::kid]);
+    /// override it if the `kid` of a signer certificate should be used.
+    fn kid(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.verification_key()?.kid()
+    }
+
+    /// Sign the CBOR encoded `Sig_structure` and return the raw signature (`r || s` for `ECDSA`)
+    fn sign(&self, sig_structure: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+}
+
+impl Signer for SigningKey {
+    fn algorithm(&self) -> i128 {
+        match self {
+            SigningKey::Es256(_) => algorithm::ES256,
+            SigningKey::Es384(_) => algorithm::ES384,
+            SigningKey::Es512(_) => algorithm::ES512,
+            SigningKey::EdDsa(_) => algorithm::EDDSA,
+            SigningKey::Rsa(key) => key.scheme().algorithm(),
+        }
+    }
+
+    fn verification_key(&self) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+        SigningKey::verification_key(self)
+    }
+
+    fn sign(&self, sig_structure: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(match self {
+            SigningKey::Es256(key) => {
+                use p256::ecdsa::signature::Signer;
+                let signature: p256::ecdsa::Signature = key.sign(sig_structure);
+                signature.to_vec()
+            }
+            SigningKey::Es384(key) => {
+                use p384::ecdsa::signature::Signer;
+                let signature: p384::ecdsa::Signature = key.sign(sig_structure);
+                signature.to_vec()
+            }
+            SigningKey::Es512(key) => {
+                use p521::ecdsa::signature::Signer;
+                let signature: p521::ecdsa::Signature = key.sign(sig_structure);
+                signature.to_vec()
+            }
+            SigningKey::EdDsa(key) => key.key_pair().sign(sig_structure).as_ref().to_vec(),
+            SigningKey::Rsa(key) => key.sign(sig_structure)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{RFC_TEST, RSA_PRIVATE_KEY};
+    use crate::get_payload;
+    use serde_cbor::Value;
+
+    /// A signer which only hands out signatures, as a handle to an external device would
+    struct ExternalSigner<'a> {
+        key: &'a SigningKey,
+    }
+
+    impl Signer for ExternalSigner<'_> {
+        fn algorithm(&self) -> i128 {
+            self.key.algorithm()
+        }
+
+        fn verification_key(&self) -> Result<VerificationKey, Box<dyn std::error::Error>> {
+            self.key.verification_key()
+        }
+
+        fn kid(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            Ok(b"external".to_vec())
+        }
+
+        fn sign(&self, sig_structure: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            Signer::sign(self.key, sig_structure)
+        }
+    }
+
+    #[test]
+    fn test_external_signer() {
+        let key = SigningKey::es512(&[1u8; 66]).unwrap();
+        let signer = ExternalSigner { key: &key };
+        let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+        cwt.sign_with_kid(&signer as &dyn Signer).unwrap();
+        assert_eq!(cwt.key_id(), Some(b"external".to_vec()));
+
+        // the RFC test vector claims ES256, signing sets the algorithm of the signer
+        let mut cwt = get_payload(&cwt.to_cbor().unwrap()).unwrap();
+        assert_eq!(
+            crate::algorithm(&cwt.protected_headers),
+            Some(algorithm::ES512)
+        );
+        let public = key.verification_key().unwrap();
+        assert!(cwt.verify(&public).is_ok());
+
+        cwt.protected_headers.insert(
+            Value::Integer(crate::headers::label::ALG),
+            Value::Integer(algorithm::ES256),
+        );
+        let message = cwt.get_signing_bytes().unwrap();
+        let signature = Signer::sign(&key, &message).unwrap();
+        assert!(public
+            .verify_signature_with_algorithm(Some(algorithm::ES256), &message, &signature)
+            .is_err());
+        assert!(public
+            .verify_signature_with_algorithm(Some(algorithm::ES512), &message, &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rsa_sign_and_verify() {
+        let der = base64::decode(RSA_PRIVATE_KEY).unwrap();
+        for (alg, other) in [
+            (algorithm::PS256, algorithm::RS256),
+            (algorithm::RS256, algorithm::PS256),
+        ] {
+            let key = SigningKey::from_pkcs1_der(&der)
+                .unwrap()
+                .with_algorithm(alg)
+                .unwrap();
+            assert_eq!(key.algorithm(), alg);
+            let public = key.verification_key().unwrap();
+            let signature = Signer::sign(&key, b"Sig_structure").unwrap();
+            assert!(public
+                .verify_signature_with_algorithm(Some(alg), b"Sig_structure", &signature)
+                .is_ok());
+            assert!(public
+                .verify_signature_with_algorithm(Some(other), b"Sig_structure", &signature)
+                .is_err());
+
+            let mut cwt = get_payload(&crate::decode_hex(RFC_TEST).unwrap()).unwrap();
+            cwt.sign_with_kid(&key).unwrap();
+            let cwt = get_payload(&cwt.to_cbor().unwrap()).unwrap();
+            assert_eq!(crate::algorithm(&cwt.protected_headers), Some(alg));
+            assert!(cwt.verify(&public).is_ok());
+        }
+        assert!(SigningKey::es256(&[1u8; 32])
+            .unwrap()
+            .with_algorithm(algorithm::PS256)
+            .is_err());
+    }
+
+    #[test]
+    fn test_debug_hides_private_key() {
+        let key = SigningKey::es256(&[0x42u8; 32]).unwrap();
+        let debug = format!("{:?}", key);
+        assert!(!debug.contains("66, 66"));
+        assert!(!debug.to_lowercase().contains("4242"));
+        assert!(debug.starts_with("Es256"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Unique vaccination certificate/assertion identifiers (UVCI) as used in the `ci` field, c.f. Annex 2 of the
+//! [eHealth Network guidelines](https://ec.europa.eu/health/sites/default/files/ehealth/docs/vaccination-proof_interoperability-guidelines_en.pdf).
+//!
+//! ```text
+//! URN:UVCI:01:NL:187/37512422923#K
+//! ^^^^^^^^^ optional prefix
+//!          ^^ version
+//!             ^^ issuing country
+//!                ^^^^^^^^^^^^^^^ schema option blocks separated by `/`
+//!                               ^^ optional checksum
+//! ```
+//!
+//! The checksum is calculated with the Luhn mod N algorithm over the identifier including the `URN:UVCI:`
+//! prefix, using the characters `0-9`, `A-Z`, `/` and `:`.
+use std::fmt;
+
+/// The optional prefix of a UVCI
+pub const PREFIX: &str = "URN:UVCI:";
+/// Characters of the Luhn mod N checksum
+const CHECKSUM_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ/:";
+/// Maximum length according to the DCC schema
+pub const MAX_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A parsed UVCI. The blocks keep their case, c.f. [Uvci::validate].
+pub struct Uvci {
+    /// Whether the identifier started with `URN:UVCI:`
+    pub prefix: bool,
+    pub version: String,
+    pub country: String,
+    /// The schema option blocks, e.g. issuing entity and opaque unique string
+    pub blocks: Vec<String>,
+    pub checksum: Option<char>,
+}
+
+/// The Luhn mod N checksum character of `text`, `None` if `text` contains characters outside the alphabet
+pub fn luhn_mod_n(text: &str) -> Option<char> {
+    let n = CHECKSUM_ALPHABET.len() as u32;
+    let mut factor = 2;
+    let mut sum = 0;
+    for c in text.chars().rev() {
+        let addend = CHECKSUM_ALPHABET.find(c)? as u32 * factor;
+        factor = if factor == 2 { 1 } else { 2 };
+        sum += addend / n + addend % n;
+    }
+    CHECKSUM_ALPHABET.chars().nth(((n - sum % n) % n) as usize)
+}
+
+impl Uvci {
+    /// Split `text` into its parts. Fails if version, country or blocks are missing or contain invalid
+    /// characters, but not on a wrong checksum or lower case letters.
+    pub fn parse(text: &str) -> Result<Uvci, Box<dyn std::error::Error>> {
+        if text.len() > MAX_LENGTH {
+            return Err(format!("UVCI longer than {} characters", MAX_LENGTH).into());
+        }
+        let (prefix, rest) = match text.get(..PREFIX.len()) {
+            Some(start) if start.eq_ignore_ascii_case(PREFIX) => (true, &text[PREFIX.len()..]),
+            _ => (false, text),
+        };
+        let (rest, checksum) = match rest.split_once('#') {
+            Some((rest, checksum)) => {
+                let mut chars = checksum.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => (rest, Some(c)),
+                    _ => return Err("The checksum must be a single character".into()),
+                }
+            }
+            None => (rest, None),
+        };
+        let mut parts = rest.splitn(3, ':');
+        let (version, country, blocks) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(version), Some(country), Some(blocks)) => (version, country, blocks),
+            _ => return Err("Expected version:country:blocks".into()),
+        };
+        if version.len() != 2 || !version.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid UVCI version {}", version).into());
+        }
+        if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(format!("Invalid UVCI country {}", country).into());
+        }
+        let blocks: Vec<String> = blocks.split('/').map(str::to_string).collect();
+        if blocks.iter().any(String::is_empty) {
+            return Err("Empty UVCI block".into());
+        }
+        if let Some(block) = blocks
+            .iter()
+            .find(|block| !block.bytes().all(|b| b.is_ascii_alphanumeric()))
+        {
+            return Err(format!("Invalid characters in UVCI block {}", block).into());
+        }
+        Ok(Uvci {
+            prefix,
+            version: version.to_string(),
+            country: country.to_string(),
+            blocks,
+            checksum,
+        })
+    }
+
+    /// The identifier with `URN:UVCI:` prefix and without checksum, as used for the checksum
+    pub fn canonical(&self) -> String {
+        format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            self.version,
+            self.country,
+            self.blocks.join("/")
+        )
+    }
+
+    /// Whether the checksum matches, `None` if there is no checksum. Lower case letters are checked as their
+    /// upper case counterparts.
+    pub fn checksum_valid(&self) -> Option<bool> {
+        self.checksum.map(|checksum| {
+            luhn_mod_n(&self.canonical().to_ascii_uppercase())
+                == Some(checksum.to_ascii_uppercase())
+        })
+    }
+
+    /// Whether the blocks contain lower case letters. The guidelines only allow upper case letters, but lower
+    /// case UVCIs are issued in practice, so this is no error.
+    pub fn has_lower_case(&self) -> bool {
+        self.blocks
+            .iter()
+            .any(|block| block.bytes().any(|b| b.is_ascii_lowercase()))
+    }
+
+    /// Check the rule [Uvci::parse] is lenient about: the checksum has to match if present
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.checksum_valid() == Some(false) {
+            return Err("Invalid UVCI checksum".into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Uvci {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let canonical = self.canonical();
+        let text = if self.prefix {
+            &canonical[..]
+        } else {
+            &canonical[PREFIX.len()..]
+        };
+        match self.checksum {
+            Some(checksum) => write!(f, "{}#{}", text, checksum),
+            None => write!(f, "{}", text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uvci() {
+        let uvci = Uvci::parse("URN:UVCI:01:NL:187/37512422923").unwrap();
+        assert!(uvci.prefix);
+        assert_eq!(uvci.country, "NL");
+        assert_eq!(uvci.blocks, vec!["187", "37512422923"]);
+        assert_eq!(uvci.checksum_valid(), None);
+        assert!(uvci.validate().is_ok());
+
+        let uvci = Uvci::parse("01:CH:29E4EF9B55A24AF98C528D8EB228D69A").unwrap();
+        assert!(!uvci.prefix);
+        assert_eq!(uvci.to_string(), "01:CH:29E4EF9B55A24AF98C528D8EB228D69A");
+
+        // the NL sample of the SE test certificate
+        let uvci = Uvci::parse("urn:uvci:01:NL:PlA8UWS60Z4RZXVALl6GAZ").unwrap();
+        assert!(uvci.has_lower_case());
+        assert!(uvci.validate().is_ok());
+
+        for malformed in &[
+            "URN:UVCI:1:NL:187",
+            "URN:UVCI:01:nl:187",
+            "URN:UVCI:01:NL",
+            "URN:UVCI:01:NL:187//1",
+            "URN:UVCI:01:NL:18-7",
+            "URN:UVCI:01:NL:187#AB",
+        ] {
+            assert!(Uvci::parse(malformed).is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn test_checksum() {
+        let checksum = luhn_mod_n("URN:UVCI:01:NL:187/37512422923").unwrap();
+        assert_eq!(checksum, 'K');
+        let text = format!("01:NL:187/37512422923#{}", checksum);
+        let uvci = Uvci::parse(&text).unwrap();
+        assert_eq!(uvci.checksum_valid(), Some(true));
+        assert_eq!(uvci.to_string(), text);
+
+        let uvci = Uvci::parse("01:NL:187/37512422924#K").unwrap();
+        assert_eq!(uvci.checksum_valid(), Some(false));
+        assert!(uvci.validate().is_err());
+        // lower case letters are checked as upper case ones
+        let checksum = luhn_mod_n("URN:UVCI:01:NL:ABC/123").unwrap();
+        let uvci = Uvci::parse(&format!("01:NL:abc/123#{}", checksum)).unwrap();
+        assert!(uvci.has_lower_case());
+        assert_eq!(uvci.checksum_valid(), Some(true));
+        assert!(uvci.validate().is_ok());
+        assert_eq!(luhn_mod_n("a"), None);
+    }
+}
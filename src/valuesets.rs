@@ -0,0 +1,214 @@
+//! EU value sets resolving the coded fields of the `hcert` to display names (c.f. the
+//! [value sets of the DCC schema](https://github.com/ehn-dcc-development/ehn-dcc-valuesets)).
+//!
+//! Each value set is a JSON file like
+//!
+//! ```json
+//! {
+//!   "valueSetId": "disease-agent-targeted",
+//!   "valueSetDate": "2021-04-27",
+//!   "valueSetValues": {
+//!     "840539006": { "display": "COVID-19", "lang": "en", "active": true, "version": "...", "system": "..." }
+//!   }
+//! }
+//! ```
+//!
+//! Codes missing in the value set are [CodeStatus::Unknown], codes no longer active are [CodeStatus::Outdated].
+use std::collections::BTreeMap;
+
+use crate::hcert::Hcert;
+
+/// Ids of the value sets used by the `hcert`
+pub mod id {
+    /// `tg` of all entries
+    pub const DISEASE_AGENT_TARGETED: &str = "disease-agent-targeted";
+    /// `v.vp`
+    pub const VACCINE_PROPHYLAXIS: &str = "sct-vaccines-covid-19";
+    /// `v.mp`
+    pub const VACCINE_MEDICINAL_PRODUCT: &str = "vaccines-covid-19-names";
+    /// `v.ma`
+    pub const VACCINE_MANUFACTURER: &str = "vaccines-covid-19-auth-holders";
+    /// `t.tt`
+    pub const TEST_TYPE: &str = "covid-19-lab-test-type";
+    /// `t.tr`
+    pub const TEST_RESULT: &str = "covid-19-lab-result";
+    /// `t.ma`
+    pub const TEST_MANUFACTURER: &str = "covid-19-lab-test-manufacturer-and-name";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ValueSetEntry {
+    pub display: String,
+    pub lang: String,
+    pub active: bool,
+    pub version: String,
+    pub system: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueSet {
+    pub value_set_id: String,
+    pub value_set_date: String,
+    /// Entries by code
+    pub value_set_values: BTreeMap<String, ValueSetEntry>,
+}
+
+impl ValueSet {
+    pub fn from_json(json: &str) -> Result<ValueSet, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeStatus {
+    Active,
+    /// The code is in the value set, but no longer active
+    Outdated,
+    /// The code is not in the value set, or the value set is not loaded
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A coded field of the `hcert` together with its display name
+pub struct ResolvedCode {
+    /// The path of the field, e.g. `v.0.mp`
+    pub field: String,
+    pub value_set_id: String,
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    pub status: CodeStatus,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Loaded value sets by id
+pub struct ValueSets {
+    pub sets: BTreeMap<String, ValueSet>,
+}
+
+impl ValueSets {
+    /// Add (or replace) a value set given as JSON
+    pub fn add_json(&mut self, json: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.add(ValueSet::from_json(json)?);
+        Ok(())
+    }
+
+    pub fn add(&mut self, value_set: ValueSet) {
+        self.sets.insert(value_set.value_set_id.clone(), value_set);
+    }
+
+    pub fn get(&self, value_set_id: &str, code: &str) -> Option<&ValueSetEntry> {
+        self.sets.get(value_set_id)?.value_set_values.get(code)
+    }
+
+    /// Resolve `code` of the field at `field`
+    pub fn resolve(&self, field: &str, value_set_id: &str, code: &str) -> ResolvedCode {
+        let entry = self.get(value_set_id, code);
+        ResolvedCode {
+            field: field.to_string(),
+            value_set_id: value_set_id.to_string(),
+            code: code.to_string(),
+            display: entry.map(|e| e.display.clone()),
+            status: match entry {
+                Some(e) if e.active => CodeStatus::Active,
+                Some(_) => CodeStatus::Outdated,
+                None => CodeStatus::Unknown,
+            },
+        }
+    }
+
+    /// All codes by value set id, as expected in the CertLogic external parameters
+    /// (c.f. [crate::certlogic::ExternalParameters::value_sets])
+    pub fn codes(&self) -> BTreeMap<String, Vec<String>> {
+        self.sets
+            .iter()
+            .map(|(id, set)| (id.clone(), set.value_set_values.keys().cloned().collect()))
+            .collect()
+    }
+}
+
+impl Hcert {
+    /// Resolve all coded fields. The optional test manufacturer is only resolved if present.
+    pub fn resolve_codes(&self, value_sets: &ValueSets) -> Vec<ResolvedCode> {
+        let mut resolved = vec![];
+        let mut resolve = |field: String, value_set_id: &str, code: &str| {
+            resolved.push(value_sets.resolve(&field, value_set_id, code));
+        };
+        for (i, v) in self.v.iter().flatten().enumerate() {
+            resolve(format!("v.{}.tg", i), id::DISEASE_AGENT_TARGETED, &v.tg);
+            resolve(format!("v.{}.vp", i), id::VACCINE_PROPHYLAXIS, &v.vp);
+            resolve(format!("v.{}.mp", i), id::VACCINE_MEDICINAL_PRODUCT, &v.mp);
+            resolve(format!("v.{}.ma", i), id::VACCINE_MANUFACTURER, &v.ma);
+        }
+        for (i, t) in self.t.iter().flatten().enumerate() {
+            resolve(format!("t.{}.tg", i), id::DISEASE_AGENT_TARGETED, &t.tg);
+            resolve(format!("t.{}.tt", i), id::TEST_TYPE, &t.tt);
+            if let Some(ma) = &t.ma {
+                resolve(format!("t.{}.ma", i), id::TEST_MANUFACTURER, ma);
+            }
+            resolve(format!("t.{}.tr", i), id::TEST_RESULT, &t.tr);
+        }
+        for (i, r) in self.r.iter().flatten().enumerate() {
+            resolve(format!("r.{}.tg", i), id::DISEASE_AGENT_TARGETED, &r.tg);
+        }
+        resolved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::SE_HCERT;
+    use crate::get_payload;
+
+    const DISEASE_AGENT: &str = r#"{
+        "valueSetId": "disease-agent-targeted",
+        "valueSetDate": "2021-04-27",
+        "valueSetValues": {
+            "840539006": {
+                "display": "COVID-19",
+                "lang": "en",
+                "active": true,
+                "version": "http://snomed.info/sct/900000000000207008/version/20210131",
+                "system": "http://snomed.info/sct"
+            }
+        }
+    }"#;
+
+    const MEDICINAL_PRODUCT: &str = r#"{
+        "valueSetId": "vaccines-covid-19-names",
+        "valueSetDate": "2021-04-27",
+        "valueSetValues": {
+            "EU/1/20/1528": {
+                "display": "Comirnaty",
+                "lang": "en",
+                "active": false,
+                "version": "",
+                "system": "https://ec.europa.eu/health/documents/community-register/html/"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_resolve_codes() {
+        let mut value_sets = ValueSets::default();
+        value_sets.add_json(DISEASE_AGENT).unwrap();
+        value_sets.add_json(MEDICINAL_PRODUCT).unwrap();
+        let cwt = get_payload(&crate::decode_hex(SE_HCERT).unwrap()).unwrap();
+        let resolved = cwt.hcert().unwrap().resolve_codes(&value_sets);
+        assert_eq!(resolved.len(), 4);
+        assert_eq!(resolved[0].field, "v.0.tg");
+        assert_eq!(resolved[0].display.as_deref(), Some("COVID-19"));
+        assert_eq!(resolved[0].status, CodeStatus::Active);
+        assert_eq!(resolved[1].code, "1119349007");
+        assert_eq!(resolved[1].status, CodeStatus::Unknown);
+        assert_eq!(resolved[2].display.as_deref(), Some("Comirnaty"));
+        assert_eq!(resolved[2].status, CodeStatus::Outdated);
+
+        let codes = value_sets.codes();
+        assert_eq!(codes[id::DISEASE_AGENT_TARGETED], vec!["840539006"]);
+        assert!(ValueSet::from_json("{}").is_err());
+    }
+}
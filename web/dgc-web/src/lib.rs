@@ -1,7 +1,9 @@
-use std::io::Cursor;
-
-use rust_dgc::{base45, from_byte_string, get_meta, get_payload, to_byte_string, VerificationKey};
-use std::io::Read;
+use rust_dgc::jwk::{Jwk, JwkSet};
+use rust_dgc::light::{self, LightCertOptions};
+use rust_dgc::report::{self, VerificationContext};
+use rust_dgc::uvci::Uvci;
+use rust_dgc::valuesets::{ValueSet, ValueSets};
+use rust_dgc::{get_meta, get_payload, VerificationKey};
 use wasm_bindgen::prelude::*;
 
 const DEV_KEY : [&str;2] = ["AOLmTuP+7Z3md1w+TgIk8qADTqIUGQvg82eGAtAKC5xDvmdz3E4mpQrkSktcx37ozTyNBhhtPQ0VVV3b/rXCjVxQ7f50VNc5VgxhX+P+t5eUSI5FhQ9yRSqkfCJXCY62GMbLbmbNzGst0hkCfpGWnh+RhWTEbxNMGh6jMW38GpL43/KsgVwq2dVrCvlyX+4mGyUtnTtWuR53oMT7kQO2c/IpDu0Ec5kqJ4KjpZHoxGiJBY8e4Cxk1LDqwT2GubHWaopw8Jp47Soudhy1mqzF7PrdTDeHrSKexhO/82q4wTcZNRH4osJfkXXMCdrlcH64M8X79/03pGRfCFMpFdhnrt0=", "AQAB"];
@@ -24,6 +26,18 @@ const AA_KEY: [&str;2] = [
     "AQAB"
 ];
 
+/// The `COSE` bytes of the content of a QR code (starting with `HC1:`) or of hex encoded CBOR. Content which cannot
+/// be decoded yields no bytes, so parsing it fails.
+fn cose_bytes(cbor_cwt: &str) -> Vec<u8> {
+    if cbor_cwt.starts_with(report::HC1_PREFIX) {
+        report::decode_qr_content(cbor_cwt, report::HC1_PREFIX)
+            .map(|(cose, _)| cose)
+            .unwrap_or_default()
+    } else {
+        rust_dgc::decode_hex(&cbor_cwt.replace(" ", "").replace("\n", "")).unwrap_or_default()
+    }
+}
+
 #[wasm_bindgen]
 pub fn get_qr_code_data(image: String) -> String {
     if let Ok(data) = base64::decode(&image) {
@@ -34,22 +48,11 @@ pub fn get_qr_code_data(image: String) -> String {
 
 #[wasm_bindgen]
 pub fn parse_cwt_from_bytestring(cbor_cwt: String) -> String {
-    let cbor_cwt = if cbor_cwt.starts_with("HC1:") {
-        let mut decoded =
-            Cursor::new(base45::decode(&cbor_cwt.replace("HC1:", "")).unwrap_or(vec![]));
-        let mut decompressor = flate2::read::ZlibDecoder::new(&mut decoded);
-        let mut decompressed = vec![];
-        match decompressor.read_to_end(&mut decompressed) {
-            Ok(_) => to_byte_string!(decompressed),
-            Err(r) => "".to_string(),
-        }
-    } else {
-        cbor_cwt.replace(" ", "").replace("\n", "")
-    };
-
-    let cbor_bytes = from_byte_string!(cbor_cwt);
+    let cbor_bytes = cose_bytes(&cbor_cwt);
     if let Ok(cwt) = get_payload(&cbor_bytes) {
         return serde_json::to_string_pretty(&cwt).unwrap_or("{}".to_string());
+    } else if let Ok(cose_sign) = rust_dgc::cose_sign::get_cose_sign(&cbor_bytes) {
+        return serde_json::to_string_pretty(&cose_sign).unwrap_or("{}".to_string());
     } else {
         return "{}".to_string();
     }
@@ -57,20 +60,7 @@ pub fn parse_cwt_from_bytestring(cbor_cwt: String) -> String {
 
 #[wasm_bindgen]
 pub fn get_cwt_info(cbor_cwt: String) -> String {
-    let cbor_cwt = if cbor_cwt.starts_with("HC1:") {
-        let mut decoded =
-            Cursor::new(base45::decode(&cbor_cwt.replace("HC1:", "")).unwrap_or(vec![]));
-        let mut decompressor = flate2::read::ZlibDecoder::new(&mut decoded);
-        let mut decompressed = vec![];
-        match decompressor.read_to_end(&mut decompressed) {
-            Ok(_) => to_byte_string!(decompressed),
-            Err(r) => "".to_string(),
-        }
-    } else {
-        cbor_cwt.replace(" ", "").replace("\n", "")
-    };
-
-    let cbor_bytes = from_byte_string!(cbor_cwt);
+    let cbor_bytes = cose_bytes(&cbor_cwt);
     if let Ok(cwt) = get_meta(&cbor_bytes) {
         return serde_json::to_string_pretty(&cwt).unwrap_or("{}".to_string());
     } else {
@@ -80,19 +70,7 @@ pub fn get_cwt_info(cbor_cwt: String) -> String {
 
 #[wasm_bindgen]
 pub fn get_hcert_from_cwt(cbor_cwt: String) -> String {
-    let cbor_cwt = if cbor_cwt.starts_with("HC1:") {
-        let mut decoded =
-            Cursor::new(base45::decode(&cbor_cwt.replace("HC1:", "")).unwrap_or(vec![]));
-        let mut decompressor = flate2::read::ZlibDecoder::new(&mut decoded);
-        let mut decompressed = vec![];
-        match decompressor.read_to_end(&mut decompressed) {
-            Ok(_) => to_byte_string!(decompressed),
-            Err(r) => "".to_string(),
-        }
-    } else {
-        cbor_cwt.replace(" ", "").replace("\n", "")
-    };
-    let cbor_bytes = from_byte_string!(cbor_cwt);
+    let cbor_bytes = cose_bytes(&cbor_cwt);
     if let Ok(cwt) = get_payload(&cbor_bytes) {
         return serde_json::to_string_pretty(&cwt.get_hcert()).unwrap_or("{}".to_string());
     } else {
@@ -102,19 +80,7 @@ pub fn get_hcert_from_cwt(cbor_cwt: String) -> String {
 
 #[wasm_bindgen]
 pub fn verify_cwt_ec(cbor_cwt: String, x: String, y: String, encoding: String) -> bool {
-    let cbor_cwt = if cbor_cwt.starts_with("HC1:") {
-        let mut decoded =
-            Cursor::new(base45::decode(&cbor_cwt.replace("HC1:", "")).unwrap_or(vec![]));
-        let mut decompressor = flate2::read::ZlibDecoder::new(&mut decoded);
-        let mut decompressed = vec![];
-        match decompressor.read_to_end(&mut decompressed) {
-            Ok(_) => to_byte_string!(decompressed),
-            Err(r) => "".to_string(),
-        }
-    } else {
-        cbor_cwt.replace(" ", "").replace("\n", "")
-    };
-    let cbor_bytes = from_byte_string!(cbor_cwt);
+    let cbor_bytes = cose_bytes(&cbor_cwt);
 
     let cwt = if let Ok(cwt) = get_payload(&cbor_bytes) {
         cwt
@@ -122,25 +88,52 @@ pub fn verify_cwt_ec(cbor_cwt: String, x: String, y: String, encoding: String) -
         return false;
     };
 
-    let x = if encoding.to_lowercase() == "base64" {
-        to_byte_string!(base64::decode_config(x, base64::STANDARD_NO_PAD).unwrap_or(vec![]))
-    } else {
-        x
+    let decode = |coordinate: &str| {
+        if encoding.to_lowercase() == "base64" {
+            base64::decode_config(coordinate, base64::STANDARD_NO_PAD).map_err(|e| e.into())
+        } else {
+            rust_dgc::decode_hex(coordinate)
+        }
     };
-    let y = if encoding.to_lowercase() == "base64" {
-        to_byte_string!(base64::decode_config(y, base64::STANDARD_NO_PAD).unwrap_or(vec![]))
-    } else {
-        y
+    let key = match (decode(&x), decode(&y)) {
+        (Ok(x), Ok(y)) => match VerificationKey::es256(&x, &y) {
+            Ok(key) => key,
+            Err(_) => return false,
+        },
+        _ => return false,
     };
-    println!("{}", x);
-    println!("{}", y);
-    let key = VerificationKey::Es256 { x, y };
     match cwt.verify(&key) {
         Ok(_) => true,
         _ => false,
     }
 }
 
+/// Verify the CWT with a JWK or a JWK Set. For a set, the key matching the `kid` of the CWT is used if present.
+#[wasm_bindgen]
+pub fn verify_cwt_jwk(cbor_cwt: String, jwk: String) -> bool {
+    let cbor_bytes = cose_bytes(&cbor_cwt);
+
+    let cwt = if let Ok(cwt) = get_payload(&cbor_bytes) {
+        cwt
+    } else {
+        return false;
+    };
+
+    let keys = if let Ok(set) = JwkSet::from_json(&jwk) {
+        match cwt.key_id().and_then(|kid| set.find(&kid)) {
+            Some(key) => vec![key.clone()],
+            None => set.keys,
+        }
+    } else if let Ok(jwk) = Jwk::from_json(&jwk) {
+        vec![jwk]
+    } else {
+        return false;
+    };
+    keys.iter()
+        .filter_map(|jwk| VerificationKey::from_jwk(jwk).ok())
+        .any(|key| cwt.verify(&key).is_ok())
+}
+
 #[wasm_bindgen]
 pub fn verify_cwt_rsa_with_environment(cbor_cwt: String, env: String) -> bool {
     let keys = match env.to_lowercase().as_str() {
@@ -170,19 +163,7 @@ pub fn verify_cwt_rsa_with_environment(cbor_cwt: String, env: String) -> bool {
         "aa" => vec![VerificationKey::rsa_from_n_and_e(AA_KEY[0], AA_KEY[1])],
         _ => return false,
     };
-    let cbor_cwt = if cbor_cwt.starts_with("HC1:") {
-        let mut decoded =
-            Cursor::new(base45::decode(&cbor_cwt.replace("HC1:", "")).unwrap_or(vec![]));
-        let mut decompressor = flate2::read::ZlibDecoder::new(&mut decoded);
-        let mut decompressed = vec![];
-        match decompressor.read_to_end(&mut decompressed) {
-            Ok(_) => to_byte_string!(decompressed),
-            Err(r) => "".to_string(),
-        }
-    } else {
-        cbor_cwt.replace(" ", "").replace("\n", "")
-    };
-    let cbor_bytes = from_byte_string!(cbor_cwt);
+    let cbor_bytes = cose_bytes(&cbor_cwt);
 
     let cwt = if let Ok(cwt) = get_payload(&cbor_bytes) {
         cwt
@@ -200,25 +181,107 @@ pub fn verify_cwt_rsa_with_environment(cbor_cwt: String, env: String) -> bool {
 
 #[wasm_bindgen]
 pub fn verify_cwt_rsa(cbor_cwt: String, pem: String) -> bool {
-    let cbor_bytes = from_byte_string!(cbor_cwt);
+    let cbor_bytes = cose_bytes(&cbor_cwt);
     let cwt = if let Ok(cwt) = get_payload(&cbor_bytes) {
         cwt
     } else {
         return false;
     };
-    let mut payload = String::from("");
-    for line in pem.lines() {
-        if line.starts_with("-") {
-            continue;
-        }
-        payload += line;
-    }
-    let bytes = base64::decode(payload).unwrap_or(vec![]);
-    let key = VerificationKey::Rsa {
-        key: to_byte_string!(bytes),
+    // accepts PEM (SPKI, PKCS#1 or certificate) as well as the plain base64 encoded DER
+    let key = match VerificationKey::from_pem(&pem).or_else(|_| {
+        base64::decode(pem.trim())
+            .map_err(|e| e.into())
+            .and_then(|der| VerificationKey::from_der(&der))
+    }) {
+        Ok(key) => key,
+        Err(_) => return false,
     };
     match cwt.verify(&key) {
         Ok(_) => true,
         _ => false,
     }
 }
+
+/// Run all checks and return the [rust_dgc::report::VerificationReport] as JSON. `jwk_set` holds the trusted keys,
+/// `time` is the validation time in seconds since the UNIX epoch.
+#[wasm_bindgen]
+pub fn verify_report(cbor_cwt: String, jwk_set: String, time: f64) -> String {
+    let mut context = VerificationContext::new(time as i64);
+    if let Ok(set) = JwkSet::from_json(&jwk_set) {
+        context.add_jwk_set(&set);
+    }
+    let report = if cbor_cwt.starts_with(report::HC1_PREFIX) {
+        report::verify_qr(&cbor_cwt, &context)
+    } else {
+        match rust_dgc::decode_hex(&cbor_cwt.replace(" ", "").replace("\n", "")) {
+            Ok(cose) => report::verify_cose(&cose, &context),
+            Err(e) => {
+                let mut report = report::VerificationReport::default();
+                report.set(report::Step::Encoding, report::Check::failed(e.to_string()));
+                report
+            }
+        }
+    };
+    report.to_json().unwrap_or_default()
+}
+
+/// Resolve the coded fields of the hcert to display names. `value_sets` is a JSON array of EU value set files,
+/// the result a JSON array of [rust_dgc::valuesets::ResolvedCode].
+#[wasm_bindgen]
+pub fn resolve_hcert_codes(cbor_cwt: String, value_sets: String) -> String {
+    let cbor_bytes = cose_bytes(&cbor_cwt);
+    let hcert = match get_payload(&cbor_bytes).and_then(|cwt| cwt.hcert()) {
+        Ok(hcert) => hcert,
+        Err(_) => return "[]".to_string(),
+    };
+    let mut sets = ValueSets::default();
+    for set in serde_json::from_str::<Vec<ValueSet>>(&value_sets).unwrap_or_default() {
+        sets.add(set);
+    }
+    serde_json::to_string_pretty(&hcert.resolve_codes(&sets)).unwrap_or("[]".to_string())
+}
+
+/// Parse a UVCI (the `ci` field) into its parts. Returns the [rust_dgc::uvci::Uvci] as JSON with an additional
+/// `error` field for malformed identifiers.
+#[wasm_bindgen]
+pub fn parse_uvci(ci: String) -> String {
+    let result = match Uvci::parse(&ci) {
+        Ok(uvci) => {
+            let mut json = serde_json::to_value(&uvci).unwrap_or_default();
+            if let Err(e) = uvci.validate() {
+                json["error"] = serde_json::Value::String(e.to_string());
+            }
+            json
+        }
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    };
+    result.to_string()
+}
+
+/// Decode a light certificate (`LT1:`) without verifying it. Returns the [rust_dgc::light::LightCert] as JSON or
+/// an object with an `error` field.
+#[wasm_bindgen]
+pub fn get_light_cert(content: String) -> String {
+    let result = light::decode_qr_content(&content, light::LT1_PREFIX)
+        .and_then(|cwt| cwt.light_cert())
+        .and_then(|light_cert| Ok(serde_json::to_value(&light_cert)?));
+    match result {
+        Ok(json) => json.to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    }
+}
+
+/// Verify a light certificate (`LT1:`) with the light certificate keys in `jwk_set` at `time` (seconds since the
+/// UNIX epoch). Returns the [rust_dgc::light::VerifiedLightCert] as JSON or an object with an `error` field.
+#[wasm_bindgen]
+pub fn verify_light_cert(content: String, jwk_set: String, time: f64) -> String {
+    let result = JwkSet::from_json(&jwk_set)
+        .and_then(|keys| {
+            light::verify(&content, &keys, time as i64, &LightCertOptions::default())
+        })
+        .and_then(|verified| Ok(serde_json::to_value(&verified)?));
+    match result {
+        Ok(json) => json.to_string(),
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    }
+}